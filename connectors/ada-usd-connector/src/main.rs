@@ -6,10 +6,14 @@
 /// of lovelaces per 1 USD, not the rate per lovelace.
 use anyhow::{anyhow, Result};
 use frontend_connector_lib::FrontendConnector;
+use std::time::Duration;
 
 // Number of Lovelaces in a single Ada
 static LOVELACE_CONVERSION: f64 = 1000000.0;
 
+// Timeout of the requests to CoinGecko
+static REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
 static CG_RATE_URL: &str =
     "https://api.coingecko.com/api/v3/simple/price?ids=cardano&vs_currencies=USD";
 
@@ -21,7 +25,11 @@ pub fn generate_current_price(datapoint: u64) -> f64 {
 /// Acquires the price of Ada in USD from CoinGecko, convert it
 /// into Lovelaces per 1 USD, and return it.
 fn get_lovelace_usd_price() -> Result<u64> {
-    let resp = reqwest::blocking::Client::new().get(CG_RATE_URL).send()?;
+    let resp = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .get(CG_RATE_URL)
+        .send()?;
     let price_json = json::parse(&resp.text()?)?;
     if let Some(p) = price_json["cardano"]["usd"].as_f64() {
         let lovelace_price = (1.0 / p) * LOVELACE_CONVERSION;
@@ -0,0 +1,295 @@
+/// Aggregation of datapoints from several sources. An `Aggregator` queries
+/// all of its sources concurrently, requires a quorum of them to respond,
+/// rejects the datapoints which deviate too far from the median, and returns
+/// the median of the remaining datapoints along with per-source diagnostics.
+/// As an `Aggregator` is itself a `DatapointSource`, aggregators can be
+/// nested and used directly as the source of a `Connector`.
+use crate::connector::Datapoint;
+use crate::median::median_of;
+use crate::oracle_core::{PoolInfo, PoolStatus};
+use crate::source::{DatapointSource, Sample};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum AggregationError {
+    #[error(
+        "Only {accepted} of the required {required} sources provided an acceptable datapoint."
+    )]
+    QuorumNotReached { accepted: usize, required: usize },
+}
+
+/// The outcome of querying a single source
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceStatus {
+    /// The datapoint was used for the aggregate
+    Accepted,
+    /// The datapoint deviated from the median by more than the allowed
+    /// deviation (deviation given as a fraction of the median)
+    Outlier { deviation: f64 },
+    /// The source failed to provide a datapoint
+    Failed { error: String },
+}
+
+/// Diagnostics of a single source
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceReport {
+    pub name: String,
+    pub datapoint: Option<Datapoint>,
+    pub status: SourceStatus,
+}
+
+/// The result of an aggregation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    /// The median of the accepted datapoints (`None` if no quorum was reached)
+    pub datapoint: Option<Datapoint>,
+    /// The median of all datapoints which were provided
+    pub median: Option<Datapoint>,
+    pub reports: Vec<SourceReport>,
+}
+
+/// Aggregates the datapoints of several `DatapointSource`s
+pub struct Aggregator {
+    name: String,
+    sources: Vec<Arc<dyn DatapointSource>>,
+    /// Number of sources which must provide an acceptable datapoint
+    quorum: usize,
+    /// Maximum deviation from the median (as a fraction, ie. 0.05 = 5%)
+    max_deviation: f64,
+    last_aggregate: Mutex<Option<Aggregate>>,
+}
+
+impl Aggregator {
+    /// Create a new `Aggregator` requiring `quorum` sources to provide a
+    /// datapoint within `max_deviation` (ie. 0.05 = 5%) of the median
+    pub fn new(name: &str, quorum: usize, max_deviation: f64) -> Aggregator {
+        Aggregator {
+            name: name.to_string(),
+            sources: vec![],
+            quorum: quorum,
+            max_deviation: max_deviation,
+            last_aggregate: Mutex::new(None),
+        }
+    }
+
+    /// Add a source to the `Aggregator`
+    pub fn with_source(mut self, source: impl DatapointSource + 'static) -> Aggregator {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    /// Query every source concurrently and aggregate their datapoints
+    pub fn aggregate(&self) -> Aggregate {
+        self.aggregate_sources(None).0
+    }
+
+    /// Query every source concurrently, requiring their datapoints by the
+    /// `deadline` (if provided), and aggregate their datapoints. Sources
+    /// which did not respond by the `deadline` are reported as failed. Along
+    /// with the `Aggregate`, the total volume of the accepted sources is
+    /// returned if all of them reported one (volumes are only sampled
+    /// without a `deadline`).
+    fn aggregate_sources(&self, deadline: Option<Instant>) -> (Aggregate, Option<f64>) {
+        let (sender, receiver) = channel();
+        for (i, source) in self.sources.iter().enumerate() {
+            let (source, sender) = (source.clone(), sender.clone());
+            thread::spawn(move || {
+                let result = match deadline {
+                    Some(d) => source.get_datapoint_before(d).map(|datapoint| Sample {
+                        datapoint: datapoint,
+                        volume: None,
+                    }),
+                    None => source.get_sample(),
+                };
+                sender.send((i, result.map_err(|e| e.to_string()))).ok();
+            });
+        }
+        drop(sender);
+
+        let mut received: Vec<Option<Result<Sample, String>>> = vec![None; self.sources.len()];
+        for _ in 0..self.sources.len() {
+            let next = match deadline {
+                Some(d) => receiver
+                    .recv_timeout(d.saturating_duration_since(Instant::now()))
+                    .ok(),
+                None => receiver.recv().ok(),
+            };
+            match next {
+                Some((i, result)) => received[i] = Some(result),
+                // The deadline passed, or the remaining sources panicked
+                None => break,
+            }
+        }
+        let missing = match deadline {
+            Some(d) if Instant::now() >= d => "The source did not respond before the deadline.",
+            _ => "The source panicked.",
+        };
+        let samples: Vec<Result<Sample, String>> = received
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(missing.to_string())))
+            .collect();
+        let results = samples
+            .iter()
+            .zip(&self.sources)
+            .map(|(sample, source)| (source.name(), sample.clone().map(|s| s.datapoint)))
+            .collect();
+        let aggregate = aggregate_results(results, self.quorum, self.max_deviation);
+        let volume = aggregate
+            .reports
+            .iter()
+            .zip(&samples)
+            .filter(|(report, _)| report.status == SourceStatus::Accepted)
+            .map(|(_, sample)| sample.as_ref().ok().and_then(|s| s.volume))
+            .sum::<Option<f64>>();
+        *self.last_aggregate.lock().unwrap() = Some(aggregate.clone());
+        (aggregate, volume)
+    }
+
+    /// The result of the latest aggregation (ie. for printing diagnostics)
+    pub fn last_aggregate(&self) -> Option<Aggregate> {
+        self.last_aggregate.lock().unwrap().clone()
+    }
+
+    /// The datapoint of an `Aggregate`, or an error if no quorum was reached
+    fn aggregate_datapoint(&self, aggregate: Aggregate) -> anyhow::Result<Datapoint> {
+        let accepted = aggregate
+            .reports
+            .iter()
+            .filter(|r| r.status == SourceStatus::Accepted)
+            .count();
+        aggregate.datapoint.ok_or(
+            AggregationError::QuorumNotReached {
+                accepted: accepted,
+                required: self.quorum,
+            }
+            .into(),
+        )
+    }
+}
+
+impl DatapointSource for Aggregator {
+    fn get_datapoint(&self) -> anyhow::Result<Datapoint> {
+        self.aggregate_datapoint(self.aggregate_sources(None).0)
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> anyhow::Result<Datapoint> {
+        self.aggregate_datapoint(self.aggregate_sources(Some(deadline)).0)
+    }
+
+    fn get_sample(&self) -> anyhow::Result<Sample> {
+        let (aggregate, volume) = self.aggregate_sources(None);
+        Ok(Sample {
+            datapoint: self.aggregate_datapoint(aggregate)?,
+            volume: volume,
+        })
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn pool_updated(&self, pool_info: &PoolInfo, pool_status: &PoolStatus) {
+        for source in &self.sources {
+            source.pool_updated(pool_info, pool_status);
+        }
+    }
+}
+
+/// Aggregates the `(source name, result)` of every queried source
+pub fn aggregate_results(
+    results: Vec<(String, Result<Datapoint, String>)>,
+    quorum: usize,
+    max_deviation: f64,
+) -> Aggregate {
+    let mut datapoints: Vec<Datapoint> = results
+        .iter()
+        .filter_map(|(_, r)| r.as_ref().ok().copied())
+        .collect();
+    let median = median_of(&mut datapoints);
+    let reports: Vec<SourceReport> = results
+        .into_iter()
+        .map(|(name, result)| {
+            let status = match (&result, median) {
+                (Err(e), _) => SourceStatus::Failed { error: e.clone() },
+                (Ok(d), Some(m)) => {
+                    let deviation = (*d as f64 - m as f64).abs() / (m as f64).max(1.0);
+                    if deviation > max_deviation {
+                        SourceStatus::Outlier {
+                            deviation: deviation,
+                        }
+                    } else {
+                        SourceStatus::Accepted
+                    }
+                }
+                (Ok(_), None) => SourceStatus::Accepted,
+            };
+            SourceReport {
+                name: name,
+                datapoint: result.ok(),
+                status: status,
+            }
+        })
+        .collect();
+    let mut accepted: Vec<Datapoint> = reports
+        .iter()
+        .filter(|r| r.status == SourceStatus::Accepted)
+        .filter_map(|r| r.datapoint)
+        .collect();
+    let datapoint = if accepted.len() >= quorum.max(1) {
+        median_of(&mut accepted)
+    } else {
+        None
+    };
+    Aggregate {
+        datapoint: datapoint,
+        median: median,
+        reports: reports,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(values: &[Option<Datapoint>]) -> Vec<(String, Result<Datapoint, String>)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("Source {}", i), v.ok_or("Offline".to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn outliers_are_rejected() {
+        let aggregate = aggregate_results(
+            results(&[Some(1000), Some(1010), Some(100000), None, Some(990)]),
+            3,
+            0.05,
+        );
+        assert_eq!(aggregate.median, Some(1005));
+        assert_eq!(aggregate.datapoint, Some(1000));
+        assert_eq!(aggregate.reports[0].status, SourceStatus::Accepted);
+        assert!(matches!(
+            aggregate.reports[2].status,
+            SourceStatus::Outlier { .. }
+        ));
+        assert_eq!(
+            aggregate.reports[3].status,
+            SourceStatus::Failed {
+                error: "Offline".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn quorum_is_required() {
+        let aggregate = aggregate_results(results(&[Some(1000), None, Some(5000)]), 2, 0.05);
+        assert_eq!(aggregate.datapoint, None);
+        let aggregate = aggregate_results(results(&[Some(1000), None, Some(1020)]), 2, 0.05);
+        assert_eq!(aggregate.datapoint, Some(1010));
+    }
+}
@@ -0,0 +1,460 @@
+/// Windowed averaging of datapoints. Rather than submitting the spot price at
+/// the moment a datapoint is required (which a short-lived spike can
+/// distort), an `AveragingSource` samples its source continuously in the
+/// background and answers with the time-weighted (TWAP) or volume-weighted
+/// (VWAP) average price over a window. The window is a rolling window ending
+/// when the datapoint is required, lasting either a fixed duration or a number
+/// of pool epochs (converted into a duration at the average block interval).
+/// A window of one epoch thus roughly covers the time since the previous
+/// datapoint, without being aligned to the epoch boundaries.
+use crate::connector::{Datapoint, EXPECTED_BLOCK_INTERVAL};
+use crate::oracle_core::{get_config_yaml_string, ConnectorError, PoolInfo, PoolStatus};
+use crate::source::{DatapointSource, Sample};
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// How long samples are kept while the length of an epoch-aligned window is
+/// not known yet
+static UNRESOLVED_WINDOW_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How the samples of the window are averaged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AveragingMethod {
+    /// Time-weighted average price, every sample is weighted by how long it
+    /// was the latest sample
+    Twap,
+    /// Volume-weighted average price, every sample is weighted by the volume
+    /// reported with it
+    Vwap,
+}
+
+/// The length of the averaging window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AveragingWindow {
+    Fixed(Duration),
+    /// A number of epochs of the pool (Live Epoch & Epoch Preparation)
+    Epochs(f64),
+}
+
+/// How datapoints are sampled & averaged
+#[derive(Debug, Clone, PartialEq)]
+pub struct AveragingSettings {
+    pub method: AveragingMethod,
+    pub window: AveragingWindow,
+    /// Time between two samples
+    pub sample_interval: Duration,
+    /// Number of samples the window must hold for an average to be returned
+    pub min_samples: usize,
+}
+
+impl AveragingSettings {
+    /// Create `AveragingSettings` averaging over a window of one epoch, with
+    /// a sample every 30 seconds
+    pub fn new(method: AveragingMethod) -> AveragingSettings {
+        AveragingSettings {
+            method: method,
+            window: AveragingWindow::Epochs(1.0),
+            sample_interval: Duration::from_secs(30),
+            min_samples: 1,
+        }
+    }
+
+    /// Average over `window` instead
+    pub fn with_window(mut self, window: AveragingWindow) -> AveragingSettings {
+        self.window = window;
+        self
+    }
+
+    /// Sample the source every `sample_interval` instead
+    pub fn with_sample_interval(mut self, sample_interval: Duration) -> AveragingSettings {
+        self.sample_interval = sample_interval;
+        self
+    }
+
+    /// Require `min_samples` samples within the window
+    pub fn with_min_samples(mut self, min_samples: usize) -> AveragingSettings {
+        self.min_samples = min_samples;
+        self
+    }
+
+    /// Create `AveragingSettings` from a `&Yaml` config. Averaging is enabled
+    /// by setting "datapoint_averaging" to "twap" or "vwap" (`None` if it
+    /// isn't set), while the other settings fall back to their defaults.
+    pub fn new_from_yaml(config: &Yaml) -> Result<Option<AveragingSettings>> {
+        let number = |key: &str| {
+            config[key]
+                .as_f64()
+                .or(config[key].as_i64().map(|n| n as f64))
+        };
+        let method = match config["datapoint_averaging"].as_str() {
+            None => return Ok(None),
+            Some("twap") => AveragingMethod::Twap,
+            Some("vwap") => AveragingMethod::Vwap,
+            Some(other) => return Err(anyhow!("Unknown datapoint averaging \"{}\".", other)),
+        };
+        let mut settings = AveragingSettings::new(method);
+        match (
+            number("datapoint_averaging_window"),
+            number("datapoint_averaging_window_epochs"),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "Only one of `datapoint_averaging_window` & `datapoint_averaging_window_epochs` may be set."
+                ))
+            }
+            (Some(seconds), None) if seconds > 0.0 => {
+                settings.window = AveragingWindow::Fixed(Duration::from_secs_f64(seconds))
+            }
+            (None, Some(epochs)) if epochs > 0.0 => {
+                settings.window = AveragingWindow::Epochs(epochs)
+            }
+            (None, None) => (),
+            _ => return Err(anyhow!("The datapoint averaging window must be positive.")),
+        }
+        if let Some(interval) = number("datapoint_averaging_sample_interval") {
+            if !(interval > 0.0) {
+                return Err(anyhow!("The datapoint sample interval must be positive."));
+            }
+            settings.sample_interval = Duration::from_secs_f64(interval);
+        }
+        if let Some(min_samples) = config["datapoint_averaging_min_samples"].as_i64() {
+            settings.min_samples = min_samples.max(1) as usize;
+        }
+        Ok(Some(settings))
+    }
+}
+
+/// Returns the `AveragingSettings` ("datapoint_averaging",
+/// "datapoint_averaging_window" (in seconds) or
+/// "datapoint_averaging_window_epochs", "datapoint_averaging_sample_interval"
+/// & "datapoint_averaging_min_samples") from the local config file, or `None`
+/// if datapoints aren't averaged (or there is no config file)
+pub fn get_averaging_settings() -> Result<Option<AveragingSettings>> {
+    let config_string = match get_config_yaml_string() {
+        Ok(config_string) => config_string,
+        Err(_) => return Ok(None),
+    };
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    AveragingSettings::new_from_yaml(config)
+}
+
+/// The samples of the window, shared with the sampling thread
+#[derive(Debug, Default)]
+struct AveragingState {
+    samples: VecDeque<(Instant, Sample)>,
+    /// The length of the window (`None` until the epoch length of the pool
+    /// is known for epoch-aligned windows)
+    window: Option<Duration>,
+    last_error: Option<String>,
+}
+
+/// A `DatapointSource` which averages the samples of another source over a
+/// window
+#[derive(Clone)]
+pub struct AveragingSource {
+    name: String,
+    settings: AveragingSettings,
+    state: Arc<Mutex<AveragingState>>,
+}
+
+impl AveragingSource {
+    /// Start sampling `source` on a background thread, which exits once the
+    /// `AveragingSource` (and all of its clones) is dropped
+    pub fn start(
+        name: &str,
+        source: impl DatapointSource + 'static,
+        settings: AveragingSettings,
+    ) -> AveragingSource {
+        let window = match settings.window {
+            AveragingWindow::Fixed(window) => Some(window),
+            AveragingWindow::Epochs(_) => None,
+        };
+        let state = Arc::new(Mutex::new(AveragingState {
+            window: window,
+            ..AveragingState::default()
+        }));
+        let weak_state = Arc::downgrade(&state);
+        let sample_interval = settings.sample_interval;
+        thread::Builder::new()
+            .name(format!("{} Sampling Thread", name))
+            .spawn(move || run_sampler(source, sample_interval, weak_state))
+            .ok();
+        AveragingSource {
+            name: name.to_string(),
+            settings: settings,
+            state: state,
+        }
+    }
+
+    /// The settings the source was started with
+    pub fn settings(&self) -> &AveragingSettings {
+        &self.settings
+    }
+
+    /// The length of the window (`None` if the epoch length of the pool isn't
+    /// known yet)
+    pub fn window(&self) -> Option<Duration> {
+        self.state.lock().unwrap().window
+    }
+}
+
+impl DatapointSource for AveragingSource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        let state = self.state.lock().unwrap();
+        let window = state.window.ok_or(anyhow!(
+            "The averaging window isn't known yet, as the epoch length of the pool wasn't received from the core."
+        ))?;
+        let end = Instant::now();
+        let start = window_start(&state.samples, end, window);
+        let samples: Vec<(Instant, Sample)> = state.samples.iter().copied().collect();
+        let in_window = samples.iter().filter(|(t, _)| *t >= start).count();
+        if in_window < self.settings.min_samples {
+            return Err(anyhow!(
+                "Only {} of the required {} samples were taken within the averaging window. Latest error: {}",
+                in_window,
+                self.settings.min_samples,
+                state.last_error.as_deref().unwrap_or("None")
+            ));
+        }
+        match self.settings.method {
+            AveragingMethod::Twap => time_weighted_average(&samples, start, end),
+            AveragingMethod::Vwap => volume_weighted_average(&samples, start),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn pool_updated(&self, pool_info: &PoolInfo, pool_status: &PoolStatus) {
+        if let AveragingWindow::Epochs(epochs) = self.settings.window {
+            let block_interval = pool_status
+                .average_block_interval
+                .unwrap_or(EXPECTED_BLOCK_INTERVAL as f64);
+            let epoch_length = (pool_info.live_epoch_length + pool_info.epoch_prep_length) as f64;
+            self.state.lock().unwrap().window = Some(Duration::from_secs_f64(
+                epochs * epoch_length * block_interval,
+            ));
+        }
+    }
+
+    fn info(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let method = match self.settings.method {
+            AveragingMethod::Twap => "TWAP",
+            AveragingMethod::Vwap => "VWAP",
+        };
+        let length = match state.window {
+            Some(window) => format_duration(window),
+            None => "Unknown".to_string(),
+        };
+        let window = match self.settings.window {
+            AveragingWindow::Fixed(_) => length,
+            AveragingWindow::Epochs(epochs) => format!("{} Epochs ({})", epochs, length),
+        };
+        let samples = match state.window {
+            Some(window) => {
+                let start = window_start(&state.samples, Instant::now(), window);
+                state.samples.iter().filter(|(t, _)| *t >= start).count()
+            }
+            None => 0,
+        };
+        vec![
+            format!("Datapoint Averaging: {}", method),
+            format!("Averaging Window: {}", window),
+            format!(
+                "Sample Interval: {}",
+                format_duration(self.settings.sample_interval)
+            ),
+            format!(
+                "Samples In Window: {} (Minimum {})",
+                samples, self.settings.min_samples
+            ),
+        ]
+    }
+}
+
+/// Samples `source` every `sample_interval` until the `AveragingSource` is
+/// dropped
+fn run_sampler(
+    source: impl DatapointSource,
+    sample_interval: Duration,
+    state: Weak<Mutex<AveragingState>>,
+) {
+    loop {
+        let started = Instant::now();
+        let result = source.get_sample();
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => return,
+        };
+        {
+            let mut state = state.lock().unwrap();
+            match result {
+                Ok(sample) => state.samples.push_back((Instant::now(), sample)),
+                Err(e) => state.last_error = Some(e.to_string()),
+            }
+            let retention = state.window.unwrap_or(UNRESOLVED_WINDOW_RETENTION);
+            prune_samples(&mut state.samples, Instant::now(), retention);
+        }
+        drop(state);
+        thread::sleep(sample_interval.saturating_sub(started.elapsed()));
+    }
+}
+
+/// The start of the window ending at `end` (the first sample if the window
+/// reaches back further than the process has been running)
+fn window_start(samples: &VecDeque<(Instant, Sample)>, end: Instant, window: Duration) -> Instant {
+    end.checked_sub(window)
+        .or(samples.front().map(|(t, _)| *t))
+        .unwrap_or(end)
+}
+
+/// Drops the samples before the window, except for the latest of them (which
+/// still was the latest sample at the start of the window)
+fn prune_samples(samples: &mut VecDeque<(Instant, Sample)>, now: Instant, window: Duration) {
+    let start = window_start(samples, now, window);
+    while samples.len() > 1 && samples[1].0 <= start {
+        samples.pop_front();
+    }
+}
+
+/// The average of the `samples` between `start` & `end`, weighting every
+/// sample by how long it was the latest sample. The window starts at the
+/// first sample if none was taken before `start`.
+fn time_weighted_average(
+    samples: &[(Instant, Sample)],
+    start: Instant,
+    end: Instant,
+) -> Result<Datapoint> {
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0.0;
+    for (i, (taken, sample)) in samples.iter().enumerate() {
+        let from = (*taken).max(start);
+        let to = samples.get(i + 1).map(|(t, _)| *t).unwrap_or(end).min(end);
+        if to > from {
+            let weight = (to - from).as_secs_f64();
+            weighted_sum += sample.datapoint as f64 * weight;
+            total_weight += weight;
+        }
+    }
+    if total_weight > 0.0 {
+        return Ok((weighted_sum / total_weight).round() as Datapoint);
+    }
+    // A single sample taken just now
+    samples
+        .iter()
+        .rev()
+        .find(|(t, _)| *t >= start && *t <= end)
+        .map(|(_, s)| s.datapoint)
+        .ok_or(anyhow!(
+            "No samples were taken within the averaging window."
+        ))
+}
+
+/// The average of the `samples` taken since `start`, weighting every sample by
+/// its volume
+fn volume_weighted_average(samples: &[(Instant, Sample)], start: Instant) -> Result<Datapoint> {
+    let mut weighted_sum = 0.0;
+    let mut total_volume = 0.0;
+    for (_, sample) in samples.iter().filter(|(t, _)| *t >= start) {
+        let volume = sample.volume.ok_or(anyhow!(
+            "The source reports no volumes, which volume-weighted averaging requires."
+        ))?;
+        weighted_sum += sample.datapoint as f64 * volume;
+        total_volume += volume;
+    }
+    if !(total_volume > 0.0) {
+        return Err(anyhow!("No volume was traded within the averaging window."));
+    }
+    Ok((weighted_sum / total_volume).round() as Datapoint)
+}
+
+/// Formats a duration as ie. "1h 5m 30s"
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let parts: Vec<String> = vec![(hours, "h"), (minutes, "m"), (seconds, "s")]
+        .into_iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect();
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(start: Instant, values: &[(u64, Datapoint, Option<f64>)]) -> Vec<(Instant, Sample)> {
+        values
+            .iter()
+            .map(|(seconds, datapoint, volume)| {
+                (
+                    start + Duration::from_secs(*seconds),
+                    Sample {
+                        datapoint: *datapoint,
+                        volume: *volume,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_are_weighted() {
+        let start = Instant::now();
+        let end = start + Duration::from_secs(100);
+        // 1000 for 50 seconds, 2000 for 25 seconds & 4000 for 25 seconds
+        let twap = samples(
+            start,
+            &[(0, 1000, None), (50, 2000, None), (75, 4000, None)],
+        );
+        assert_eq!(time_weighted_average(&twap, start, end).unwrap(), 2000);
+        // Only the second half of the window
+        assert_eq!(
+            time_weighted_average(&twap, start + Duration::from_secs(50), end).unwrap(),
+            3000
+        );
+        let vwap = samples(
+            start,
+            &[
+                (0, 1000, Some(3.0)),
+                (50, 2000, Some(1.0)),
+                (75, 4000, Some(0.0)),
+            ],
+        );
+        assert_eq!(volume_weighted_average(&vwap, start).unwrap(), 1250);
+        assert!(volume_weighted_average(&twap, start).is_err());
+        assert!(volume_weighted_average(&vwap, start + Duration::from_secs(60)).is_err());
+    }
+
+    #[test]
+    fn averaging_settings_are_read() {
+        let load = |yaml: &str| {
+            AveragingSettings::new_from_yaml(&YamlLoader::load_from_str(yaml).unwrap()[0])
+        };
+        assert_eq!(load("core_api_port: \"9090\"").unwrap(), None);
+        assert_eq!(
+            load("datapoint_averaging: vwap\ndatapoint_averaging_window: 600").unwrap(),
+            Some(
+                AveragingSettings::new(AveragingMethod::Vwap)
+                    .with_window(AveragingWindow::Fixed(Duration::from_secs(600)))
+            )
+        );
+        assert!(load("datapoint_averaging: median").is_err());
+        assert!(load(
+            "datapoint_averaging: twap\ndatapoint_averaging_window: 600\ndatapoint_averaging_window_epochs: 1"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,262 @@
+/// A `DatapointSource` which runs an external command (ie. an internal script
+/// or the client of a proprietary feed) and reads the datapoint from its
+/// stdout. The output is either a plain integer or JSON, in which case the
+/// datapoint is read at a JSON pointer (the whole document by default). A
+/// command which exits with a non-zero code, or doesn't exit before its
+/// timeout (in which case it is killed), fails the fetch.
+use crate::connector::Datapoint;
+use crate::definition::DefinitionError;
+use crate::source::DatapointSource;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use yaml_rust::Yaml;
+
+/// Default time a command may run for
+static DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval at which a running command is checked for having exited
+static POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Fetches a datapoint by running an external command
+#[derive(Debug, Clone)]
+pub struct CommandSource {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Environment variables the command is run with (in addition to the
+    /// environment of the Connector)
+    pub env: Vec<(String, String)>,
+    /// JSON pointer to the datapoint if the command outputs JSON
+    pub pointer: String,
+    pub timeout: Duration,
+}
+
+impl CommandSource {
+    /// Create a new `CommandSource` which runs `program` with `args`
+    pub fn new(name: &str, program: &str, args: &[&str]) -> CommandSource {
+        CommandSource {
+            name: name.to_string(),
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: vec![],
+            pointer: "".to_string(),
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+
+    /// Set an environment variable the command is run with
+    pub fn with_env(mut self, key: &str, value: &str) -> CommandSource {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Read the datapoint at the JSON `pointer` of the output
+    pub fn with_pointer(mut self, pointer: &str) -> CommandSource {
+        self.pointer = pointer.to_string();
+        self
+    }
+
+    /// Kill the command if it runs for longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> CommandSource {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Create a `CommandSource` from a `&Yaml` source definition with the
+    /// fields `command` and optionally `name`, `args` (a list), `env` (a
+    /// mapping), `pointer` & `timeout` (in seconds, defaults to 10)
+    pub fn new_from_yaml(definition: &Yaml) -> std::result::Result<CommandSource, DefinitionError> {
+        let program = definition["command"]
+            .as_str()
+            .ok_or(DefinitionError::MissingField("command".to_string()))?;
+        let invalid = |field: &str, reason: &str| DefinitionError::InvalidField {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut source =
+            CommandSource::new(definition["name"].as_str().unwrap_or(program), program, &[]);
+        if let Some(args) = definition["args"].as_vec() {
+            for arg in args {
+                let arg = match arg {
+                    Yaml::String(s) => s.clone(),
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Real(r) => r.clone(),
+                    _ => return Err(invalid("args", "Arguments must be strings.")),
+                };
+                source.args.push(arg);
+            }
+        }
+        if let Some(env) = definition["env"].as_hash() {
+            for (key, value) in env {
+                match (key.as_str(), value.as_str()) {
+                    (Some(k), Some(v)) => source = source.with_env(k, v),
+                    _ => {
+                        return Err(invalid(
+                            "env",
+                            "Environment variables and their values must be strings.",
+                        ))
+                    }
+                }
+            }
+        }
+        if let Some(pointer) = definition["pointer"].as_str() {
+            source.pointer = pointer.to_string();
+        }
+        if let Some(timeout) = definition["timeout"]
+            .as_f64()
+            .or(definition["timeout"].as_i64().map(|n| n as f64))
+        {
+            if !(timeout > 0.0) {
+                return Err(invalid("timeout", "The timeout must be positive."));
+            }
+            source.timeout = Duration::from_secs_f64(timeout);
+        }
+        Ok(source)
+    }
+
+    /// Runs the command, returning its stdout if it exits successfully within
+    /// the `timeout`
+    fn run(&self, timeout: Duration) -> Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .envs(self.env.iter().cloned())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run `{}`: {}", self.program, e))?;
+        // The pipes are drained while the command runs so that it can't block
+        // on a full pipe
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if started.elapsed() >= timeout {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(anyhow!(
+                    "`{}` was killed after running for {:.1} seconds.",
+                    self.program,
+                    timeout.as_secs_f64()
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        if !status.success() {
+            let stderr = stderr.join().unwrap_or_default();
+            return Err(anyhow!(
+                "`{}` failed ({}): {}",
+                self.program,
+                status,
+                stderr.trim()
+            ));
+        }
+        Ok(stdout)
+    }
+
+    /// Reads the datapoint from the output of the command
+    pub fn datapoint_from_output(&self, output: &str) -> Result<Datapoint> {
+        let output = output.trim();
+        if let Ok(datapoint) = output.parse::<Datapoint>() {
+            return Ok(datapoint);
+        }
+        let json: Value = serde_json::from_str(output)
+            .map_err(|_| anyhow!("The output is neither an integer nor JSON: {}", output))?;
+        match json.pointer(&self.pointer) {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => s.trim().parse::<Datapoint>().ok(),
+            _ => None,
+        }
+        .ok_or(anyhow!(
+            "No non-negative integer datapoint found at `{}` in the output.",
+            self.pointer
+        ))
+    }
+
+    fn fetch(&self, timeout: Duration) -> Result<Datapoint> {
+        self.datapoint_from_output(&self.run(timeout)?)
+    }
+}
+
+impl DatapointSource for CommandSource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        self.fetch(self.timeout)
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        self.fetch(
+            self.timeout
+                .min(deadline.saturating_duration_since(Instant::now())),
+        )
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Reads a pipe of a child process to the end on a separate thread
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_string(&mut output).ok();
+        }
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> CommandSource {
+        CommandSource::new("Test", "sh", &["-c", script])
+    }
+
+    #[test]
+    fn command_output_is_parsed() {
+        assert_eq!(shell("echo 1234").get_datapoint().unwrap(), 1234);
+        assert_eq!(
+            shell("echo '{\"erg\": {\"usd\": \"5678\"}}'")
+                .with_pointer("/erg/usd")
+                .get_datapoint()
+                .unwrap(),
+            5678
+        );
+        assert_eq!(
+            shell("echo $PRICE")
+                .with_env("PRICE", "42")
+                .get_datapoint()
+                .unwrap(),
+            42
+        );
+        assert!(shell("echo 1.5").get_datapoint().is_err());
+        assert!(shell("echo '{\"price\": -1}'")
+            .with_pointer("/price")
+            .get_datapoint()
+            .is_err());
+    }
+
+    #[test]
+    fn failing_commands_are_errors() {
+        let error = shell("echo Offline >&2; exit 3")
+            .get_datapoint()
+            .unwrap_err();
+        assert!(error.to_string().contains("Offline"));
+        let started = Instant::now();
+        assert!(shell("sleep 5")
+            .with_timeout(Duration::from_millis(100))
+            .get_datapoint()
+            .is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
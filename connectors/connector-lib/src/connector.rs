@@ -1,18 +1,33 @@
-use crate::oracle_core::{get_core_api_port, OracleCore};
+use crate::averaging::{get_averaging_settings, AveragingSource};
+use crate::guard::{get_guard_settings, DatapointGuard, GuardRejection};
+use crate::oracle_core::{get_oracle_core, OracleCore, PoolStatus};
+use crate::policy::{fetch_with_policy, get_fetch_policy, FetchPolicy};
+use crate::source::{DatapointSource, FnSource};
+use crate::ui::{BasicConnectorUi, ConnectorUi, FnConnectorUi};
 use anyhow::Result;
 use ergo_lib::chain::Base16Str;
 use ergo_lib::ergotree_ir::mir::constant::Constant;
 use std::env;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub type Datapoint = u64;
 
+/// Expected time between blocks (in seconds) if the core provides no estimate
+pub(crate) static EXPECTED_BLOCK_INTERVAL: u64 = 120;
+
 #[derive(Clone)]
 pub struct Connector {
     pub title: String,
-    pub get_datapoint: fn() -> Result<Datapoint>,
-    pub print_info: fn(&Connector, &OracleCore) -> Result<bool>,
+    /// The source the datapoints are fetched from
+    pub source: Arc<dyn DatapointSource>,
+    /// Reports the state & activity of the Connector
+    pub ui: Arc<dyn ConnectorUi>,
+    /// Timeout & retries applied when fetching a datapoint from the source
+    pub fetch_policy: FetchPolicy,
+    /// Sanity checks & circuit breaker applied before submitting a datapoint
+    pub guard: Arc<DatapointGuard>,
 }
 
 // Key Connector methods
@@ -23,20 +38,94 @@ impl Connector {
         get_datapoint: fn() -> Result<u64>,
         print_info: fn(&Connector, &OracleCore) -> Result<bool>,
     ) -> Connector {
-        let connector = Connector {
+        Connector::from_source(title, FnSource(get_datapoint), FnConnectorUi(print_info))
+    }
+
+    /// Create a new custom Connector which fetches datapoints from `source`
+    /// and reports via `ui`. If datapoint averaging is configured, the source
+    /// is sampled in the background and the average of the window is
+    /// submitted instead (bootstrap values are taken from `source` directly).
+    pub fn from_source(
+        title: &str,
+        source: impl DatapointSource + 'static,
+        ui: impl ConnectorUi + 'static,
+    ) -> Connector {
+        let mut connector = Connector {
             title: title.to_string(),
-            get_datapoint: get_datapoint,
-            print_info: print_info,
+            source: Arc::new(source),
+            ui: Arc::new(ui),
+            fetch_policy: get_fetch_policy().unwrap_or_default(),
+            guard: Arc::new(DatapointGuard::new(
+                get_guard_settings().unwrap_or_default(),
+            )),
         };
         connector.check_bootstrap();
+        let averaging = get_averaging_settings().expect(
+            "Failed to read the datapoint averaging settings from local `oracle-config.yaml`.",
+        );
+        if let Some(settings) = averaging {
+            connector.source = Arc::new(AveragingSource::start(
+                title,
+                connector.source.clone(),
+                settings,
+            ));
+        }
         connector
     }
 
+    /// Fetch the current datapoint from the source of the Connector
+    pub fn get_datapoint(&self) -> Result<Datapoint> {
+        self.source.get_datapoint()
+    }
+
+    /// Fetch the current datapoint following the `fetch_policy`, retrying
+    /// failed attempts as long as they can start before the `deadline`
+    pub fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        fetch_with_policy(&self.source, &self.fetch_policy, Some(deadline))
+    }
+
+    /// Fetches a datapoint, checks it with the `guard` and submits it to the core
+    fn fetch_and_submit_datapoint(&self, oc: &OracleCore, pool_status: &PoolStatus) {
+        let deadline = self.live_epoch_deadline(pool_status, oc);
+        let price = match self.get_datapoint_before(deadline) {
+            Ok(price) => price,
+            Err(e) => return self.ui.error(self, &e),
+        };
+        match self.guard.check(price, pool_status.latest_datapoint) {
+            Ok(()) => (),
+            Err(e @ GuardRejection::CircuitBreakerTripped { .. }) => {
+                return self.ui.alert(self, &e.to_string())
+            }
+            Err(e) => return self.ui.error(self, &e.into()),
+        }
+        match oc.submit_datapoint(price) {
+            Ok(tx_id) => self.ui.datapoint_submitted(self, price, &tx_id),
+            Err(e) => self.ui.error(self, &e.into()),
+        }
+    }
+
+    /// The time by which a datapoint must be fetched to be submitted in the
+    /// current Live Epoch (at least the timeout of a single attempt)
+    fn live_epoch_deadline(&self, pool_status: &PoolStatus, oc: &OracleCore) -> Instant {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let seconds_left = match pool_status.epoch_ends_time {
+            Some(epoch_ends_time) => epoch_ends_time.saturating_sub(now),
+            None => {
+                let height = oc.current_block_height().unwrap_or(pool_status.epoch_ends);
+                pool_status.epoch_ends.saturating_sub(height) * EXPECTED_BLOCK_INTERVAL
+            }
+        };
+        Instant::now() + Duration::from_secs(seconds_left).max(self.fetch_policy.timeout)
+    }
+
     /// Checks if asked for bootstrap value via CLI flag
     pub fn check_bootstrap(&self) {
         let args: Vec<String> = env::args().collect();
         if args.len() > 1 && &args[1] == "--bootstrap-value" {
-            if let Ok(price) = (self.get_datapoint)() {
+            if let Ok(price) = self.get_datapoint() {
                 // Convert the price into a sigma `Constant`
                 let constant: Constant = (price as i64).into();
 
@@ -48,18 +137,21 @@ impl Connector {
         }
     }
 
-    /// Run the Connector using a local Oracle Core
+    /// Run the Connector using the Oracle Core configured in the local config file
     pub fn run(&self) {
-        let core_port =
-            get_core_api_port().expect("Failed to read port from local `oracle-config.yaml`.");
-        let oc = OracleCore::new("0.0.0.0", &core_port);
+        let oc = get_oracle_core().unwrap_or_else(|e| {
+            panic!(
+                "Failed to read the Oracle Core settings from local `oracle-config.yaml`: {}",
+                e
+            )
+        });
 
         // Main Loop
         loop {
             // If printing isn't successful (which involves fetching state from core)
-            if let Err(e) = (self.print_info)(&self, &oc) {
+            if let Err(e) = self.ui.print_info(&self, &oc) {
                 print!("\x1B[2J\x1B[1;1H");
-                println!("Error: {:?}", e);
+                self.ui.error(&self, &e);
             }
             // Otherwise if state is accessible
             else {
@@ -68,16 +160,19 @@ impl Connector {
                 let res_os = oc.oracle_status();
                 if let Err(e) = res_ps {
                     print!("\x1B[2J\x1B[1;1H");
-                    println!("Error: {:?}", e);
+                    self.ui.error(&self, &e.into());
                     continue;
                 }
                 if let Err(e) = res_os {
                     print!("\x1B[2J\x1B[1;1H");
-                    println!("Error: {:?}", e);
+                    self.ui.error(&self, &e.into());
                     continue;
                 }
                 let pool_status = res_ps.unwrap();
                 let oracle_status = res_os.unwrap();
+                if let Ok(pool_info) = oc.pool_info() {
+                    self.source.pool_updated(&pool_info, &pool_status);
+                }
 
                 // Check if Connector should post
                 let repost_check = oc.repost_check().unwrap_or_default();
@@ -86,20 +181,7 @@ impl Connector {
                         && oracle_status.waiting_for_datapoint_submit;
 
                 if should_post {
-                    let price_res = (self.get_datapoint)();
-                    // If acquiring price worked
-                    if let Ok(price) = price_res {
-                        // If submitting Datapoint tx worked
-                        let submit_result = oc.submit_datapoint(price);
-                        if let Ok(tx_id) = submit_result {
-                            println!("\nSubmit New {} Datapoint: {}", self.title, price);
-                            println!("Transaction ID: {}", tx_id);
-                        } else {
-                            println!("Datapoint Tx Submit Error: {:?}", submit_result);
-                        }
-                    } else {
-                        println!("{:?}", price_res);
-                    }
+                    self.fetch_and_submit_datapoint(&oc, &pool_status);
                 }
             }
 
@@ -112,28 +194,14 @@ impl Connector {
 impl Connector {
     /// Create a new basic Connector with a number of predefined defaults
     pub fn new_basic_connector(title: &str, get_datapoint: fn() -> Result<u64>) -> Connector {
-        Connector::new(title, get_datapoint, Connector::basic_print_info)
+        Connector::new_basic_connector_from_source(title, FnSource(get_datapoint))
     }
 
-    // Default Basic Connector print info
-    fn basic_print_info(&self, oc: &OracleCore) -> Result<bool> {
-        let pool_status = oc.pool_status()?;
-        let oracle_status = oc.oracle_status()?;
-        print!("\x1B[2J\x1B[1;1H");
-        println!("{} Connector", self.title);
-        println!("===========================================");
-        println!("Current Blockheight: {}", oc.current_block_height()?);
-        println!(
-            "Current Oracle Pool Stage: {}",
-            pool_status.current_pool_stage
-        );
-        println!(
-            "Submit Datapoint In Latest Epoch: {}",
-            !oracle_status.waiting_for_datapoint_submit
-        );
-
-        println!("Latest Datapoint: {}", oracle_status.latest_datapoint);
-        println!("===========================================");
-        Ok(true)
+    /// Create a new basic Connector which fetches datapoints from `source`
+    pub fn new_basic_connector_from_source(
+        title: &str,
+        source: impl DatapointSource + 'static,
+    ) -> Connector {
+        Connector::from_source(title, source, BasicConnectorUi)
     }
 }
@@ -0,0 +1,302 @@
+/// Connectors defined declaratively in the `connector` section of the
+/// `oracle-config.yaml`, so that a new pool can be served by the generic
+/// connector without writing any Rust code:
+///
+/// ```yaml
+/// connector:
+///   title: "Erg-USD"
+///   # "median" (requiring a quorum of sources) or "fallback" (first source which succeeds)
+///   aggregation: "median"
+///   quorum: 2
+///   max_deviation: 0.05
+///   sources:
+///     - name: "CoinGecko"
+///       url: "https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD"
+///       pointer: "/ergo/usd"
+///       invert: true
+///       scale: 1000000000
+///     - name: "Internal Feed"
+///       command: "./erg-usd-feed.sh"
+///       args: ["--nanoergs"]
+///     - name: "Exchange Stream"
+///       stream: "wss://exchange.example/ws"
+///       subscribe: '{"type": "subscribe", "topic": "/market/ticker:ERG-USDT"}'
+///       pointer: "/data/price"
+///       invert: true
+///       scale: 1000000000
+/// ```
+use crate::aggregator::Aggregator;
+use crate::command_source::CommandSource;
+use crate::connector::Datapoint;
+use crate::http_source::HttpJsonSource;
+use crate::oracle_core::{get_config_yaml_string, ConnectorError};
+use crate::policy::FallbackSource;
+use crate::source::{DatapointSource, Sample};
+use crate::stream_source::{StreamSettings, StreamSource};
+use std::time::Instant;
+use thiserror::Error;
+use yaml_rust::{Yaml, YamlLoader};
+
+#[derive(Error, Debug)]
+pub enum DefinitionError {
+    #[error("The `connector` section is missing from the local `oracle-config.yaml` file.")]
+    MissingDefinition,
+    #[error("The `{0}` field of the connector definition is missing.")]
+    MissingField(String),
+    #[error("The `{field}` field of the connector definition is invalid: {reason}")]
+    InvalidField { field: String, reason: String },
+    #[error("The connector definition has no sources.")]
+    NoSources,
+    #[error("{0}")]
+    Config(#[from] ConnectorError),
+}
+
+/// How the datapoints of the sources of a definition are combined
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aggregation {
+    /// The median of the sources via an `Aggregator`
+    Median { quorum: usize, max_deviation: f64 },
+    /// The first source which succeeds via a `FallbackSource`
+    Fallback,
+}
+
+/// A source of a `ConnectorDefinition`
+#[derive(Clone)]
+pub enum DefinedSource {
+    Http(HttpJsonSource),
+    Command(CommandSource),
+    Stream(StreamSource),
+}
+
+impl DefinedSource {
+    /// Create a `DefinedSource` from a `&Yaml` source definition. Sources with
+    /// a `command` run an external command, sources with a `stream` stream
+    /// from it (once started by `ConnectorDefinition::into_source`), all
+    /// others request their `url`.
+    pub fn new_from_yaml(definition: &Yaml) -> Result<DefinedSource, DefinitionError> {
+        if !definition["command"].is_badvalue() {
+            Ok(DefinedSource::Command(CommandSource::new_from_yaml(
+                definition,
+            )?))
+        } else if !definition["stream"].is_badvalue() {
+            let settings = StreamSettings::new_from_yaml(definition)?;
+            let name = definition["name"]
+                .as_str()
+                .map(|n| n.to_string())
+                .unwrap_or(settings.url.clone());
+            Ok(DefinedSource::Stream(StreamSource::new(&name, settings)))
+        } else {
+            Ok(DefinedSource::Http(HttpJsonSource::new_from_yaml(
+                definition,
+            )?))
+        }
+    }
+
+    /// Converts a datapoint back into the price reported by the source
+    /// (commands output the datapoint itself)
+    pub fn price_of(&self, datapoint: Datapoint) -> f64 {
+        match self {
+            DefinedSource::Http(source) => source.price_of(datapoint),
+            DefinedSource::Command(_) => datapoint as f64,
+            DefinedSource::Stream(source) => source.settings().price_of(datapoint),
+        }
+    }
+}
+
+impl DatapointSource for DefinedSource {
+    fn get_datapoint(&self) -> anyhow::Result<Datapoint> {
+        match self {
+            DefinedSource::Http(source) => source.get_datapoint(),
+            DefinedSource::Command(source) => source.get_datapoint(),
+            DefinedSource::Stream(source) => source.get_datapoint(),
+        }
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> anyhow::Result<Datapoint> {
+        match self {
+            DefinedSource::Http(source) => source.get_datapoint_before(deadline),
+            DefinedSource::Command(source) => source.get_datapoint_before(deadline),
+            DefinedSource::Stream(source) => source.get_datapoint_before(deadline),
+        }
+    }
+
+    fn get_sample(&self) -> anyhow::Result<Sample> {
+        match self {
+            DefinedSource::Http(source) => source.get_sample(),
+            DefinedSource::Command(source) => source.get_sample(),
+            DefinedSource::Stream(source) => source.get_sample(),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            DefinedSource::Http(source) => source.name(),
+            DefinedSource::Command(source) => source.name(),
+            DefinedSource::Stream(source) => source.name(),
+        }
+    }
+}
+
+/// A Connector defined in config
+#[derive(Clone)]
+pub struct ConnectorDefinition {
+    pub title: String,
+    pub aggregation: Aggregation,
+    pub sources: Vec<DefinedSource>,
+}
+
+impl ConnectorDefinition {
+    /// Create a `ConnectorDefinition` from the `&Yaml` of the `connector`
+    /// section. `aggregation` defaults to "median" with a `quorum` of 1 and a
+    /// `max_deviation` of 0.05.
+    pub fn new_from_yaml(definition: &Yaml) -> Result<ConnectorDefinition, DefinitionError> {
+        if definition.is_badvalue() || definition.is_null() {
+            return Err(DefinitionError::MissingDefinition);
+        }
+        let title = definition["title"]
+            .as_str()
+            .ok_or(DefinitionError::MissingField("title".to_string()))?;
+        let aggregation = match definition["aggregation"].as_str().unwrap_or("median") {
+            "median" => Aggregation::Median {
+                quorum: definition["quorum"].as_i64().unwrap_or(1).max(1) as usize,
+                max_deviation: definition["max_deviation"]
+                    .as_f64()
+                    .or(definition["max_deviation"].as_i64().map(|n| n as f64))
+                    .unwrap_or(0.05),
+            },
+            "fallback" => Aggregation::Fallback,
+            other => {
+                return Err(DefinitionError::InvalidField {
+                    field: "aggregation".to_string(),
+                    reason: format!("Unknown aggregation \"{}\".", other),
+                })
+            }
+        };
+        let sources = definition["sources"]
+            .as_vec()
+            .ok_or(DefinitionError::NoSources)?
+            .iter()
+            .map(DefinedSource::new_from_yaml)
+            .collect::<Result<Vec<_>, _>>()?;
+        if sources.is_empty() {
+            return Err(DefinitionError::NoSources);
+        }
+        if let Aggregation::Median { quorum, .. } = aggregation {
+            if quorum > sources.len() {
+                return Err(DefinitionError::InvalidField {
+                    field: "quorum".to_string(),
+                    reason: format!(
+                        "A quorum of {} can't be reached by {} sources.",
+                        quorum,
+                        sources.len()
+                    ),
+                });
+            }
+        }
+        Ok(ConnectorDefinition {
+            title: title.to_string(),
+            aggregation: aggregation,
+            sources: sources,
+        })
+    }
+
+    /// Converts a datapoint back into the price, as reported by the first source
+    pub fn price_of(&self, datapoint: Datapoint) -> f64 {
+        self.sources[0].price_of(datapoint)
+    }
+
+    /// The names of the sources
+    pub fn source_names(&self) -> Vec<String> {
+        self.sources.iter().map(|s| s.name()).collect()
+    }
+
+    /// Combines the sources into the single `DatapointSource` of the
+    /// Connector, starting the streams of the stream sources
+    pub fn into_source(self) -> Box<dyn DatapointSource> {
+        for source in &self.sources {
+            if let DefinedSource::Stream(stream) = source {
+                stream.start_streaming();
+            }
+        }
+        let mut sources = self.sources;
+        if sources.len() == 1 {
+            return Box::new(sources.remove(0));
+        }
+        match self.aggregation {
+            Aggregation::Median {
+                quorum,
+                max_deviation,
+            } => Box::new(sources.into_iter().fold(
+                Aggregator::new(&self.title, quorum, max_deviation),
+                |a, s| a.with_source(s),
+            )),
+            Aggregation::Fallback => Box::new(
+                sources
+                    .into_iter()
+                    .fold(FallbackSource::new(&self.title), |f, s| f.with_source(s)),
+            ),
+        }
+    }
+}
+
+/// Returns the `ConnectorDefinition` in the `connector` section of the local
+/// config file
+pub fn get_connector_definition() -> Result<ConnectorDefinition, DefinitionError> {
+    let config_string = get_config_yaml_string()?;
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    ConnectorDefinition::new_from_yaml(&config["connector"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_are_validated() {
+        let load = |yaml: &str| {
+            let config = &YamlLoader::load_from_str(yaml).unwrap()[0];
+            ConnectorDefinition::new_from_yaml(&config["connector"])
+        };
+        let definition = load(
+            "
+connector:
+  title: Erg-USD
+  quorum: 2
+  sources:
+    - url: https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD
+      pointer: /ergo/usd
+    - url: https://api.kucoin.com/api/v1/market/orderbook/level1?symbol=ERG-USDT
+      pointer: /data/price
+    - name: Internal Feed
+      command: ./erg-usd-feed.sh
+      args: [--nanoergs]
+",
+        )
+        .unwrap();
+        assert_eq!(
+            definition.aggregation,
+            Aggregation::Median {
+                quorum: 2,
+                max_deviation: 0.05
+            }
+        );
+        assert_eq!(definition.sources.len(), 3);
+        assert!(matches!(
+            &definition.sources[2],
+            DefinedSource::Command(CommandSource { args, .. }) if args == &["--nanoergs"]
+        ));
+        assert!(matches!(
+            load("core_api_port: \"9090\""),
+            Err(DefinitionError::MissingDefinition)
+        ));
+        assert!(matches!(
+            load("connector:\n  title: Erg-USD\n  sources: []"),
+            Err(DefinitionError::NoSources)
+        ));
+        assert!(matches!(
+            load("connector:\n  title: Erg-USD\n  sources:\n    - url: https://example.com"),
+            Err(DefinitionError::MissingField(_))
+        ));
+    }
+}
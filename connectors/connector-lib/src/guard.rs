@@ -0,0 +1,277 @@
+/// Sanity checks of the datapoints a Connector is about to submit. Every
+/// datapoint must lie within absolute bounds and, if configured, must not
+/// change too much relative to the pool's latest finalized datapoint. If
+/// enabled, the circuit breaker trips after a number of consecutive anomalous
+/// datapoints and pauses all submissions for a while, so that a broken source
+/// can't keep feeding the pool bad datapoints while the operator is alerted.
+use crate::connector::Datapoint;
+use crate::oracle_core::{get_config_yaml_string, ConnectorError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use yaml_rust::{Yaml, YamlLoader};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum GuardRejection {
+    #[error("Datapoint {datapoint} is below the minimum of {minimum}.")]
+    BelowMinimum {
+        datapoint: Datapoint,
+        minimum: Datapoint,
+    },
+    #[error("Datapoint {datapoint} is above the maximum of {maximum}.")]
+    AboveMaximum {
+        datapoint: Datapoint,
+        maximum: Datapoint,
+    },
+    #[error("Datapoint {datapoint} differs by {change:.2}% from the pool's latest datapoint {latest}, more than the allowed {max_change:.2}%.")]
+    ExcessiveChange {
+        datapoint: Datapoint,
+        latest: Datapoint,
+        change: f64,
+        max_change: f64,
+    },
+    #[error("Circuit breaker tripped after {anomalies} consecutive anomalous datapoints, datapoint submissions are paused for {pause_seconds} seconds. Latest anomaly: {reason}")]
+    CircuitBreakerTripped {
+        anomalies: u32,
+        reason: Box<GuardRejection>,
+        pause_seconds: u64,
+    },
+    #[error("Datapoint submissions are paused by the circuit breaker for another {0} seconds.")]
+    CircuitOpen(u64),
+}
+
+/// The limits datapoints are checked against
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardSettings {
+    pub min_datapoint: Datapoint,
+    pub max_datapoint: Datapoint,
+    /// Maximum change versus the pool's latest datapoint in percent (`None` disables the check)
+    pub max_change_percent: Option<f64>,
+    /// Number of consecutive anomalies which trip the circuit breaker (0 disables it)
+    pub breaker_threshold: u32,
+    /// How long submissions are paused once the circuit breaker tripped
+    pub breaker_pause: Duration,
+}
+
+impl Default for GuardSettings {
+    fn default() -> GuardSettings {
+        GuardSettings {
+            // A datapoint of 0 is never valid
+            min_datapoint: 1,
+            // Datapoints are stored on-chain as a signed `Long`
+            max_datapoint: i64::MAX as Datapoint,
+            max_change_percent: None,
+            breaker_threshold: 0,
+            breaker_pause: Duration::from_secs(1800),
+        }
+    }
+}
+
+impl GuardSettings {
+    /// Create `GuardSettings` from a `&Yaml` config, using the defaults for
+    /// the settings which are not set
+    pub fn new_from_yaml(config: &Yaml) -> GuardSettings {
+        let default = GuardSettings::default();
+        let number = |key: &str| {
+            config[key]
+                .as_f64()
+                .or(config[key].as_i64().map(|n| n as f64))
+        };
+        GuardSettings {
+            min_datapoint: config["datapoint_min"]
+                .as_i64()
+                .map(|n| n as Datapoint)
+                .unwrap_or(default.min_datapoint),
+            max_datapoint: config["datapoint_max"]
+                .as_i64()
+                .map(|n| n as Datapoint)
+                .unwrap_or(default.max_datapoint),
+            max_change_percent: match number("datapoint_max_change_percent") {
+                Some(p) if p <= 0.0 => None,
+                Some(p) => Some(p),
+                None => default.max_change_percent,
+            },
+            breaker_threshold: config["circuit_breaker_threshold"]
+                .as_i64()
+                .map(|n| n as u32)
+                .unwrap_or(default.breaker_threshold),
+            breaker_pause: number("circuit_breaker_pause")
+                .map(Duration::from_secs_f64)
+                .unwrap_or(default.breaker_pause),
+        }
+    }
+}
+
+/// Returns the `GuardSettings` ("datapoint_min", "datapoint_max",
+/// "datapoint_max_change_percent", "circuit_breaker_threshold" &
+/// "circuit_breaker_pause") from the local config file
+pub fn get_guard_settings() -> crate::oracle_core::Result<GuardSettings> {
+    let config_string = get_config_yaml_string()?;
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    Ok(GuardSettings::new_from_yaml(config))
+}
+
+/// The state of the circuit breaker
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_anomalies: u32,
+    paused_until: Option<Instant>,
+}
+
+/// Checks datapoints before they are submitted
+pub struct DatapointGuard {
+    pub settings: GuardSettings,
+    state: Mutex<BreakerState>,
+}
+
+impl DatapointGuard {
+    pub fn new(settings: GuardSettings) -> DatapointGuard {
+        DatapointGuard {
+            settings: settings,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Checks whether `datapoint` may be submitted, given the pool's
+    /// `latest_datapoint` (0 if the pool has no datapoint yet)
+    pub fn check(
+        &self,
+        datapoint: Datapoint,
+        latest_datapoint: Datapoint,
+    ) -> Result<(), GuardRejection> {
+        self.check_at(datapoint, latest_datapoint, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        datapoint: Datapoint,
+        latest_datapoint: Datapoint,
+        now: Instant,
+    ) -> Result<(), GuardRejection> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(paused_until) = state.paused_until {
+            if now < paused_until {
+                return Err(GuardRejection::CircuitOpen((paused_until - now).as_secs()));
+            }
+            state.paused_until = None;
+        }
+        let anomaly = match self.find_anomaly(datapoint, latest_datapoint) {
+            Some(anomaly) => anomaly,
+            None => {
+                state.consecutive_anomalies = 0;
+                return Ok(());
+            }
+        };
+        state.consecutive_anomalies += 1;
+        let threshold = self.settings.breaker_threshold;
+        if threshold == 0 || state.consecutive_anomalies < threshold {
+            return Err(anomaly);
+        }
+        let anomalies = state.consecutive_anomalies;
+        state.consecutive_anomalies = 0;
+        state.paused_until = Some(now + self.settings.breaker_pause);
+        Err(GuardRejection::CircuitBreakerTripped {
+            anomalies: anomalies,
+            reason: Box::new(anomaly),
+            pause_seconds: self.settings.breaker_pause.as_secs(),
+        })
+    }
+
+    /// Checks `datapoint` against the bounds & maximum change
+    fn find_anomaly(
+        &self,
+        datapoint: Datapoint,
+        latest_datapoint: Datapoint,
+    ) -> Option<GuardRejection> {
+        if datapoint < self.settings.min_datapoint {
+            return Some(GuardRejection::BelowMinimum {
+                datapoint: datapoint,
+                minimum: self.settings.min_datapoint,
+            });
+        }
+        if datapoint > self.settings.max_datapoint {
+            return Some(GuardRejection::AboveMaximum {
+                datapoint: datapoint,
+                maximum: self.settings.max_datapoint,
+            });
+        }
+        match self.settings.max_change_percent {
+            Some(max_change) if latest_datapoint > 0 => {
+                let change = (datapoint as f64 - latest_datapoint as f64).abs()
+                    / latest_datapoint as f64
+                    * 100.0;
+                if change > max_change {
+                    Some(GuardRejection::ExcessiveChange {
+                        datapoint: datapoint,
+                        latest: latest_datapoint,
+                        change: change,
+                        max_change: max_change,
+                    })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datapoints_are_checked() {
+        let guard = DatapointGuard::new(GuardSettings::default());
+        // The change is only checked if configured
+        assert_eq!(guard.check(90000, 900), Ok(()));
+        let guard = DatapointGuard::new(GuardSettings {
+            max_change_percent: Some(50.0),
+            ..GuardSettings::default()
+        });
+        assert_eq!(guard.check(1000, 900), Ok(()));
+        assert_eq!(guard.check(1000, 0), Ok(()));
+        assert_eq!(
+            guard.check(0, 900),
+            Err(GuardRejection::BelowMinimum {
+                datapoint: 0,
+                minimum: 1
+            })
+        );
+        assert!(matches!(
+            guard.check(u64::MAX, 900),
+            Err(GuardRejection::AboveMaximum { .. })
+        ));
+        assert!(matches!(
+            guard.check(90000, 900),
+            Err(GuardRejection::ExcessiveChange { .. })
+        ));
+    }
+
+    #[test]
+    fn circuit_breaker_trips_after_consecutive_anomalies() {
+        let guard = DatapointGuard::new(GuardSettings {
+            breaker_threshold: 3,
+            ..GuardSettings::default()
+        });
+        let now = Instant::now();
+        assert!(guard.check_at(0, 900, now).is_err());
+        // A valid datapoint resets the count
+        assert_eq!(guard.check_at(1000, 900, now), Ok(()));
+        assert!(guard.check_at(0, 900, now).is_err());
+        assert!(guard.check_at(0, 900, now).is_err());
+        assert!(matches!(
+            guard.check_at(0, 900, now),
+            Err(GuardRejection::CircuitBreakerTripped { anomalies: 3, .. })
+        ));
+        assert_eq!(
+            guard.check_at(1000, 900, now + Duration::from_secs(60)),
+            Err(GuardRejection::CircuitOpen(1740))
+        );
+        assert_eq!(
+            guard.check_at(1000, 900, now + Duration::from_secs(1800)),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,294 @@
+/// A `DatapointSource` which fetches a price from an HTTP API returning JSON.
+/// The price is located in the response via a JSON pointer (RFC 6901, ie.
+/// `/ergo/usd`), optionally inverted (ie. USD per Erg into Ergs per USD) and
+/// scaled into the on-chain units of the datapoint (ie. nanoErgs).
+use crate::connector::Datapoint;
+use crate::definition::DefinitionError;
+use crate::source::{DatapointSource, Sample};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use yaml_rust::Yaml;
+
+/// Default timeout of the requests of a source
+static DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches a datapoint from an HTTP/JSON API
+#[derive(Clone)]
+pub struct HttpJsonSource {
+    pub name: String,
+    pub url: String,
+    /// Headers sent with every request (ie. API keys)
+    pub headers: Vec<(String, String)>,
+    /// JSON pointer to the price within the response
+    pub pointer: String,
+    /// Whether the price is inverted (`1 / price`) before it is scaled
+    pub invert: bool,
+    /// Factor converting the (inverted) price into the on-chain units
+    pub scale: f64,
+    /// JSON pointer to the traded volume within the response (ie. for
+    /// volume-weighted averaging)
+    pub volume_pointer: Option<String>,
+    /// Maximum duration of a request
+    timeout: Duration,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpJsonSource {
+    /// Create a new `HttpJsonSource` which requests `url` and reads the price
+    /// at `pointer`, without any inversion or scaling
+    pub fn new(name: &str, url: &str, pointer: &str) -> Result<HttpJsonSource> {
+        HttpJsonSource::with_timeout(name, url, pointer, DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    fn with_timeout(
+        name: &str,
+        url: &str,
+        pointer: &str,
+        timeout: Duration,
+    ) -> Result<HttpJsonSource> {
+        Ok(HttpJsonSource {
+            name: name.to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            pointer: pointer.to_string(),
+            invert: false,
+            scale: 1.0,
+            volume_pointer: None,
+            timeout: timeout,
+            client: reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .build()?,
+        })
+    }
+
+    /// Add a header which is sent with every request
+    pub fn with_header(mut self, name: &str, value: &str) -> HttpJsonSource {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Invert the price before it is scaled
+    pub fn inverted(mut self) -> HttpJsonSource {
+        self.invert = true;
+        self
+    }
+
+    /// Scale the (inverted) price by `scale` to convert it into on-chain units
+    pub fn scaled(mut self, scale: f64) -> HttpJsonSource {
+        self.scale = scale;
+        self
+    }
+
+    /// Read the traded volume at `volume_pointer` of the response
+    pub fn with_volume_pointer(mut self, volume_pointer: &str) -> HttpJsonSource {
+        self.volume_pointer = Some(volume_pointer.to_string());
+        self
+    }
+
+    /// Create an `HttpJsonSource` from a `&Yaml` source definition with the
+    /// fields `name`, `url`, `pointer` and optionally `headers` (a mapping),
+    /// `invert` (defaults to false), `scale` (defaults to 1), `volume_pointer`
+    /// & `timeout` (in seconds, defaults to 10)
+    pub fn new_from_yaml(
+        definition: &Yaml,
+    ) -> std::result::Result<HttpJsonSource, DefinitionError> {
+        let string = |field: &str| {
+            definition[field]
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or(DefinitionError::MissingField(field.to_string()))
+        };
+        let number = |field: &str| {
+            definition[field]
+                .as_f64()
+                .or(definition[field].as_i64().map(|n| n as f64))
+        };
+        let url = string("url")?;
+        let name = string("name").unwrap_or(url.clone());
+        let timeout = number("timeout")
+            .map(Duration::from_secs_f64)
+            .unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+        let mut source = HttpJsonSource::with_timeout(&name, &url, &string("pointer")?, timeout)
+            .map_err(|e| DefinitionError::InvalidField {
+                field: "url".to_string(),
+                reason: e.to_string(),
+            })?;
+        if let Some(headers) = definition["headers"].as_hash() {
+            for (header, value) in headers {
+                match (header.as_str(), value.as_str()) {
+                    (Some(h), Some(v)) => source = source.with_header(h, v),
+                    _ => {
+                        return Err(DefinitionError::InvalidField {
+                            field: "headers".to_string(),
+                            reason: "Headers and their values must be strings.".to_string(),
+                        })
+                    }
+                }
+            }
+        }
+        source.invert = definition["invert"].as_bool().unwrap_or(false);
+        source.volume_pointer = string("volume_pointer").ok();
+        source.scale = number("scale").unwrap_or(1.0);
+        if !(source.scale > 0.0) {
+            return Err(DefinitionError::InvalidField {
+                field: "scale".to_string(),
+                reason: "The scale must be positive.".to_string(),
+            });
+        }
+        Ok(source)
+    }
+
+    /// Converts the datapoint back into the price reported by the source
+    pub fn price_of(&self, datapoint: Datapoint) -> f64 {
+        datapoint_to_price(datapoint, self.invert, self.scale)
+    }
+
+    /// Reads the price from a JSON response body and converts it into a
+    /// datapoint
+    pub fn datapoint_from_json(&self, body: &str) -> Result<Datapoint> {
+        Ok(self.sample_from_json(body)?.datapoint)
+    }
+
+    /// Reads the price (converted into a datapoint) & the volume (if a
+    /// `volume_pointer` is set) from a JSON response body
+    pub fn sample_from_json(&self, body: &str) -> Result<Sample> {
+        let json: Value = serde_json::from_str(body)?;
+        let price = price_at(&json, &self.pointer).ok_or(anyhow!(
+            "No price found at `{}` in the response.",
+            self.pointer
+        ))?;
+        let volume = match &self.volume_pointer {
+            Some(pointer) => Some(
+                price_at(&json, pointer)
+                    .filter(|v| *v >= 0.0)
+                    .ok_or(anyhow!("No volume found at `{}` in the response.", pointer))?,
+            ),
+            None => None,
+        };
+        Ok(Sample {
+            datapoint: price_to_datapoint(price, self.invert, self.scale)?,
+            volume: volume,
+        })
+    }
+
+    /// Requests the url, returning the response body. The request fails if
+    /// it takes longer than `timeout`.
+    fn request(&self, timeout: Duration) -> Result<String> {
+        let mut req = self.client.get(&self.url).timeout(timeout);
+        for (name, value) in &self.headers {
+            req = req.header(name.as_str(), value.as_str());
+        }
+        let resp = req.send()?.error_for_status()?;
+        Ok(resp.text()?)
+    }
+}
+
+/// Reads the price at the JSON `pointer`, which is either a number or a
+/// numeric string (many exchanges return prices as strings to preserve precision)
+pub(crate) fn price_at(json: &Value, pointer: &str) -> Option<f64> {
+    match json.pointer(pointer) {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// Converts a price into a datapoint by inverting (if `invert`) & scaling it
+pub(crate) fn price_to_datapoint(price: f64, invert: bool, scale: f64) -> Result<Datapoint> {
+    let price = if invert { 1.0 / price } else { price };
+    let datapoint = (price * scale).round();
+    if !datapoint.is_finite() || datapoint < 0.0 || datapoint > Datapoint::MAX as f64 {
+        return Err(anyhow!(
+            "The price {} can't be converted into a datapoint.",
+            price
+        ));
+    }
+    Ok(datapoint as Datapoint)
+}
+
+/// Converts a datapoint back into the price it was converted from
+pub(crate) fn datapoint_to_price(datapoint: Datapoint, invert: bool, scale: f64) -> f64 {
+    let price = datapoint as f64 / scale;
+    if invert {
+        1.0 / price
+    } else {
+        price
+    }
+}
+
+impl DatapointSource for HttpJsonSource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        self.datapoint_from_json(&self.request(self.timeout)?)
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        let timeout = self
+            .timeout
+            .min(deadline.saturating_duration_since(Instant::now()));
+        self.datapoint_from_json(&self.request(timeout)?)
+    }
+
+    fn get_sample(&self) -> Result<Sample> {
+        self.sample_from_json(&self.request(self.timeout)?)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn prices_are_converted_into_datapoints() {
+        let definition = &YamlLoader::load_from_str(
+            "
+name: CoinGecko
+url: https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD
+pointer: /ergo/usd
+headers:
+  Accept: application/json
+invert: true
+scale: 1000000000
+",
+        )
+        .unwrap()[0];
+        let source = HttpJsonSource::new_from_yaml(definition).unwrap();
+        assert_eq!(source.headers.len(), 1);
+        assert_eq!(
+            source
+                .datapoint_from_json(r#"{"ergo": {"usd": 2.5}}"#)
+                .unwrap(),
+            400000000
+        );
+        assert_eq!(source.price_of(400000000), 2.5);
+        let source = HttpJsonSource::new("KuCoin", "", "/data/price").unwrap();
+        assert_eq!(
+            source
+                .datapoint_from_json(r#"{"data": {"price": "1234.4"}}"#)
+                .unwrap(),
+            1234
+        );
+        assert!(source.datapoint_from_json(r#"{"data": {}}"#).is_err());
+        assert!(source
+            .datapoint_from_json(r#"{"data": {"price": -1}}"#)
+            .is_err());
+        let source = source.with_volume_pointer("/data/vol");
+        assert_eq!(
+            source
+                .sample_from_json(r#"{"data": {"price": 1234, "vol": "56.5"}}"#)
+                .unwrap(),
+            Sample {
+                datapoint: 1234,
+                volume: Some(56.5)
+            }
+        );
+        assert!(source
+            .sample_from_json(r#"{"data": {"price": 1234}}"#)
+            .is_err());
+    }
+}
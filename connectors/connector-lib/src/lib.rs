@@ -1,8 +1,36 @@
 #[macro_use]
 extern crate json;
 
+pub mod aggregator;
+pub mod averaging;
+pub mod command_source;
 pub mod connector;
+pub mod definition;
+pub mod guard;
+pub mod http_source;
+pub mod median;
 pub mod oracle_core;
+pub mod policy;
+pub mod source;
+pub mod stream_source;
+pub mod tls;
+pub mod tls_proxy;
+pub mod ui;
 
+pub use aggregator::Aggregator;
+pub use averaging::{
+    get_averaging_settings, AveragingMethod, AveragingSettings, AveragingSource, AveragingWindow,
+};
+pub use command_source::CommandSource;
 pub use connector::{Connector, Datapoint};
-pub use oracle_core::{get_core_api_port, OracleCore};
+pub use definition::{get_connector_definition, ConnectorDefinition};
+pub use guard::{get_guard_settings, DatapointGuard, GuardRejection, GuardSettings};
+pub use http_source::HttpJsonSource;
+pub use oracle_core::{
+    get_core_api_port, get_core_tls_settings, get_oracle_core, get_post_api_credentials,
+    ConnectorError, OracleCore,
+};
+pub use policy::{get_fetch_policy, FallbackSource, FetchPolicy, PolicySource};
+pub use source::{DatapointSource, FnSource, Sample};
+pub use stream_source::{StreamSettings, StreamSource};
+pub use ui::{print_connector_info, BasicConnectorUi, ConnectorUi, FnConnectorUi};
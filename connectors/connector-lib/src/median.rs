@@ -0,0 +1,40 @@
+/// The median used to aggregate datapoints, both by Connectors (ie. of the
+/// sources of an `Aggregator` or of a streamed price window) and by the
+/// oracle core's `backtest` subcommand which simulates them.
+///
+/// This file is also compiled into the oracle core (see `src/backtest.rs`),
+/// thus it may only depend on `std`.
+use std::ops::{Add, Div, Rem};
+
+/// The median of the datapoints (the mean of the two middle datapoints if
+/// there is an even number of them). The datapoints are sorted in place.
+pub fn median_of<T>(datapoints: &mut [T]) -> Option<T>
+where
+    T: Copy + Ord + From<u8> + Add<Output = T> + Div<Output = T> + Rem<Output = T>,
+{
+    datapoints.sort();
+    let len = datapoints.len();
+    let two = T::from(2);
+    match len {
+        0 => None,
+        _ if len % 2 == 1 => Some(datapoints[len / 2]),
+        _ => {
+            // Halved separately so that the sum cannot overflow
+            let (a, b) = (datapoints[len / 2 - 1], datapoints[len / 2]);
+            Some(a / two + b / two + (a % two + b % two) / two)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_is_calculated() {
+        assert_eq!(median_of::<u64>(&mut []), None);
+        assert_eq!(median_of(&mut [3u64, 1, 2]), Some(2));
+        assert_eq!(median_of(&mut [1000i64, 1011]), Some(1005));
+        assert_eq!(median_of(&mut [u64::MAX, u64::MAX - 2]), Some(u64::MAX - 1));
+    }
+}
@@ -1,5 +1,8 @@
+use blake2b_simd::Params;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::from_str;
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use yaml_rust::YamlLoader;
 
@@ -7,28 +10,74 @@ pub type Result<T> = std::result::Result<T, ConnectorError>;
 
 #[derive(Error, Debug)]
 pub enum ConnectorError {
-    #[error("The configured oracle core is unreachable. Please ensure your config is correctly filled out and the core is running.")]
-    CoreUnreachable,
+    #[error("The oracle core at {url} is unreachable ({reason}). Please ensure your config is correctly filled out and the core is running.")]
+    CoreUnreachable { url: String, reason: String },
+    #[error("The request to the oracle core at {url} timed out after {seconds:.1} seconds.")]
+    CoreTimedOut { url: String, seconds: f64 },
+    #[error("The oracle core rejected the request to {url} with status {status}: {error}")]
+    CoreRejectedRequest {
+        url: String,
+        status: u16,
+        error: String,
+    },
     #[error("Failed reading response from core: {0}")]
     FailedParsingCoreResponse(String),
     #[error("Failed opening the local `oracle-config.yaml` file.")]
     FailedOpeningOracleConfigFile,
+    #[error("The `{key}` setting of the local `oracle-config.yaml` file is invalid: {reason}")]
+    InvalidConfigSetting { key: String, reason: String },
     #[error("Datapoint Error: {0}")]
     FailedSubmittingDatapoint(String),
+    #[error("Failed setting up TLS for the core connection: {0}")]
+    FailedSettingUpTls(String),
 }
 
+/// Returned by `submit_datapoint()` in place of a tx id when the core is
+/// dry running the `Commit Datapoint` action
+pub static DRY_RUN_TX_ID: &str = "Dry run (transaction not posted)";
+
+/// Default timeout of the requests to the Oracle Core
+static DEFAULT_CORE_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// The base struct for interfacing with the Oracle Core.
 /// All methods are implemented on this struct.
 pub struct OracleCore {
-    pub ip: String,
-    pub api_port: String,
-    pub post_api_port: String,
+    /// Base url of the GET API (ie. `http://127.0.0.1:9090`)
+    pub api_url: String,
+    /// Base url of the POST API (ie. `http://127.0.0.1:9091`)
+    pub post_api_url: String,
+    pub post_api_credentials: PostApiCredentials,
+    /// TLS settings if the Oracle Core APIs are served over TLS (set via
+    /// `with_tls()`, which also switches the urls to `https://`)
+    pub tls: Option<CoreTlsSettings>,
+    /// Maximum duration of a request (including connecting & reading the
+    /// response)
+    pub timeout: Duration,
+}
+
+/// TLS settings for connecting to an Oracle Core which serves its APIs over TLS
+#[derive(Debug, Clone)]
+pub struct CoreTlsSettings {
+    /// Path of a PEM encoded certificate to trust (ie. the core's self-signed certificate)
+    pub ca_cert_path: String,
+    /// Path of the PKCS#12 client identity presented to the POST API (if it verifies clients)
+    pub client_identity_path: Option<String>,
+    pub client_identity_password: String,
+}
+
+/// Credentials the POST API requests are sent with, matching the
+/// `post_api_key` & `post_api_signing_secret` of the Oracle Core config
+#[derive(Debug, Clone, Default)]
+pub struct PostApiCredentials {
+    pub api_key: Option<String>,
+    pub signing_secret: Option<String>,
 }
 
 /// Info about the local Oracle
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OracleInfo {
-    pub oracle_address: String,
+    /// The local oracle's address (`None` if the core runs in observer mode)
+    pub oracle_address: Option<String>,
 }
 
 /// Info about the Oracle Pool
@@ -46,7 +95,8 @@ pub struct PoolInfo {
     pub consensus_num: f64,
     pub minimum_pool_box_value: u64,
     pub oracle_pool_nft_id: String,
-    pub oracle_pool_participant_token_id: String,
+    /// `None` for observers without the participant token configured
+    pub oracle_pool_participant_token_id: Option<String>,
 }
 
 /// Info about the Node
@@ -72,140 +122,260 @@ pub struct PoolStatus {
     pub latest_datapoint: u64,
     pub current_epoch_id: String,
     pub epoch_ends: u64,
+    /// Average interval between the latest blocks in seconds (`None` if
+    /// unavailable, as are the estimated unix times below)
+    #[serde(default)]
+    pub average_block_interval: Option<f64>,
+    #[serde(default)]
+    pub epoch_ends_time: Option<u64>,
+    #[serde(default)]
+    pub next_epoch_starts_time: Option<u64>,
+    #[serde(default)]
+    pub next_collection_time: Option<u64>,
 }
 
 impl OracleCore {
-    /// Create a new `OracleCore` struct for use with your Connector
+    /// Create a new `OracleCore` struct for use with your Connector, which
+    /// connects to the GET API at `ip:api_port` & the POST API at
+    /// `ip:api_port + 1`
     pub fn new(ip: &str, api_port: &str) -> OracleCore {
         let post_api_port = ((api_port
             .parse::<u16>()
             .expect("Failed to parse oracle core port from config to u16."))
             + 1)
         .to_string();
+        OracleCore::from_urls(
+            &format!("http://{}:{}", ip, api_port),
+            &format!("http://{}:{}", ip, post_api_port),
+        )
+    }
+
+    /// Create a new `OracleCore` struct which connects to the GET API at
+    /// `api_url` & the POST API at `post_api_url`
+    pub fn from_urls(api_url: &str, post_api_url: &str) -> OracleCore {
         OracleCore {
-            ip: ip.to_string(),
-            api_port: api_port.to_string(),
-            post_api_port: post_api_port,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            post_api_url: post_api_url.trim_end_matches('/').to_string(),
+            post_api_credentials: PostApiCredentials::default(),
+            tls: None,
+            timeout: DEFAULT_CORE_REQUEST_TIMEOUT,
         }
     }
 
+    /// Send the POST API requests with `credentials`
+    pub fn with_post_api_credentials(mut self, credentials: PostApiCredentials) -> OracleCore {
+        self.post_api_credentials = credentials;
+        self
+    }
+
+    /// Connect over TLS, switching `http://` urls to `https://`
+    pub fn with_tls(mut self, tls: CoreTlsSettings) -> OracleCore {
+        for url in vec![&mut self.api_url, &mut self.post_api_url] {
+            if url.starts_with("http://") {
+                *url = url.replacen("http://", "https://", 1);
+            }
+        }
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Fail requests which take longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> OracleCore {
+        self.timeout = timeout;
+        self
+    }
+
     /// Returns the url of the Oracle Core GET API
     pub fn oracle_core_url(&self) -> String {
-        "http://".to_string() + &self.ip + ":" + &self.api_port
+        self.api_url.clone()
     }
 
     /// Returns the url of the Oracle Core POST API
     pub fn oracle_core_post_url(&self) -> String {
-        "http://".to_string() + &self.ip + ":" + &self.post_api_port
+        self.post_api_url.clone()
+    }
+
+    /// Builds the HTTP client used for requests to the Oracle Core
+    fn http_client(&self) -> Result<reqwest::blocking::Client> {
+        let builder = reqwest::blocking::Client::builder().timeout(self.timeout);
+        let tls = match &self.tls {
+            Some(tls) => tls,
+            None => {
+                return builder
+                    .build()
+                    .map_err(|e| ConnectorError::FailedSettingUpTls(e.to_string()))
+            }
+        };
+        let tls_error =
+            |e: &dyn std::fmt::Display| ConnectorError::FailedSettingUpTls(e.to_string());
+        let ca_cert = std::fs::read(&tls.ca_cert_path).map_err(|e| tls_error(&e))?;
+        let mut builder = builder.add_root_certificate(
+            reqwest::Certificate::from_pem(&ca_cert).map_err(|e| tls_error(&e))?,
+        );
+        if let Some(identity_path) = &tls.client_identity_path {
+            let identity = std::fs::read(identity_path).map_err(|e| tls_error(&e))?;
+            builder = builder.identity(
+                reqwest::Identity::from_pkcs12_der(&identity, &tls.client_identity_password)
+                    .map_err(|e| tls_error(&e))?,
+            );
+        }
+        builder.build().map_err(|e| tls_error(&e))
     }
 
     /// Submit a u64 Datapoint to the Oracle Core
     pub fn submit_datapoint(&self, datapoint: u64) -> Result<String> {
         let datapoint_json = object! { datapoint: datapoint};
-        let resp_text = self.send_post_req("/submitDatapoint", datapoint_json.dump())?;
-        // Add error checking here by parsing the json.
-        if let Ok(resp_json) = json::parse(&resp_text) {
-            let tx_id = resp_json["tx_id"].clone();
-
+        let resp_json = self.send_post_req("/submitDatapoint", datapoint_json.dump())?;
+        // If the core is dry running `Commit Datapoint` no tx is posted
+        if !resp_json["dry_run"].is_null() {
+            return Ok(DRY_RUN_TX_ID.to_string());
+        }
+        match resp_json["tx_id"].as_str() {
+            Some(tx_id) if !tx_id.is_empty() => Ok(tx_id.to_string()),
             // If there no tx_id/there is an error
-            if tx_id.is_empty() {
-                let error = resp_json["error"].clone();
-                return Err(ConnectorError::FailedSubmittingDatapoint(error.to_string()));
-            } else {
-                return Ok(tx_id.to_string());
-            }
-        } else {
-            return Err(ConnectorError::FailedParsingCoreResponse(resp_text));
+            _ => match error_message(&resp_json) {
+                Some(error) => Err(ConnectorError::FailedSubmittingDatapoint(error)),
+                None => Err(ConnectorError::FailedParsingCoreResponse(
+                    resp_json.to_string(),
+                )),
+            },
         }
     }
+
     /// Get information about the local Oracle
     pub fn oracle_info(&self) -> Result<OracleInfo> {
-        let resp_text = self.send_get_req("/oracleInfo")?;
-        from_str(&resp_text).map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
+        self.get("/oracleInfo")
     }
 
     /// Get information about the Oracle Pool
     pub fn pool_info(&self) -> Result<PoolInfo> {
-        let resp_text = self.send_get_req("/poolInfo")?;
-        from_str(&resp_text).map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
+        self.get("/poolInfo")
     }
 
     /// Get node info
     pub fn node_info(&self) -> Result<NodeInfo> {
-        let resp_text = self.send_get_req("/nodeInfo")?;
-        from_str(&resp_text).map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
+        self.get("/nodeInfo")
     }
 
     /// Get the current local Oracle Status
     pub fn oracle_status(&self) -> Result<OracleStatus> {
-        let resp_text = self.send_get_req("/oracleStatus")?;
-        from_str(&resp_text).map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
+        self.get("/oracleStatus")
     }
 
     /// Get the current Oracle Pool Status
     pub fn pool_status(&self) -> Result<PoolStatus> {
-        let resp_text = self.send_get_req("/poolStatus")?;
-        from_str(&resp_text).map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
+        self.get("/poolStatus")
     }
 
     /// Get the current block height
     pub fn current_block_height(&self) -> Result<u64> {
-        let resp_text = self.send_get_req("/blockHeight")?;
-        resp_text
-            .parse()
-            .map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
+        self.get("/blockHeight")
     }
 
-    /// Get the current block height
+    /// Get whether the core requires the Connector to repost a datapoint
     pub fn repost_check(&self) -> Result<bool> {
-        let resp_text = self.send_get_req("/requireDatapointRepost")?;
-        resp_text
-            .parse()
-            .map_err(|_| ConnectorError::FailedParsingCoreResponse(resp_text))
-    }
-
-    /// Sends a GET request to the Oracle Core and converts response to text with extra quotes removed
-    fn send_get_req(&self, endpoint: &str) -> Result<String> {
-        let url = self.oracle_core_url().to_owned() + endpoint;
-        let resp = reqwest::blocking::Client::new()
-            .get(&url)
-            .send()
-            .map_err(|_| ConnectorError::CoreUnreachable)?;
-        let text: String = resp
-            .text()
-            .map(|s| s.chars().filter(|&c| c != '\\').collect())
-            .map_err(|_| {
-                ConnectorError::FailedParsingCoreResponse("Send Get Request Failed.".to_string())
-            })?;
-
-        // Check if returned response has quotes around it which need to be removed
-        if &text[0..1] == "\"" {
-            // Remove quotes before returning
-            return Ok(text[1..(text.len() - 1)].to_string());
+        self.get("/requireDatapointRepost")
+    }
+
+    /// Sends a GET request to the Oracle Core and decodes the response into `T`
+    fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        let resp_json = self.send_get_req(endpoint)?;
+        serde_json::from_value(resp_json.clone()).map_err(|e| {
+            ConnectorError::FailedParsingCoreResponse(format!("{} ({})", resp_json, e))
+        })
+    }
+
+    /// Sends a GET request to the Oracle Core and decodes the JSON response
+    fn send_get_req(&self, endpoint: &str) -> Result<Value> {
+        let url = self.api_url.clone() + endpoint;
+        let req = self.http_client()?.get(&url);
+        self.send(req, &url)
+    }
+
+    /// Sends a POST request to the Oracle Core and decodes the JSON response
+    fn send_post_req(&self, endpoint: &str, body: String) -> Result<Value> {
+        let url = self.post_api_url.clone() + endpoint;
+        let mut req = self.http_client()?.post(&url);
+        if let Some(api_key) = &self.post_api_credentials.api_key {
+            req = req.header("X-Api-Key", api_key.as_str());
+        }
+        if let Some(secret) = &self.post_api_credentials.signing_secret {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+                .to_string();
+            let signature = sign_request(secret, &timestamp, body.as_bytes());
+            req = req
+                .header("X-Timestamp", timestamp)
+                .header("X-Signature", signature);
         }
-        Ok(text)
-    }
-
-    /// Sends a POST request to the Oracle Core and converts response to text
-    fn send_post_req(&self, endpoint: &str, body: String) -> Result<String> {
-        let url = self.oracle_core_post_url().to_owned() + endpoint;
-        let resp = reqwest::blocking::Client::new()
-            .post(&url)
-            .body(body)
-            .send()
-            .map_err(|_| ConnectorError::CoreUnreachable)?;
-        let text: String = resp
-            .text()
-            .map(|s| s.chars().filter(|&c| c != '\\').collect())
-            .map_err(|_| {
-                ConnectorError::FailedParsingCoreResponse("Send Post Request Failed.".to_string())
-            })?;
-        Ok(text[1..(text.len() - 1)].to_string())
+        self.send(req.body(body), &url)
+    }
+
+    /// Sends a request, failing if the core doesn't answer with a success
+    /// status, and decodes the JSON response
+    fn send(&self, req: reqwest::blocking::RequestBuilder, url: &str) -> Result<Value> {
+        let request_error = |e: reqwest::Error| {
+            if e.is_timeout() {
+                ConnectorError::CoreTimedOut {
+                    url: url.to_string(),
+                    seconds: self.timeout.as_secs_f64(),
+                }
+            } else {
+                ConnectorError::CoreUnreachable {
+                    url: url.to_string(),
+                    reason: e.to_string(),
+                }
+            }
+        };
+        let resp = req.send().map_err(request_error)?;
+        let status = resp.status();
+        let text = resp.text().map_err(request_error)?;
+        if !status.is_success() {
+            let error = decode_response(&text)
+                .ok()
+                .and_then(|json| error_message(&json))
+                .unwrap_or(text);
+            return Err(ConnectorError::CoreRejectedRequest {
+                url: url.to_string(),
+                status: status.as_u16(),
+                error: error,
+            });
+        }
+        decode_response(&text)
+    }
+}
+
+/// Decodes the body of a response of the Oracle Core. Most endpoints encode
+/// their JSON a second time as a JSON string, which is decoded as well, while
+/// plain text bodies (ie. the block height) are decoded as JSON values.
+fn decode_response(text: &str) -> Result<Value> {
+    let json: Value = serde_json::from_str(text.trim())
+        .map_err(|e| ConnectorError::FailedParsingCoreResponse(format!("{} ({})", text, e)))?;
+    match json {
+        Value::String(inner) => Ok(serde_json::from_str(&inner).unwrap_or(Value::String(inner))),
+        json => Ok(json),
+    }
+}
+
+/// The error of an error response, either `{"error": "..."}` or
+/// `{"error": {"code": ..., "message": "..."}}` (as returned by `/v1`)
+fn error_message(json: &Value) -> Option<String> {
+    match &json["error"] {
+        Value::Null => None,
+        Value::String(error) => Some(error.clone()),
+        error => Some(
+            error["message"]
+                .as_str()
+                .map(|m| m.to_string())
+                .unwrap_or(error.to_string()),
+        ),
     }
 }
 
 /// Reads the local `oracle-config.yaml` file
-fn get_config_yaml_string() -> Result<String> {
+pub(crate) fn get_config_yaml_string() -> Result<String> {
     std::fs::read_to_string("oracle-config.yaml")
         .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)
 }
@@ -222,6 +392,113 @@ pub fn get_core_api_port() -> Result<String> {
     }
 }
 
+/// Returns the POST API credentials ("post_api_key" & "post_api_signing_secret") from the local config file
+pub fn get_post_api_credentials() -> Result<PostApiCredentials> {
+    let config_string = get_config_yaml_string()?;
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    let optional_str = |key: &str| {
+        config[key]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+    };
+    Ok(PostApiCredentials {
+        api_key: optional_str("post_api_key"),
+        signing_secret: optional_str("post_api_signing_secret"),
+    })
+}
+
+/// Returns the TLS settings for connecting to the Oracle Core from the local
+/// config file, if the core serves its APIs over TLS ("api_tls_cert_path" is set)
+pub fn get_core_tls_settings() -> Result<Option<CoreTlsSettings>> {
+    let config_string = get_config_yaml_string()?;
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    let optional_str = |key: &str| {
+        config[key]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+    };
+    Ok(
+        optional_str("api_tls_cert_path").map(|cert_path| CoreTlsSettings {
+            ca_cert_path: cert_path,
+            client_identity_path: optional_str("post_api_client_identity_path"),
+            client_identity_password: optional_str("post_api_client_identity_password")
+                .unwrap_or_default(),
+        }),
+    )
+}
+
+/// Returns an `OracleCore` configured via the local config file: the GET &
+/// POST API urls ("core_api_url" & "core_post_api_url", defaulting to the
+/// local core at "core_api_port" & the port after it), the request timeout
+/// ("core_request_timeout" in seconds, defaults to 30), the POST API
+/// credentials & the TLS settings
+pub fn get_oracle_core() -> Result<OracleCore> {
+    let config_string = get_config_yaml_string()?;
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    let invalid = |key: &str, reason: &str| ConnectorError::InvalidConfigSetting {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    };
+    let url = |key: &str, port_offset: u16| -> Result<String> {
+        match config[key].as_str().filter(|s| !s.is_empty()) {
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(url.to_string())
+            }
+            Some(_) => Err(invalid(
+                key,
+                "The url must start with `http://` or `https://`.",
+            )),
+            None => {
+                let port = get_core_api_port()?
+                    .parse::<u16>()
+                    .ok()
+                    .and_then(|p| p.checked_add(port_offset))
+                    .ok_or(invalid(
+                        "core_api_port",
+                        "The port must be a number below 65535.",
+                    ))?;
+                Ok(format!("http://127.0.0.1:{}", port))
+            }
+        }
+    };
+    let mut oc = OracleCore::from_urls(&url("core_api_url", 0)?, &url("core_post_api_url", 1)?)
+        .with_post_api_credentials(get_post_api_credentials()?);
+    let timeout = config["core_request_timeout"]
+        .as_f64()
+        .or(config["core_request_timeout"].as_i64().map(|n| n as f64));
+    if let Some(timeout) = timeout {
+        if !(timeout > 0.0) {
+            return Err(invalid(
+                "core_request_timeout",
+                "The timeout must be positive.",
+            ));
+        }
+        oc = oc.with_timeout(Duration::from_secs_f64(timeout));
+    }
+    if let Some(tls) = get_core_tls_settings()? {
+        oc = oc.with_tls(tls);
+    }
+    Ok(oc)
+}
+
+/// Signs a POST API request the same way the Oracle Core verifies it: the
+/// hex encoded keyed Blake2b-256 hash of `<timestamp>.<body>`
+pub fn sign_request(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    Params::new()
+        .hash_length(32)
+        .key(secret.as_bytes())
+        .hash(&message)
+        .to_hex()
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -229,6 +506,35 @@ mod tests {
     static IP: &str = "0.0.0.0";
     static PORT: &str = "9090";
 
+    #[test]
+    fn responses_are_decoded() {
+        // Legacy endpoints encode their JSON as a JSON string
+        assert_eq!(
+            decode_response(r#""{\"tx_id\": \"abc\"}""#).unwrap()["tx_id"],
+            "abc"
+        );
+        assert_eq!(decode_response("288670\n").unwrap(), 288670u64);
+        assert_eq!(decode_response("false").unwrap(), false);
+        assert_eq!(decode_response(r#""Live Epoch""#).unwrap(), "Live Epoch");
+        assert!(decode_response("").is_err());
+        let error: Value =
+            serde_json::from_str(r#"{"error": {"code": "unauthorized", "message": "Bad key"}}"#)
+                .unwrap();
+        assert_eq!(error_message(&error), Some("Bad key".to_string()));
+        assert_eq!(error_message(&Value::Null), None);
+    }
+
+    #[test]
+    fn tls_switches_urls() {
+        let oc = OracleCore::new(IP, PORT).with_tls(CoreTlsSettings {
+            ca_cert_path: "core.crt".to_string(),
+            client_identity_path: None,
+            client_identity_password: "".to_string(),
+        });
+        assert_eq!(oc.oracle_core_url(), "https://0.0.0.0:9090");
+        assert_eq!(oc.oracle_core_post_url(), "https://0.0.0.0:9091");
+    }
+
     #[test]
     fn test_core_api_get() {
         if let Err(e) = get_core_api_port() {
@@ -0,0 +1,294 @@
+/// Timeouts, retries & fallbacks for fetching datapoints. Every attempt to
+/// fetch a datapoint is given a deadline (the fetch timeout) which the source
+/// bounds its requests by (ie. the request timeout of an `HttpJsonSource`),
+/// failed attempts are retried with exponential backoff, and retries are never
+/// scheduled past the deadline the datapoint is required by (ie. the end of
+/// the current Live Epoch).
+/// `PolicySource` applies a `FetchPolicy` to a single source, while
+/// `FallbackSource` tries a list of sources in order until one succeeds.
+use crate::connector::Datapoint;
+use crate::oracle_core::{get_config_yaml_string, ConnectorError, PoolInfo, PoolStatus};
+use crate::source::{DatapointSource, Sample};
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// How a datapoint is fetched from a source
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchPolicy {
+    /// Maximum duration of a single attempt (passed to the source as the
+    /// deadline of the attempt)
+    pub timeout: Duration,
+    /// Number of times a failed attempt is retried
+    pub max_retries: u32,
+    /// Wait before the first retry, which doubles with every further retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the wait between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> FetchPolicy {
+        FetchPolicy {
+            timeout: Duration::from_secs(10),
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl FetchPolicy {
+    /// Create a `FetchPolicy` from a `&Yaml` config, using the defaults for
+    /// the settings which are not set
+    pub fn new_from_yaml(config: &Yaml) -> FetchPolicy {
+        let default = FetchPolicy::default();
+        let seconds = |key: &str, default: Duration| {
+            config[key]
+                .as_f64()
+                .or(config[key].as_i64().map(|s| s as f64))
+                .map(Duration::from_secs_f64)
+                .unwrap_or(default)
+        };
+        FetchPolicy {
+            timeout: seconds("datapoint_fetch_timeout", default.timeout),
+            max_retries: config["datapoint_fetch_retries"]
+                .as_i64()
+                .map(|r| r as u32)
+                .unwrap_or(default.max_retries),
+            initial_backoff: seconds("datapoint_fetch_backoff", default.initial_backoff),
+            max_backoff: seconds("datapoint_fetch_max_backoff", default.max_backoff),
+        }
+    }
+
+    /// The wait before retry number `retry` (starting at 0)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        exponential_backoff(self.initial_backoff, self.max_backoff, retry)
+    }
+}
+
+/// The wait before retry number `retry` (starting at 0), doubling `initial`
+/// with every retry up to `max`
+pub(crate) fn exponential_backoff(initial: Duration, max: Duration, retry: u32) -> Duration {
+    let factor = 2u32.saturating_pow(retry.min(16));
+    initial.checked_mul(factor).unwrap_or(max).min(max)
+}
+
+/// Returns the `FetchPolicy` ("datapoint_fetch_timeout",
+/// "datapoint_fetch_retries", "datapoint_fetch_backoff" &
+/// "datapoint_fetch_max_backoff", in seconds) from the local config file
+pub fn get_fetch_policy() -> crate::oracle_core::Result<FetchPolicy> {
+    let config_string = get_config_yaml_string()?;
+    let config = &YamlLoader::load_from_str(&config_string)
+        .map_err(|_| ConnectorError::FailedOpeningOracleConfigFile)?[0];
+    Ok(FetchPolicy::new_from_yaml(config))
+}
+
+/// Fetches a datapoint from `source` following `policy`. Retries are only
+/// attempted if they can start before the `deadline` (if provided).
+pub fn fetch_with_policy(
+    source: &Arc<dyn DatapointSource>,
+    policy: &FetchPolicy,
+    deadline: Option<Instant>,
+) -> Result<Datapoint> {
+    let mut retry = 0;
+    loop {
+        let attempt_deadline = Instant::now() + policy.timeout;
+        let attempt_deadline = match deadline {
+            Some(d) => attempt_deadline.min(d),
+            None => attempt_deadline,
+        };
+        let error = match source.get_datapoint_before(attempt_deadline) {
+            Ok(datapoint) => return Ok(datapoint),
+            Err(e) => e,
+        };
+        let backoff = policy.backoff(retry);
+        let retry_allowed = retry < policy.max_retries
+            && deadline
+                .map(|d| Instant::now() + backoff < d)
+                .unwrap_or(true);
+        if !retry_allowed {
+            return Err(error);
+        }
+        thread::sleep(backoff);
+        retry += 1;
+    }
+}
+
+/// A `DatapointSource` which applies a `FetchPolicy` to another source
+pub struct PolicySource {
+    source: Arc<dyn DatapointSource>,
+    policy: FetchPolicy,
+}
+
+impl PolicySource {
+    pub fn new(source: impl DatapointSource + 'static, policy: FetchPolicy) -> PolicySource {
+        PolicySource {
+            source: Arc::new(source),
+            policy: policy,
+        }
+    }
+}
+
+impl DatapointSource for PolicySource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        fetch_with_policy(&self.source, &self.policy, None)
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        fetch_with_policy(&self.source, &self.policy, Some(deadline))
+    }
+
+    fn get_sample(&self) -> Result<Sample> {
+        self.source.get_sample()
+    }
+
+    fn name(&self) -> String {
+        self.source.name()
+    }
+
+    fn pool_updated(&self, pool_info: &PoolInfo, pool_status: &PoolStatus) {
+        self.source.pool_updated(pool_info, pool_status)
+    }
+
+    fn info(&self) -> Vec<String> {
+        self.source.info()
+    }
+}
+
+/// A `DatapointSource` which tries its sources in order, returning the
+/// datapoint of the first source which succeeds
+pub struct FallbackSource {
+    name: String,
+    sources: Vec<Arc<dyn DatapointSource>>,
+}
+
+impl FallbackSource {
+    pub fn new(name: &str) -> FallbackSource {
+        FallbackSource {
+            name: name.to_string(),
+            sources: vec![],
+        }
+    }
+
+    /// Add a source, which is tried if all previously added sources failed
+    pub fn with_source(mut self, source: impl DatapointSource + 'static) -> FallbackSource {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    fn fetch(&self, deadline: Option<Instant>) -> Result<Sample> {
+        let mut errors = vec![];
+        for source in &self.sources {
+            if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+                errors.push(format!("{}: Deadline passed.", source.name()));
+                break;
+            }
+            let result = match deadline {
+                Some(d) => source.get_datapoint_before(d).map(|datapoint| Sample {
+                    datapoint: datapoint,
+                    volume: None,
+                }),
+                None => source.get_sample(),
+            };
+            match result {
+                Ok(sample) => return Ok(sample),
+                Err(e) => errors.push(format!("{}: {}", source.name(), e)),
+            }
+        }
+        Err(anyhow!(
+            "All sources of {} failed. {}",
+            self.name,
+            errors.join(" ")
+        ))
+    }
+}
+
+impl DatapointSource for FallbackSource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        Ok(self.fetch(None)?.datapoint)
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        Ok(self.fetch(Some(deadline))?.datapoint)
+    }
+
+    fn get_sample(&self) -> Result<Sample> {
+        self.fetch(None)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn pool_updated(&self, pool_info: &PoolInfo, pool_status: &PoolStatus) {
+        for source in &self.sources {
+            source.pool_updated(pool_info, pool_status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::FnSource;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the given number of times before succeeding
+    struct FlakySource {
+        failures: u32,
+        attempts: AtomicU32,
+    }
+
+    impl DatapointSource for FlakySource {
+        fn get_datapoint(&self) -> Result<Datapoint> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(anyhow!("Offline"))
+            } else {
+                Ok(1000)
+            }
+        }
+    }
+
+    fn policy() -> FetchPolicy {
+        FetchPolicy {
+            timeout: Duration::from_millis(500),
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(15),
+        }
+    }
+
+    #[test]
+    fn failed_fetches_are_retried() {
+        assert_eq!(policy().backoff(0), Duration::from_millis(10));
+        assert_eq!(policy().backoff(3), Duration::from_millis(15));
+        let long_backoff = FetchPolicy {
+            initial_backoff: Duration::from_secs(u64::MAX / 2),
+            ..policy()
+        };
+        assert_eq!(long_backoff.backoff(4), Duration::from_millis(15));
+        let flaky = |failures| -> Arc<dyn DatapointSource> {
+            Arc::new(FlakySource {
+                failures: failures,
+                attempts: AtomicU32::new(0),
+            })
+        };
+        assert_eq!(fetch_with_policy(&flaky(2), &policy(), None).unwrap(), 1000);
+        assert!(fetch_with_policy(&flaky(3), &policy(), None).is_err());
+        // No retry fits before the deadline
+        let deadline = Instant::now() + Duration::from_millis(5);
+        assert!(fetch_with_policy(&flaky(1), &policy(), Some(deadline)).is_err());
+    }
+
+    #[test]
+    fn fallback_sources_are_tried_in_order() {
+        let source = FallbackSource::new("Test")
+            .with_source(FnSource(|| Err(anyhow!("Offline"))))
+            .with_source(FnSource(|| Ok(2000)))
+            .with_source(FnSource(|| Ok(3000)));
+        assert_eq!(source.get_datapoint().unwrap(), 2000);
+    }
+}
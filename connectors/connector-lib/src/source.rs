@@ -0,0 +1,115 @@
+/// Datapoint sources which Connectors fetch their datapoints from. Connectors
+/// implement `DatapointSource` on their own structs, which can hold any state
+/// they require (configuration, HTTP clients, caches, ...), while plain
+/// functions are supported via the `FnSource` adapter.
+use crate::connector::Datapoint;
+use crate::oracle_core::{PoolInfo, PoolStatus};
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A datapoint along with the trading volume it is based on (if the source
+/// reports one)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub datapoint: Datapoint,
+    pub volume: Option<f64>,
+}
+
+/// A source which fetches & processes the datapoint of a Connector
+pub trait DatapointSource: Send + Sync {
+    /// Fetch the current datapoint
+    fn get_datapoint(&self) -> Result<Datapoint>;
+
+    /// Fetch the current datapoint, which is required by `deadline` (sources
+    /// which retry or wait on other sources should give up by then)
+    fn get_datapoint_before(&self, _deadline: Instant) -> Result<Datapoint> {
+        self.get_datapoint()
+    }
+
+    /// Fetch the current datapoint along with its volume (sources which
+    /// report volumes override this)
+    fn get_sample(&self) -> Result<Sample> {
+        Ok(Sample {
+            datapoint: self.get_datapoint()?,
+            volume: None,
+        })
+    }
+
+    /// A name which identifies the source (ie. in logs & diagnostics)
+    fn name(&self) -> String {
+        "Datapoint Source".to_string()
+    }
+
+    /// Informs the source of the current state of the pool (ie. for sources
+    /// which align to the epochs of the pool)
+    fn pool_updated(&self, _pool_info: &PoolInfo, _pool_status: &PoolStatus) {}
+
+    /// Lines describing the state of the source (ie. its settings), which
+    /// are printed in the status output of the Connector
+    fn info(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Adapts a plain `fn() -> Result<Datapoint>` into a `DatapointSource`
+#[derive(Clone, Copy)]
+pub struct FnSource(pub fn() -> Result<Datapoint>);
+
+impl DatapointSource for FnSource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        (self.0)()
+    }
+}
+
+impl<S: DatapointSource + ?Sized> DatapointSource for Box<S> {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        (**self).get_datapoint()
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        (**self).get_datapoint_before(deadline)
+    }
+
+    fn get_sample(&self) -> Result<Sample> {
+        (**self).get_sample()
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn pool_updated(&self, pool_info: &PoolInfo, pool_status: &PoolStatus) {
+        (**self).pool_updated(pool_info, pool_status)
+    }
+
+    fn info(&self) -> Vec<String> {
+        (**self).info()
+    }
+}
+
+impl<S: DatapointSource + ?Sized> DatapointSource for Arc<S> {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        (**self).get_datapoint()
+    }
+
+    fn get_datapoint_before(&self, deadline: Instant) -> Result<Datapoint> {
+        (**self).get_datapoint_before(deadline)
+    }
+
+    fn get_sample(&self) -> Result<Sample> {
+        (**self).get_sample()
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn pool_updated(&self, pool_info: &PoolInfo, pool_status: &PoolStatus) {
+        (**self).pool_updated(pool_info, pool_status)
+    }
+
+    fn info(&self) -> Vec<String> {
+        (**self).info()
+    }
+}
@@ -0,0 +1,446 @@
+/// A `DatapointSource` which streams prices from an exchange over WebSocket
+/// instead of polling a REST API. A background thread keeps a subscription
+/// to the ticker/trade stream open (sending the configured subscription
+/// messages after connecting), reconnects with exponential backoff whenever
+/// the connection fails or goes idle, and keeps the prices of the latest
+/// window in memory. `get_datapoint` answers instantly with the median of the
+/// window, failing if no price was received within it.
+use crate::connector::Datapoint;
+use crate::definition::DefinitionError;
+use crate::http_source::{datapoint_to_price, price_at, price_to_datapoint};
+use crate::median::median_of;
+use crate::policy::exponential_backoff;
+use crate::source::DatapointSource;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::client::AutoStream;
+use tungstenite::stream::Stream;
+use tungstenite::{Message, WebSocket};
+use yaml_rust::Yaml;
+
+/// How a price stream is subscribed to & processed
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamSettings {
+    /// The WebSocket url (`ws://` or `wss://`)
+    pub url: String,
+    /// Messages sent after connecting to subscribe to the stream
+    pub subscriptions: Vec<String>,
+    /// JSON pointer to the price within the messages (messages without a
+    /// price, ie. subscription acknowledgements, are ignored)
+    pub pointer: String,
+    /// Whether the price is inverted (`1 / price`) before it is scaled
+    pub invert: bool,
+    /// Factor converting the (inverted) price into the on-chain units
+    pub scale: f64,
+    /// Duration of the rolling window of prices the datapoint is based on
+    pub window: Duration,
+    /// The connection is considered dead if no message arrives for this long
+    pub idle_timeout: Duration,
+    /// Wait before the first reconnection attempt, which doubles with every
+    /// further failed attempt up to the `max_backoff`
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl StreamSettings {
+    /// Create `StreamSettings` for reading the price at `pointer` of the
+    /// messages streamed from `url`, with a window of 60 seconds
+    pub fn new(url: &str, pointer: &str) -> StreamSettings {
+        StreamSettings {
+            url: url.to_string(),
+            subscriptions: vec![],
+            pointer: pointer.to_string(),
+            invert: false,
+            scale: 1.0,
+            window: Duration::from_secs(60),
+            idle_timeout: Duration::from_secs(60),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Add a message which is sent after connecting
+    pub fn with_subscription(mut self, message: &str) -> StreamSettings {
+        self.subscriptions.push(message.to_string());
+        self
+    }
+
+    /// Invert the price before it is scaled
+    pub fn inverted(mut self) -> StreamSettings {
+        self.invert = true;
+        self
+    }
+
+    /// Scale the (inverted) price by `scale` to convert it into on-chain units
+    pub fn scaled(mut self, scale: f64) -> StreamSettings {
+        self.scale = scale;
+        self
+    }
+
+    /// Base the datapoint on the prices received within the `window`
+    pub fn with_window(mut self, window: Duration) -> StreamSettings {
+        self.window = window;
+        self
+    }
+
+    /// Create `StreamSettings` from a `&Yaml` source definition with the
+    /// fields `stream` (the url), `pointer` and optionally `subscribe` (a
+    /// message or a list of messages), `invert`, `scale`, `window` &
+    /// `idle_timeout` (in seconds)
+    pub fn new_from_yaml(
+        definition: &Yaml,
+    ) -> std::result::Result<StreamSettings, DefinitionError> {
+        let string = |field: &str| {
+            definition[field]
+                .as_str()
+                .ok_or(DefinitionError::MissingField(field.to_string()))
+        };
+        let number = |field: &str| {
+            definition[field]
+                .as_f64()
+                .or(definition[field].as_i64().map(|n| n as f64))
+        };
+        let invalid = |field: &str, reason: &str| DefinitionError::InvalidField {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut settings = StreamSettings::new(string("stream")?, string("pointer")?);
+        settings.subscriptions = match &definition["subscribe"] {
+            Yaml::String(message) => vec![message.clone()],
+            Yaml::Array(messages) => messages
+                .iter()
+                .map(|m| m.as_str().map(|s| s.to_string()))
+                .collect::<Option<Vec<String>>>()
+                .ok_or(invalid(
+                    "subscribe",
+                    "Subscription messages must be strings.",
+                ))?,
+            _ => vec![],
+        };
+        settings.invert = definition["invert"].as_bool().unwrap_or(false);
+        settings.scale = number("scale").unwrap_or(1.0);
+        if !(settings.scale > 0.0) {
+            return Err(invalid("scale", "The scale must be positive."));
+        }
+        for (field, duration) in vec![
+            ("window", &mut settings.window),
+            ("idle_timeout", &mut settings.idle_timeout),
+        ] {
+            match number(field) {
+                Some(seconds) if seconds > 0.0 => *duration = Duration::from_secs_f64(seconds),
+                Some(_) => return Err(invalid(field, "The duration must be positive.")),
+                None => (),
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Converts the datapoint back into the streamed price
+    pub fn price_of(&self, datapoint: Datapoint) -> f64 {
+        datapoint_to_price(datapoint, self.invert, self.scale)
+    }
+
+    /// Reads the price from a message and converts it into a datapoint
+    fn datapoint_from_message(&self, message: &str) -> Option<Datapoint> {
+        let json: Value = serde_json::from_str(message).ok()?;
+        let price = price_at(&json, &self.pointer)?;
+        price_to_datapoint(price, self.invert, self.scale).ok()
+    }
+
+    /// The wait before reconnection attempt number `retry` (starting at 0)
+    fn backoff(&self, retry: u32) -> Duration {
+        exponential_backoff(self.initial_backoff, self.max_backoff, retry)
+    }
+}
+
+/// The state shared between a `StreamSource` and its stream thread
+#[derive(Debug, Default)]
+struct StreamState {
+    /// The datapoints received within the window (oldest first)
+    window: VecDeque<(Instant, Datapoint)>,
+    connected: bool,
+    reconnects: u32,
+    last_error: Option<String>,
+}
+
+/// The state of the stream of a `StreamSource` (ie. for diagnostics)
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamStatus {
+    pub connected: bool,
+    /// Number of times the stream reconnected
+    pub reconnects: u32,
+    /// The error which closed the latest connection
+    pub last_error: Option<String>,
+    /// Number of prices within the current window
+    pub samples: usize,
+}
+
+/// Streams datapoints over WebSocket. Clones share the same stream, which is
+/// closed once every clone is dropped.
+#[derive(Clone)]
+pub struct StreamSource {
+    name: String,
+    settings: StreamSettings,
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl StreamSource {
+    /// Create a `StreamSource` following the `settings`, which only streams
+    /// prices once `start_streaming` is called
+    pub fn new(name: &str, settings: StreamSettings) -> StreamSource {
+        StreamSource {
+            name: name.to_string(),
+            settings: settings,
+            state: Arc::new(Mutex::new(StreamState::default())),
+        }
+    }
+
+    /// Start streaming prices following the `settings`
+    pub fn start(name: &str, settings: StreamSettings) -> StreamSource {
+        let source = StreamSource::new(name, settings);
+        source.start_streaming();
+        source
+    }
+
+    /// Start the stream thread (once per stream, which clones share)
+    pub fn start_streaming(&self) {
+        let weak_state = Arc::downgrade(&self.state);
+        let thread_settings = self.settings.clone();
+        thread::Builder::new()
+            .name(format!("{} Stream Thread", self.name))
+            .spawn(move || run_stream(thread_settings, weak_state))
+            .ok();
+    }
+
+    /// The settings the stream was started with
+    pub fn settings(&self) -> &StreamSettings {
+        &self.settings
+    }
+
+    /// The state of the stream
+    pub fn status(&self) -> StreamStatus {
+        let mut state = self.state.lock().unwrap();
+        prune_window(&mut state.window, self.settings.window, Instant::now());
+        StreamStatus {
+            connected: state.connected,
+            reconnects: state.reconnects,
+            last_error: state.last_error.clone(),
+            samples: state.window.len(),
+        }
+    }
+}
+
+impl DatapointSource for StreamSource {
+    fn get_datapoint(&self) -> Result<Datapoint> {
+        let mut state = self.state.lock().unwrap();
+        prune_window(&mut state.window, self.settings.window, Instant::now());
+        let mut datapoints: Vec<Datapoint> = state.window.iter().map(|(_, d)| *d).collect();
+        median_of(&mut datapoints).ok_or_else(|| {
+            anyhow!(
+                "No price was streamed within the last {} seconds{}.",
+                self.settings.window.as_secs(),
+                state
+                    .last_error
+                    .as_ref()
+                    .map(|e| format!(" (latest error: {})", e))
+                    .unwrap_or_default()
+            )
+        })
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Removes the datapoints which are older than the `window`
+fn prune_window(window: &mut VecDeque<(Instant, Datapoint)>, duration: Duration, now: Instant) {
+    while let Some((received, _)) = window.front() {
+        if now.saturating_duration_since(*received) <= duration {
+            break;
+        }
+        window.pop_front();
+    }
+}
+
+/// Keeps the stream connected until the `StreamSource` is dropped
+fn run_stream(settings: StreamSettings, state: Weak<Mutex<StreamState>>) {
+    let mut retry = 0;
+    loop {
+        let error = match stream_prices(&settings, &state, &mut retry) {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        match state.upgrade() {
+            Some(state) => {
+                let mut state = state.lock().unwrap();
+                state.connected = false;
+                state.last_error = Some(error.to_string());
+            }
+            None => return,
+        }
+        thread::sleep(settings.backoff(retry));
+        retry += 1;
+    }
+}
+
+/// Connects to the stream and records the streamed datapoints until the
+/// connection fails (`Err`) or the `StreamSource` is dropped (`Ok`)
+fn stream_prices(
+    settings: &StreamSettings,
+    state: &Weak<Mutex<StreamState>>,
+    retry: &mut u32,
+) -> Result<()> {
+    let (mut socket, _) = tungstenite::connect(settings.url.as_str())?;
+    set_read_timeout(&socket, settings.idle_timeout)?;
+    for message in &settings.subscriptions {
+        socket.write_message(Message::Text(message.clone()))?;
+    }
+    match state.upgrade() {
+        Some(state) => {
+            let mut state = state.lock().unwrap();
+            if *retry > 0 || state.last_error.is_some() {
+                state.reconnects += 1;
+            }
+            state.connected = true;
+        }
+        None => return Ok(()),
+    }
+    *retry = 0;
+    loop {
+        let message = socket.read_message()?;
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => {
+                socket.close(None).ok();
+                return Ok(());
+            }
+        };
+        match message {
+            Message::Text(text) => {
+                if let Some(datapoint) = settings.datapoint_from_message(&text) {
+                    let now = Instant::now();
+                    let mut state = state.lock().unwrap();
+                    state.window.push_back((now, datapoint));
+                    prune_window(&mut state.window, settings.window, now);
+                }
+            }
+            Message::Close(_) => return Err(anyhow!("The stream was closed by the server.")),
+            _ => (),
+        }
+    }
+}
+
+/// Sets the read timeout of the socket, so that an idle connection is detected
+fn set_read_timeout(socket: &WebSocket<AutoStream>, timeout: Duration) -> Result<()> {
+    let stream: &TcpStream = match socket.get_ref() {
+        Stream::Plain(stream) => stream,
+        Stream::Tls(stream) => stream.get_ref(),
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Sender};
+
+    /// A local stand-in for an exchange's WebSocket server. Every connection
+    /// reports its first (subscription) message and is sent the `prices` of
+    /// the connection in turn, after which it is closed.
+    fn stand_in_server(
+        connections: Vec<Vec<&'static str>>,
+        subscriptions: Sender<String>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for prices in connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut socket = match tungstenite::accept(stream) {
+                    Ok(socket) => socket,
+                    Err(_) => continue,
+                };
+                if let Ok(Message::Text(subscription)) = socket.read_message() {
+                    subscriptions.send(subscription).ok();
+                }
+                for price in prices {
+                    socket
+                        .write_message(Message::Text(format!(
+                            "{{\"data\": {{\"price\": \"{}\"}}}}",
+                            price
+                        )))
+                        .unwrap();
+                }
+                thread::sleep(Duration::from_millis(100));
+                socket.close(None).ok();
+                while socket.read_message().is_ok() {}
+            }
+        });
+        url
+    }
+
+    /// Waits until the source provides the `expected` datapoint
+    fn wait_for(source: &StreamSource, expected: Datapoint) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            if source.get_datapoint().ok() == Some(expected) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn prices_are_streamed_and_reconnected() {
+        let (sender, subscriptions) = channel();
+        let url = stand_in_server(vec![vec!["0.5", "0.25", "0.2"], vec!["0.1"]], sender);
+        let mut settings = StreamSettings::new(&url, "/data/price")
+            .with_subscription("{\"subscribe\": \"ERG-USDT\"}")
+            .inverted()
+            .scaled(1000.0);
+        settings.initial_backoff = Duration::from_millis(10);
+        let source = StreamSource::start("Test Stream", settings);
+        // The median of 2000, 4000 & 5000
+        assert!(wait_for(&source, 4000));
+        // After the server closed the first connection, the stream reconnects
+        // and resubscribes, and the datapoints of the new connection are added
+        assert!(wait_for(&source, 4500));
+        assert_eq!(
+            subscriptions.recv().unwrap(),
+            "{\"subscribe\": \"ERG-USDT\"}"
+        );
+        assert_eq!(
+            subscriptions.recv().unwrap(),
+            "{\"subscribe\": \"ERG-USDT\"}"
+        );
+        assert_eq!(source.status().reconnects, 1);
+    }
+
+    #[test]
+    fn stale_windows_are_errors() {
+        let mut window = VecDeque::new();
+        let now = Instant::now();
+        window.push_back((now, 1000));
+        window.push_back((now + Duration::from_secs(30), 2000));
+        prune_window(
+            &mut window,
+            Duration::from_secs(60),
+            now + Duration::from_secs(61),
+        );
+        assert_eq!(window.len(), 1);
+        let source = StreamSource::start(
+            "Unreachable",
+            StreamSettings::new("ws://127.0.0.1:1", "/price"),
+        );
+        assert!(source.get_datapoint().is_err());
+    }
+}
@@ -0,0 +1,35 @@
+/// The TLS settings of HTTP servers built on top of Connectors (ie. the
+/// frontend connector API), which are served behind a `TlsProxy`.
+pub use crate::tls_proxy::{InternalConnection, InternalListener, TlsProxy};
+use anyhow::{anyhow, Result};
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use yaml_rust::YamlLoader;
+
+/// Returns the certificate & key paths ("api_tls_cert_path" &
+/// "api_tls_key_path") from the local config file if TLS is enabled
+pub fn get_api_tls_paths() -> Result<Option<(String, String)>> {
+    let config_string = std::fs::read_to_string("oracle-config.yaml")?;
+    let config = &YamlLoader::load_from_str(&config_string)?[0];
+    let cert_path = config["api_tls_cert_path"]
+        .as_str()
+        .filter(|s| !s.is_empty());
+    let key_path = config["api_tls_key_path"]
+        .as_str()
+        .filter(|s| !s.is_empty());
+    match (cert_path, key_path) {
+        (Some(cert), Some(key)) => Ok(Some((cert.to_string(), key.to_string()))),
+        (None, None) => Ok(None),
+        _ => Err(anyhow!(
+            "Both `api_tls_cert_path` and `api_tls_key_path` must be set to enable TLS."
+        )),
+    }
+}
+
+/// Create a `TlsProxy` presenting the given PEM certificate chain & key
+pub fn new_tls_proxy(cert_path: &str, key_path: &str) -> Result<TlsProxy> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder.set_certificate_chain_file(cert_path)?;
+    builder.set_private_key_file(key_path, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+    TlsProxy::new(builder.build())
+}
@@ -0,0 +1,297 @@
+/// In-process TLS termination for the plain HTTP servers of the oracle core
+/// and of Connectors (ie. the frontend connector API). Connections to the
+/// public address are decrypted by a `TlsProxy` and forwarded to the HTTP
+/// server on a local-only port. As any local process can connect to that
+/// port, the HTTP server must reject every connection which the proxy did
+/// not forward (see `TlsProxy::internal_connection`).
+///
+/// This file is also compiled into the oracle core (see `src/tls.rs`), thus
+/// it may only depend on `std`, `openssl` and `anyhow`.
+use anyhow::{anyhow, Result};
+use openssl::rand::rand_bytes;
+use openssl::ssl::{ErrorCode, SslAcceptor, SslStream};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The number of connections which are forwarded at the same time. Further
+/// connections wait until one of them is closed.
+const MAX_CONNECTIONS: usize = 32;
+/// Forwarded connections without any traffic for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the TLS handshake of a new connection may take
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the proxy waits for the HTTP server to start
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A local-only address reserved for the HTTP server behind a `TlsProxy`.
+/// The port stays bound until the server takes it over.
+pub struct InternalListener {
+    listener: TcpListener,
+    address: String,
+}
+
+impl InternalListener {
+    /// Binds a free local-only port
+    pub fn reserve() -> Result<InternalListener> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        Ok(InternalListener {
+            listener: listener,
+            address: address,
+        })
+    }
+
+    /// The reserved address
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Hands the bound listener over to a server built on `std::net`
+    pub fn into_listener(self) -> TcpListener {
+        self.listener
+    }
+
+    /// Releases the port for a server which can only bind by address. The
+    /// `TlsProxy` does not forward any connection until it verified that
+    /// the server which took the port over is its own.
+    pub fn release(self) -> String {
+        self.address
+    }
+}
+
+/// How the HTTP server behind a `TlsProxy` must treat a connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum InternalConnection {
+    /// A client connection forwarded by the proxy from the given address
+    Forwarded(IpAddr),
+    /// The proxy verifying the server, which must respond with the given token
+    Probe(String),
+    /// A connection which did not come through the proxy
+    Rejected,
+}
+
+/// Terminates the TLS connections of one HTTP server
+pub struct TlsProxy {
+    acceptor: SslAcceptor,
+    /// The client address of each forwarded connection, by the local port of
+    /// the connection to the HTTP server
+    clients: Mutex<HashMap<u16, IpAddr>>,
+    /// The local port of the proxy's connection verifying the HTTP server
+    probe_port: Mutex<Option<u16>>,
+    probe_token: String,
+}
+
+impl TlsProxy {
+    /// Create a `TlsProxy` which accepts connections with the given acceptor
+    pub fn new(acceptor: SslAcceptor) -> Result<TlsProxy> {
+        let mut token = [0u8; 16];
+        rand_bytes(&mut token)?;
+        Ok(TlsProxy {
+            acceptor: acceptor,
+            clients: Mutex::new(HashMap::new()),
+            probe_port: Mutex::new(None),
+            probe_token: token.iter().map(|b| format!("{:02x}", b)).collect(),
+        })
+    }
+
+    /// Returns how the HTTP server must treat a connection from `peer`
+    pub fn internal_connection(&self, peer: SocketAddr) -> InternalConnection {
+        if !peer.ip().is_loopback() {
+            return InternalConnection::Rejected;
+        }
+        if *self.probe_port.lock().unwrap() == Some(peer.port()) {
+            return InternalConnection::Probe(self.probe_token.clone());
+        }
+        match self.clients.lock().unwrap().get(&peer.port()) {
+            Some(ip) => InternalConnection::Forwarded(*ip),
+            None => InternalConnection::Rejected,
+        }
+    }
+
+    /// Accepts TLS connections on `address` and forwards them to the HTTP
+    /// server at `internal_address` (in new threads). Connections are only
+    /// accepted once the server responded to the proxy's probe.
+    pub fn spawn(self: Arc<Self>, address: &str, internal_address: &str) -> Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let internal_address = internal_address.to_string();
+        let (sender, receiver) = sync_channel::<TcpStream>(MAX_CONNECTIONS);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..MAX_CONNECTIONS {
+            let (proxy, receiver) = (self.clone(), receiver.clone());
+            let internal_address = internal_address.clone();
+            thread::Builder::new()
+                .name(format!("TLS Worker {} #{}", address, i))
+                .spawn(move || proxy.run_worker(&receiver, &internal_address))?;
+        }
+        thread::Builder::new()
+            .name(format!("TLS Listener {}", address))
+            .spawn(move || {
+                self.probe(&internal_address)
+                    .expect("The TLS listener failed to verify its HTTP server.");
+                for stream in listener.incoming().flatten() {
+                    if sender.send(stream).is_err() {
+                        return;
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Forwards the connections received from the listener one at a time
+    fn run_worker(&self, receiver: &Mutex<Receiver<TcpStream>>, internal_address: &str) {
+        loop {
+            let stream = match receiver.lock().unwrap().recv() {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            self.forward(stream, internal_address).ok();
+        }
+    }
+
+    /// Checks that the HTTP server at `internal_address` is the one this
+    /// proxy forwards to, by requesting the probe token from it
+    fn probe(&self, internal_address: &str) -> Result<()> {
+        let started = Instant::now();
+        loop {
+            match self.probe_once(internal_address) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {
+                    return Err(anyhow!(
+                        "The server at {} did not respond with the probe token.",
+                        internal_address
+                    ))
+                }
+                Err(e) if started.elapsed() > STARTUP_TIMEOUT => return Err(e),
+                // The server may still be starting
+                Err(_) => thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    fn probe_once(&self, internal_address: &str) -> Result<bool> {
+        let mut backend = TcpStream::connect(internal_address)?;
+        backend.set_read_timeout(Some(STARTUP_TIMEOUT))?;
+        *self.probe_port.lock().unwrap() = Some(backend.local_addr()?.port());
+        let res = read_probe_response(&mut backend, &self.probe_token);
+        *self.probe_port.lock().unwrap() = None;
+        res
+    }
+
+    /// Performs the TLS handshake of a connection and forwards it
+    fn forward(&self, stream: TcpStream, internal_address: &str) -> Result<()> {
+        let peer = stream.peer_addr()?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let tls_stream = self
+            .acceptor
+            .accept(stream)
+            .map_err(|e| anyhow!("TLS handshake with {} failed: {}", peer, e))?;
+        let backend = TcpStream::connect(internal_address)?;
+        let local_port = backend.local_addr()?.port();
+        self.clients.lock().unwrap().insert(local_port, peer.ip());
+        let res = pipe(tls_stream, backend);
+        self.clients.lock().unwrap().remove(&local_port);
+        res
+    }
+}
+
+/// Sends the probe request and reads the response until it holds the token
+fn read_probe_response(backend: &mut TcpStream, token: &str) -> Result<bool> {
+    backend.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+    let mut response = vec![];
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = backend.read(&mut buf)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        response.extend_from_slice(&buf[..n]);
+        if String::from_utf8_lossy(&response).contains(token) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Copies data both ways between a TLS connection and the HTTP server until
+/// either side closes or the connection is idle for `IDLE_TIMEOUT`. Data
+/// from the server is copied by a second thread, thus both sides block on
+/// reads rather than being polled.
+fn pipe(tls_stream: SslStream<TcpStream>, mut backend: TcpStream) -> Result<()> {
+    let client = tls_stream.get_ref().try_clone()?;
+    client.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    backend.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let tls_stream = Arc::new(Mutex::new(tls_stream));
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+
+    // Server -> client
+    let mut server = backend.try_clone()?;
+    let (server_tls, server_activity, server_client) = (
+        tls_stream.clone(),
+        last_activity.clone(),
+        client.try_clone()?,
+    );
+    let server_to_client = thread::spawn(move || {
+        let mut buf = [0u8; 16384];
+        loop {
+            match server.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    *server_activity.lock().unwrap() = Instant::now();
+                    if server_tls.lock().unwrap().write_all(&buf[..n]).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if is_timeout(&e) && !is_idle(&server_activity) => (),
+                Err(_) => break,
+            }
+        }
+        server_tls.lock().unwrap().shutdown().ok();
+        server_client.shutdown(Shutdown::Both).ok();
+    });
+
+    // Client -> server
+    let mut buf = [0u8; 16384];
+    let mut peek_buf = [0u8; 1];
+    let res = loop {
+        // Wait for data without holding the lock, unless OpenSSL already buffered some
+        if tls_stream.lock().unwrap().ssl().pending() == 0 {
+            match client.peek(&mut peek_buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => (),
+                Err(e) if is_timeout(&e) && !is_idle(&last_activity) => continue,
+                Err(e) if is_timeout(&e) => break Ok(()),
+                Err(e) => break Err(e.into()),
+            }
+        }
+        let read = tls_stream.lock().unwrap().ssl_read(&mut buf);
+        match read {
+            Ok(0) => break Ok(()),
+            Ok(n) => {
+                *last_activity.lock().unwrap() = Instant::now();
+                if let Err(e) = backend.write_all(&buf[..n]) {
+                    break Err(e.into());
+                }
+            }
+            // Records without application data (ie. session tickets)
+            Err(e) if e.code() == ErrorCode::WANT_READ => (),
+            Err(e) if e.code() == ErrorCode::ZERO_RETURN => break Ok(()),
+            Err(e) => break Err(anyhow!("TLS connection failed: {}", e)),
+        }
+    };
+    backend.shutdown(Shutdown::Both).ok();
+    server_to_client.join().ok();
+    res
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
+}
+
+/// Whether neither side of a connection sent data for `IDLE_TIMEOUT`
+fn is_idle(last_activity: &Mutex<Instant>) -> bool {
+    last_activity.lock().unwrap().elapsed() >= IDLE_TIMEOUT
+}
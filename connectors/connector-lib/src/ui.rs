@@ -0,0 +1,86 @@
+/// The reporting of a Connector. The `Connector` main loop hands everything
+/// worth showing to the operator (the current state, submitted datapoints &
+/// errors) to its `ConnectorUi`, which Connectors can implement on their own
+/// structs to print, log or forward it as they see fit.
+use crate::connector::{Connector, Datapoint};
+use crate::oracle_core::{OracleCore, PoolStatus};
+use anyhow::Result;
+
+/// Reports the state & activity of a Connector
+pub trait ConnectorUi: Send + Sync {
+    /// Print information about the Connector & the Oracle Core. Returns an
+    /// error if the state could not be fetched from the core.
+    fn print_info(&self, connector: &Connector, oc: &OracleCore) -> Result<bool>;
+
+    /// Report that a datapoint was submitted to the core
+    fn datapoint_submitted(&self, connector: &Connector, datapoint: Datapoint, tx_id: &str) {
+        println!("\nSubmit New {} Datapoint: {}", connector.title, datapoint);
+        println!("Transaction ID: {}", tx_id);
+    }
+
+    /// Report an error which occurred in the main loop of the Connector
+    fn error(&self, _connector: &Connector, error: &anyhow::Error) {
+        println!("Error: {:?}", error);
+    }
+
+    /// Raise an alert which requires the attention of the operator (ie. the
+    /// circuit breaker tripped)
+    fn alert(&self, connector: &Connector, message: &str) {
+        println!("\n!!! {} Connector Alert !!!\n{}", connector.title, message);
+    }
+}
+
+/// Adapts a plain `fn(&Connector, &OracleCore) -> Result<bool>` printing
+/// function into a `ConnectorUi`
+#[derive(Clone, Copy)]
+pub struct FnConnectorUi(pub fn(&Connector, &OracleCore) -> Result<bool>);
+
+impl ConnectorUi for FnConnectorUi {
+    fn print_info(&self, connector: &Connector, oc: &OracleCore) -> Result<bool> {
+        (self.0)(connector, oc)
+    }
+}
+
+/// The default `ConnectorUi` of a basic Connector
+#[derive(Clone, Copy)]
+pub struct BasicConnectorUi;
+
+impl ConnectorUi for BasicConnectorUi {
+    fn print_info(&self, connector: &Connector, oc: &OracleCore) -> Result<bool> {
+        print_connector_info(connector, oc, |_| vec![])
+    }
+}
+
+/// Prints the state of the pool & the local oracle as the `BasicConnectorUi`
+/// does, followed by the `details` lines (given the `PoolStatus`) which
+/// `ConnectorUi`s can use to describe their Connector
+pub fn print_connector_info(
+    connector: &Connector,
+    oc: &OracleCore,
+    details: impl FnOnce(&PoolStatus) -> Vec<String>,
+) -> Result<bool> {
+    let pool_status = oc.pool_status()?;
+    let oracle_status = oc.oracle_status()?;
+    print!("\x1B[2J\x1B[1;1H");
+    println!("{} Connector", connector.title);
+    println!("===========================================");
+    println!("Current Blockheight: {}", oc.current_block_height()?);
+    println!(
+        "Current Oracle Pool Stage: {}",
+        pool_status.current_pool_stage
+    );
+    println!(
+        "Submit Datapoint In Latest Epoch: {}",
+        !oracle_status.waiting_for_datapoint_submit
+    );
+
+    println!("Latest Datapoint: {}", oracle_status.latest_datapoint);
+    for line in details(&pool_status) {
+        println!("{}", line);
+    }
+    for line in connector.source.info() {
+        println!("{}", line);
+    }
+    println!("===========================================");
+    Ok(true)
+}
@@ -6,10 +6,14 @@
 /// of nanoErgs per 1 USD, not the rate per nanoErg.
 use anyhow::{anyhow, Result};
 use frontend_connector_lib::FrontendConnector;
+use std::time::Duration;
 
 // Number of nanoErgs in a single Erg
 static NANO_ERG_CONVERSION: f64 = 1000000000.0;
 
+// Timeout of the requests to CoinGecko
+static REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
 static CG_RATE_URL: &str =
     "https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD";
 
@@ -21,7 +25,11 @@ pub fn generate_current_price(datapoint: u64) -> f64 {
 /// Acquires the price of Ergs in USD from CoinGecko, convert it
 /// into nanoErgs per 1 USD, and return it.
 fn get_nanoerg_usd_price() -> Result<u64> {
-    let resp = reqwest::blocking::Client::new().get(CG_RATE_URL).send()?;
+    let resp = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .get(CG_RATE_URL)
+        .send()?;
     let price_json = json::parse(&resp.text()?)?;
     if let Some(p) = price_json["ergo"]["usd"].as_f64() {
         // Convert from price Erg/USD to nanoErgs per 1 USD
@@ -1,6 +1,31 @@
 use crate::FrontendConnector;
 use connector_lib::get_core_api_port;
+use connector_lib::tls::{
+    get_api_tls_paths, new_tls_proxy, InternalConnection, InternalListener, TlsProxy,
+};
 use sincere;
+use std::sync::Arc;
+
+/// Rejects requests which did not come through the TLS proxy, and answers
+/// the proxy's probe
+fn guard_internal_connections(app: &mut sincere::App, proxy: Arc<TlsProxy>) {
+    app.before(move |context| {
+        match proxy.internal_connection(context.request.remote_addr()) {
+            InternalConnection::Forwarded(_) => return,
+            InternalConnection::Probe(token) => {
+                context.response.from_text(token).unwrap();
+            }
+            InternalConnection::Rejected => {
+                context
+                    .response
+                    .status_code(403)
+                    .from_text("Connections must use TLS.")
+                    .unwrap();
+            }
+        }
+        context.stop();
+    });
+}
 
 /// Starts the Frontend GET API server which can be made publicly available
 pub fn start_get_api(frontend_connector: FrontendConnector) {
@@ -52,5 +77,25 @@ pub fn start_get_api(frontend_connector: FrontendConnector) {
         + 2)
     .to_string();
     let address = "0.0.0.0:".to_string() + &port;
-    app.run(&address, 1).ok();
+    // If TLS is enabled, the server only listens locally behind the TLS listener
+    match get_api_tls_paths().expect("Failed to read TLS settings from `oracle-config.yaml`.") {
+        Some((cert_path, key_path)) => {
+            let proxy = Arc::new(
+                new_tls_proxy(&cert_path, &key_path)
+                    .expect("Failed to set up TLS for the Frontend Connector API server."),
+            );
+            let internal_listener =
+                InternalListener::reserve().expect("Failed to reserve a local API server port.");
+            guard_internal_connections(&mut app, proxy.clone());
+            proxy
+                .spawn(&address, internal_listener.address())
+                .expect("Failed to start the Frontend Connector TLS listener.");
+            // The proxy only forwards connections once it verified that
+            // the server which bound the released port is this one
+            app.run(&internal_listener.release(), 1).ok();
+        }
+        None => {
+            app.run(&address, 1).ok();
+        }
+    }
 }
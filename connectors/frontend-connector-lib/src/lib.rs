@@ -7,8 +7,9 @@ mod api;
 
 use anyhow::Result;
 use api::start_get_api;
-use connector_lib::{get_core_api_port, Connector, Datapoint, OracleCore};
+use connector_lib::{get_oracle_core, Connector, Datapoint, OracleCore};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 type Price = f64;
 
@@ -53,6 +54,18 @@ impl FrontendConnector {
         }
     }
 
+    /// Create a new FrontendConnector from an existing `Connector` (ie. one
+    /// using its own `DatapointSource`/`ConnectorUi`)
+    pub fn from_connector(
+        connector: Connector,
+        generate_current_price: fn(Datapoint) -> Price,
+    ) -> FrontendConnector {
+        FrontendConnector {
+            connector: connector,
+            generate_current_price: generate_current_price,
+        }
+    }
+
     /// Run the `FrontendConnector` using a local Oracle Core + start the GET
     /// API Server
     pub fn run(&self) {
@@ -69,18 +82,24 @@ impl FrontendConnector {
 
     /// Generates the json for the frontend data
     pub fn prepare_frontend_data_json(&self) -> Result<String> {
-        let oc = OracleCore::new("0.0.0.0", &get_core_api_port()?);
+        let oc = get_oracle_core()?;
         let pinfo = oc.pool_info()?;
         let pstatus = oc.pool_status()?;
         let block_height = oc.current_block_height()?;
 
+        // Average block interval reported by the core (2 minutes if unavailable)
+        let block_interval = pstatus.average_block_interval.unwrap_or(120.0);
+
         // Posting Schedule
         let posting_sched_blocks = pinfo.live_epoch_length + pinfo.epoch_prep_length;
-        let posting_sched_minutes = posting_sched_blocks * 2;
+        let posting_sched_minutes =
+            (posting_sched_blocks as f64 * block_interval / 60.0).round() as u64;
 
         // How Long Until Epoch Ends
         let mut epoch_ends_in_minutes = 0;
-        if pstatus.epoch_ends > block_height {
+        if let Some(epoch_ends_time) = pstatus.epoch_ends_time {
+            epoch_ends_in_minutes = epoch_ends_time.saturating_sub(unix_time()) / 60;
+        } else if pstatus.epoch_ends > block_height {
             epoch_ends_in_minutes = (pstatus.epoch_ends - block_height) * 2;
         }
 
@@ -114,8 +133,20 @@ impl FrontendConnector {
             oracle_pool_nft_id: pinfo.oracle_pool_nft_id,
             oracle_pool_participant_token_id: pinfo.oracle_pool_participant_token_id,
             epoch_end_height: pstatus.epoch_ends,
+            epoch_end_time: pstatus.epoch_ends_time,
+            next_epoch_start_time: pstatus.next_epoch_starts_time,
+            next_collection_time: pstatus.next_collection_time,
+            average_block_interval: block_interval,
         };
 
         Ok(data_json.dump())
     }
 }
+
+/// The current unix time in seconds
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
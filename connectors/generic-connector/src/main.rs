@@ -0,0 +1,48 @@
+/// This Connector fetches its datapoint from the HTTP/JSON sources defined
+/// in the `connector` section of the `oracle-config.yaml` and submits it to
+/// an oracle core. New pools can be served by writing a definition (URLs,
+/// headers, JSON pointers to the price, inversion, scaling into on-chain
+/// units & aggregation) rather than a new Connector crate.
+use anyhow::Result;
+use connector_lib::{
+    get_connector_definition, print_connector_info, Connector, ConnectorDefinition, ConnectorUi,
+    OracleCore,
+};
+
+/// Prints the state of the pool along with the definition of the Connector
+struct GenericConnectorUi {
+    definition: ConnectorDefinition,
+}
+
+impl ConnectorUi for GenericConnectorUi {
+    fn print_info(&self, connector: &Connector, oc: &OracleCore) -> Result<bool> {
+        print_connector_info(connector, oc, |pool_status| {
+            vec![
+                format!(
+                    "Latest Price: {}",
+                    self.definition.price_of(pool_status.latest_datapoint)
+                ),
+                format!("Aggregation: {:?}", self.definition.aggregation),
+                format!("Sources: {}", self.definition.source_names().join(", ")),
+            ]
+        })
+    }
+}
+
+fn main() {
+    let definition = get_connector_definition()
+        .expect("Failed to read the `connector` definition from local `oracle-config.yaml`.");
+
+    // Create the Connector from the definition
+    let title = definition.title.clone();
+    let connector = Connector::from_source(
+        &title,
+        definition.clone().into_source(),
+        GenericConnectorUi {
+            definition: definition,
+        },
+    );
+
+    // Start the Connector
+    connector.run();
+}
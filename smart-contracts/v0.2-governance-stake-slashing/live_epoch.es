@@ -0,0 +1,75 @@
+{ // This box:
+  // R4: The latest finalized datapoint (from the previous epoch)
+  // R5: Block height that the current epoch will finish on
+  // R6: Address of the "Epoch Preparation" stage contract.
+  // R7: The oracle payout price of the current epoch
+
+  // Oracle box:
+  // R4: Public key (group element)
+  // R5: Epoch box Id (this box's Id)
+  // R6: Data point
+  // R7: Vote for a new oracle payout price (optional)
+
+  val oracleBoxes = CONTEXT.dataInputs.filter{(b:Box) =>
+    b.R5[Coll[Byte]].get == SELF.id &&
+    b.tokens(0)._1 == oracleTokenId
+  }
+
+  val oracleReward = SELF.R7[Long].get
+
+  // Governance: If 75%+ of all oracles in the pool (`numOracles`) voted for the same
+  // payout price, then it is used for the upcoming epoch, otherwise the current price is kept.
+  // Only the votes of collected oracles are counted.
+  val votes = oracleBoxes.filter{(b:Box) => b.R7[Long].isDefined}.map{(b:Box) => b.R7[Long].get}
+  val voteThreshold = (numOracles * 3 + 3) / 4
+  def voteCount(price: Long) = votes.filter{(v: Long) => v == price}.size
+  val passedVote = votes.exists{(v: Long) => voteCount(v) >= voteThreshold}
+  val newPayoutPrice = OUTPUTS(0).R9[Long].get
+  val validNewPayoutPrice = if (passedVote) voteCount(newPayoutPrice) >= voteThreshold else newPayoutPrice == oracleReward
+
+  val oraclePubKeys = oracleBoxes.map{(b:Box) => b.R4[GroupElement].get}
+
+  val collectorPubKey = oraclePubKeys(OUTPUTS(1).R4[Int].get)
+
+  val sum = oracleBoxes.fold(0L, { (t:Long, b: Box) => t + b.R6[Long].get })
+
+  val average = sum / oracleBoxes.size
+
+  val firstOracleDataPoint = oracleBoxes(0).R6[Long].get
+
+  def getPrevOracleDataPoint(index:Int) = if (index <= 0) firstOracleDataPoint else oracleBoxes(index - 1).R6[Long].get
+
+  val rewardAndDeviationCheck = oracleBoxes.fold((1, true), {
+      (t:(Int, Boolean), b:Box) =>
+         val currOracleDataPoint = b.R6[Long].get
+         val prevOracleDataPoint = getPrevOracleDataPoint(t._1 - 1)
+
+         (t._1 + 1, t._2 &&
+                 OUTPUTS(t._1).propositionBytes == proveDlog(b.R4[GroupElement].get).propBytes &&
+                 OUTPUTS(t._1).value >= oracleReward &&
+                 prevOracleDataPoint >= currOracleDataPoint
+      )
+    }
+  )
+
+  val lastDataPoint = getPrevOracleDataPoint(rewardAndDeviationCheck._1 - 1)
+  val firstDataPoint = oracleBoxes(0).R6[Long].get
+  val globalDelta = firstDataPoint * maxDeviation / 100
+
+  val epochPrepScriptHash = SELF.R6[Coll[Byte]].get
+
+  sigmaProp(
+    blake2b256(OUTPUTS(0).propositionBytes) == epochPrepScriptHash &&
+    oracleBoxes.size >= minOracleBoxes &&
+    OUTPUTS(0).tokens == SELF.tokens &&
+    OUTPUTS(0).R4[Long].get == average &&
+    OUTPUTS(0).R5[Int].get == SELF.R5[Int].get + epochPeriod &&
+    OUTPUTS(0).R6[GroupElement].get == collectorPubKey &&
+    OUTPUTS(0).R7[Coll[GroupElement]].get == oraclePubKeys &&
+    OUTPUTS(0).R8[Coll[Byte]].get == SELF.id &&
+    validNewPayoutPrice &&
+    OUTPUTS(0).value >= SELF.value - (oracleBoxes.size + 1) * oracleReward &&
+    rewardAndDeviationCheck._2 &&
+    lastDataPoint >= firstDataPoint - globalDelta
+  ) && proveDlog(collectorPubKey)
+}
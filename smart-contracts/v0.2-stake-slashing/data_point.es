@@ -0,0 +1,59 @@
+{
+ // This box:
+ // R4: The address of the oracle (never allowed to change after bootstrap).
+ // R5: The box id of the latest Live Epoch box.
+ // R6: The oracle's datapoint.
+ // Value: The oracle's collateral.
+
+ val pubKey = SELF.R4[GroupElement].get
+
+ val validPoolBox = {(b: Box) => b.tokens(0)._1 == poolTokenId}
+
+ // Slash Uncommitted/Slash Collector: The collateral is moved into a Pool Deposit box
+ // while the Datapoint box itself is kept intact (other than holding no collateral).
+ val slashedOutputs = CONTEXT.dataInputs.size > 0 &&
+   OUTPUTS(0).R4[GroupElement].get == pubKey &&
+   OUTPUTS(0).R5[Coll[Byte]].get == SELF.R5[Coll[Byte]].get &&
+   OUTPUTS(0).R6[Long].get == SELF.R6[Long].get &&
+   OUTPUTS(0).propositionBytes == SELF.propositionBytes &&
+   OUTPUTS(0).tokens == SELF.tokens &&
+   OUTPUTS(0).value <= minBoxValue &&
+   OUTPUTS(1).value >= SELF.value - minBoxValue &&
+   blake2b256(OUTPUTS(1).propositionBytes) == poolDepositScriptHash
+
+ if (slashedOutputs && validPoolBox(CONTEXT.dataInputs(0)) &&
+     blake2b256(CONTEXT.dataInputs(0).propositionBytes) == epochPrepScriptHash) {
+   // R6: Latest collector, R7: Oracles collected in the last epoch, R8: Box id of the last Live Epoch box
+   val epochPrepBox = CONTEXT.dataInputs(0)
+
+   // Slash Uncommitted: The oracle did not commit a datapoint in the last epoch
+   val uncommitted = epochPrepBox.R8[Coll[Byte]].get != SELF.R5[Coll[Byte]].get
+
+   // Slash Collector: This oracle was the last collector and missed a datapoint from the last epoch
+   val missedDatapoint = CONTEXT.dataInputs.size > 1 && {
+     val missedBox = CONTEXT.dataInputs(1)
+     missedBox.tokens(0)._1 == oracleTokenId &&
+     missedBox.R5[Coll[Byte]].get == epochPrepBox.R8[Coll[Byte]].get &&
+     !epochPrepBox.R7[Coll[GroupElement]].get.exists{(g: GroupElement) => g == missedBox.R4[GroupElement].get} &&
+     epochPrepBox.R6[GroupElement].get == pubKey
+   }
+
+   sigmaProp(uncommitted || missedDatapoint)
+ } else {
+   // Commit Datapoint
+   val liveEpochBox = CONTEXT.dataInputs(0)
+
+   val validLiveEpochBox = validPoolBox(liveEpochBox) &&
+			 blake2b256(liveEpochBox.propositionBytes) == liveEpochScriptHash
+
+   sigmaProp(
+     OUTPUTS(0).R4[GroupElement].get == pubKey &&
+     OUTPUTS(0).R5[Coll[Byte]].get == liveEpochBox.id &&
+     OUTPUTS(0).R6[Long].get > 0 &&
+     OUTPUTS(0).propositionBytes == SELF.propositionBytes &&
+     OUTPUTS(0).tokens == SELF.tokens &&
+     OUTPUTS(0).value >= minCollateral &&
+     validLiveEpochBox
+   ) && proveDlog(pubKey)
+ }
+}
@@ -0,0 +1,49 @@
+{
+  // This box:
+  // R4: The finalized data point from collection
+  // R5: Height the epoch will end
+  // R6: The address of the latest collector
+  // R7: The addresses of all oracles who had their datapoints collected in the last epoch
+  // R8: The box id of the previous Live Epoch box
+
+  val canStartEpoch = HEIGHT > SELF.R5[Int].get - livePeriod
+  val epochNotOver = HEIGHT < SELF.R5[Int].get
+  val epochOver = HEIGHT >= SELF.R5[Int].get
+  val enoughFunds = SELF.value >= minPoolBoxValue
+
+  val maxNewEpochHeight = HEIGHT + epochPeriod + buffer
+  val minNewEpochHeight = HEIGHT + epochPeriod
+
+  // R6 of this box is a GroupElement, thus the output address is used to tell apart the spending paths
+  if (OUTPUTS(0).propositionBytes != SELF.propositionBytes) {
+    val isliveEpochOutput = OUTPUTS(0).R6[Coll[Byte]].get == blake2b256(SELF.propositionBytes) &&
+			                blake2b256(OUTPUTS(0).propositionBytes) == liveEpochScriptHash
+    sigmaProp( // start next epoch
+      epochNotOver && canStartEpoch && enoughFunds &&
+      OUTPUTS(0).R4[Long].get == SELF.R4[Long].get &&
+      OUTPUTS(0).R5[Int].get == SELF.R5[Int].get &&
+      OUTPUTS(0).tokens == SELF.tokens &&
+      OUTPUTS(0).value >= SELF.value &&
+      isliveEpochOutput
+    ) || sigmaProp( // create new epoch
+      epochOver &&
+      enoughFunds &&
+      OUTPUTS(0).R4[Long].get == SELF.R4[Long].get &&
+      OUTPUTS(0).R5[Int].get >= minNewEpochHeight &&
+      OUTPUTS(0).R5[Int].get <= maxNewEpochHeight &&
+      OUTPUTS(0).tokens == SELF.tokens &&
+      OUTPUTS(0).value >= SELF.value &&
+      isliveEpochOutput
+    )
+  } else {
+    sigmaProp( // collect funds
+      OUTPUTS(0).R4[Long].get == SELF.R4[Long].get &&
+      OUTPUTS(0).R5[Int].get == SELF.R5[Int].get &&
+      OUTPUTS(0).R6[GroupElement].get == SELF.R6[GroupElement].get &&
+      OUTPUTS(0).R7[Coll[GroupElement]].get == SELF.R7[Coll[GroupElement]].get &&
+      OUTPUTS(0).R8[Coll[Byte]].get == SELF.R8[Coll[Byte]].get &&
+      OUTPUTS(0).tokens == SELF.tokens &&
+      OUTPUTS(0).value > SELF.value
+    )
+  }
+}
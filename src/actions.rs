@@ -2,21 +2,18 @@
 /// by an oracle part of the oracle pool. These actions
 /// are implemented on the `OraclePool` struct.
 use crate::node_interface::{
-    address_to_raw_for_register, address_to_tree, current_block_height,
-    raw_from_register_to_address, send_transaction, serialize_boxes,
-    serialized_unspent_boxes_with_min_total,
+    address_to_raw_for_register, current_block_height, raw_from_register_to_address,
+    serialize_boxes, serialized_box_from_id, serialized_unspent_boxes_with_min_total,
 };
-use crate::oracle_config::PoolParameters;
-use crate::oracle_state::{LiveEpochState, OraclePool};
+use crate::oracle_config::{is_dry_run_action, PoolParameters};
+use crate::oracle_state::{LiveEpochState, OraclePool, OracleStake, SLASHED_DATAPOINT_BOX_VALUE};
 use crate::templates::BASIC_TRANSACTION_SEND_REQUEST;
 use crate::Result;
 use ergo_lib::chain::ergo_box::ErgoBox;
 use ergo_lib::chain::Base16Str;
-use ergo_lib::ergotree_ir::mir::constant::Constant;
-use ergo_offchain_utilities::encoding::{
-    serialize_hex_encoded_string, string_to_blake2b_hash, unwrap_hex_encoded_string, unwrap_long,
-};
+use ergo_offchain_utilities::encoding::{unwrap_hex_encoded_string, unwrap_long};
 use json;
+use std::ops::Range;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -29,31 +26,59 @@ pub enum CollectionError {
     LocalOracleFailedToPostDatapointWithinDeviation(),
 }
 
+#[derive(Error, Debug)]
+pub enum SlashingError {
+    #[error("Failed slashing collateral. The Datapoint box {0} does not hold enough collateral to be slashed.")]
+    InsufficientCollateral(String),
+}
+
 impl OraclePool {
+    /// Submits the tx request of the given action, or if the action is set
+    /// to dry run, returns a json report of the tx instead of posting it.
+    fn submit_action_tx(&self, action_name: &str, req: &json::JsonValue) -> Result<String> {
+        if is_dry_run_action(action_name) {
+            return self.dry_run_report(action_name, req);
+        }
+        self.preflight_and_submit(req)
+    }
+
     /// Generates and submits the "Commit Datapoint" action tx
     pub fn action_commit_datapoint(&self, datapoint: u64) -> Result<String> {
         let parameters = PoolParameters::new();
         let mut req = json::parse(BASIC_TRANSACTION_SEND_REQUEST)?;
 
-        // Defining the registers of the output box
+        // Defining the registers of the output box (as laid out by the pool's protocol)
         let live_epoch_id = self.get_live_epoch_state()?.epoch_id;
-        let registers = object! {
-            "R4": address_to_raw_for_register(&self.local_oracle_address)?,
-            "R5": serialize_hex_encoded_string(&live_epoch_id)?.base16_str(),
-            "R6": Constant::from(datapoint as i64).base16_str(),
-        };
+        let registers = parameters.protocol().datapoint_registers(
+            address_to_raw_for_register(&self.local_oracle()?.address)?,
+            &live_epoch_id,
+            datapoint,
+        )?;
         // Defining the tokens to be spent
         let token_json = object! {
-            "tokenId": self.oracle_pool_participant_token.to_string(),
+            "tokenId": self.participant_token()?.to_string(),
             "amount": 1
         };
 
-        let mut inputs_raw = vec![self.local_oracle_datapoint_scan.get_serialized_box()?];
+        // In stake slashing pools the Datapoint box must hold at least the minimum
+        // collateral, thus topping up the box if its collateral was slashed
+        let datapoint_box_value = self
+            .local_oracle()?
+            .datapoint_scan
+            .get_box()?
+            .value
+            .as_u64()
+            .clone();
+        let output_value = datapoint_box_value.max(parameters.minimum_collateral);
+        let collateral_top_up = output_value - datapoint_box_value;
+
+        let mut inputs_raw = vec![self.local_oracle()?.datapoint_scan.get_serialized_box()?];
         inputs_raw.append(&mut serialized_unspent_boxes_with_min_total(
-            parameters.base_fee.into(),
+            parameters.base_fee + collateral_top_up,
         )?);
 
         // Filling out the json tx request template
+        req["requests"][0]["value"] = output_value.into();
         req["requests"][0]["address"] = self.datapoint_stage.contract_address.clone().into();
         req["requests"][0]["registers"] = registers.into();
         req["requests"][0]["assets"] = vec![token_json].into();
@@ -61,21 +86,21 @@ impl OraclePool {
         req["dataInputsRaw"] = vec![self.live_epoch_stage.get_serialized_box()?].into();
         req["fee"] = parameters.base_fee.into();
 
-        let result = send_transaction(&req)?;
+        let result = self.submit_action_tx("Commit Datapoint", &req)?;
         Ok(result)
     }
 
     /// Generates and submits the "Collect Funds" action tx
     pub fn action_collect_funds(&self) -> Result<String> {
+        let parameters = PoolParameters::new();
         let mut req = json::parse(BASIC_TRANSACTION_SEND_REQUEST)?;
 
-        // Defining the registers of the output box
+        // Defining the registers of the output box (as laid out by the pool's protocol)
         let epoch_prep_state = self.get_preparation_state()?;
-        let registers = object! {
-            "R4": Constant::from(epoch_prep_state.latest_pool_datapoint as i64).base16_str(),
-
-            "R5": Constant::from(epoch_prep_state.next_epoch_ends as i32).base16_str(),
-        };
+        let epoch_prep_box = self.epoch_preparation_stage.get_box()?;
+        let registers = parameters
+            .protocol()
+            .collect_funds_registers(&epoch_prep_state, &epoch_prep_box)?;
         // Defining the tokens to be spent
         let token_json = object! {
             "tokenId": self.oracle_pool_nft.to_string(),
@@ -83,7 +108,7 @@ impl OraclePool {
         };
 
         // Create input boxes Vec with serialized Epoch Preparation box inside
-        let mut unserialized_input_boxes = vec![self.epoch_preparation_stage.get_box()?];
+        let mut unserialized_input_boxes = vec![epoch_prep_box];
         // Acquire all Pool Deposit boxes
         let mut initial_deposit_boxes = self.pool_deposit_stage.get_boxes()?;
         // Only append up to 27 boxes for now. This is to prevent exceeding execution limit for txs.
@@ -116,7 +141,7 @@ impl OraclePool {
         req["inputsRaw"] = serialized_input_boxes.into();
         req["fee"] = action_fee.into();
 
-        let result = send_transaction(&req)?;
+        let result = self.submit_action_tx("Collect Funds", &req)?;
         Ok(result)
     }
 
@@ -125,13 +150,14 @@ impl OraclePool {
         let parameters = PoolParameters::new();
         let mut req = json::parse(BASIC_TRANSACTION_SEND_REQUEST)?;
 
-        // Defining the registers of the output box
+        // Defining the registers of the output box (as laid out by the pool's protocol)
         let epoch_prep_state = self.get_preparation_state()?;
-        let registers = object! {
-            "R4": Constant::from(epoch_prep_state.latest_pool_datapoint as i64).base16_str(),
-            "R5": Constant::from(epoch_prep_state.next_epoch_ends as i32).base16_str(),
-            "R6": serialize_hex_encoded_string(&string_to_blake2b_hash(address_to_tree(&self.epoch_preparation_stage.contract_address)?)?)?.base16_str(),
-        };
+        let registers = parameters.protocol().live_epoch_registers(
+            self,
+            epoch_prep_state.latest_pool_datapoint,
+            epoch_prep_state.next_epoch_ends,
+            &parameters,
+        )?;
         // Defining the tokens to be spent
         let token_json = object! {
             "tokenId": self.oracle_pool_nft.to_string(),
@@ -151,7 +177,7 @@ impl OraclePool {
         req["inputsRaw"] = inputs_raw.into();
         req["fee"] = parameters.base_fee.into();
 
-        let result = send_transaction(&req)?;
+        let result = self.submit_action_tx("Start Next Epoch", &req)?;
         Ok(result)
     }
 
@@ -166,13 +192,14 @@ impl OraclePool {
             + parameters.live_epoch_length
             + parameters.buffer_length;
 
-        // Defining the registers of the output box
+        // Defining the registers of the output box (as laid out by the pool's protocol)
         let epoch_prep_state = self.get_preparation_state()?;
-        let registers = object! {
-            "R4": Constant::from(epoch_prep_state.latest_pool_datapoint as i64).base16_str(),
-            "R5": Constant::from(new_finish_height as i32).base16_str(),
-            "R6": serialize_hex_encoded_string(&string_to_blake2b_hash(address_to_tree(&self.epoch_preparation_stage.contract_address)?)?)?.base16_str(),
-        };
+        let registers = parameters.protocol().live_epoch_registers(
+            self,
+            epoch_prep_state.latest_pool_datapoint,
+            new_finish_height,
+            &parameters,
+        )?;
         // Defining the tokens to be spent
         let token_json = object! {
             "tokenId": self.oracle_pool_nft.to_string(),
@@ -192,7 +219,7 @@ impl OraclePool {
         req["inputsRaw"] = inputs_raw.into();
         req["fee"] = parameters.base_fee.into();
 
-        let result = send_transaction(&req)?;
+        let result = self.submit_action_tx("Create New Epoch", &req)?;
         Ok(result)
     }
 
@@ -206,28 +233,25 @@ impl OraclePool {
         // Filter out Datapoint boxes not from the latest epoch
         let current_epoch_datapoint_boxes =
             current_epoch_boxes_filter(&self.datapoint_stage.get_boxes()?, &live_epoch_state);
-        // Sort Datapoint boxes in decreasing order
-        let sorted_datapoint_boxes = sort_datapoint_boxes(&current_epoch_datapoint_boxes);
-
-        // Acquire the finalized oracle pool datapoint and the list of successful datapoint boxes which were within the deviation range
-        let (finalized_datapoint, successful_boxes) = finalize_datapoint(
-            &sorted_datapoint_boxes,
-            parameters.deviation_range as i64, // Make sure to change this to config #
-            parameters.consensus_num as i64,   // Make sure to change this to config #
-        )?;
 
-        // Find the index of the local oracle's Datapoint box in the successful boxes list
-        let local_datapoint_box_index = find_box_index_in_list(
-            self.local_oracle_datapoint_scan.get_box()?,
-            &successful_boxes,
-        )
-        .ok_or(CollectionError::LocalOracleFailedToPostDatapointWithinDeviation())?;
+        // Finalize the oracle pool datapoint as defined by the protocol version, acquiring
+        // the ordered list of successful Datapoint boxes and the local oracle's index in it
+        let protocol = parameters.protocol();
+        let collection = protocol.finalize_collection(
+            &current_epoch_datapoint_boxes,
+            &self.local_oracle()?.datapoint_scan.get_box()?,
+            &parameters,
+        )?;
+        let successful_boxes = &collection.boxes;
+        let local_datapoint_box_index = collection.collector_index;
 
+        // The payout price of the current epoch (held on-chain in governance pools)
+        let oracle_payout_price = self.get_oracle_payout_price(&parameters)?;
         // Tx fee for the transaction
-        let tx_fee = (parameters.base_fee) * sorted_datapoint_boxes.len() as u64;
+        let tx_fee = (parameters.base_fee) * current_epoch_datapoint_boxes.len() as u64;
         // Define the new value of the oracle pool box after payouts/tx fee
-        let new_box_value = live_epoch_state.funds
-            - (parameters.oracle_payout_price * (successful_boxes.len() as u64 + 1));
+        let new_box_value =
+            live_epoch_state.funds - (oracle_payout_price * (successful_boxes.len() as u64 + 1));
         // Define the finish height of the following epoch
         let new_finish_height = self.get_live_epoch_state()?.epoch_ends
             + parameters.epoch_preparation_length
@@ -238,10 +262,15 @@ impl OraclePool {
             "tokenId": self.oracle_pool_nft.to_string(),
             "amount": 1
         };
-        let registers = object! {
-            "R4": Constant::from(finalized_datapoint as i64).base16_str(),
-            "R5": Constant::from(new_finish_height as i32).base16_str(),
-        };
+        // Stake slashing/governance pools extend the registers (see `PoolProtocol`)
+        let registers = protocol.collected_epoch_registers(
+            self,
+            &collection,
+            &live_epoch_state,
+            new_finish_height,
+            oracle_payout_price,
+            &parameters,
+        )?;
         let mut inputs_raw = vec![self.live_epoch_stage.get_serialized_box()?];
         inputs_raw.append(&mut serialized_unspent_boxes_with_min_total(tx_fee)?);
 
@@ -252,7 +281,7 @@ impl OraclePool {
         req["requests"][0]["assets"] = vec![token_json].into();
 
         // Filling out requests for the oracle payout outputs
-        for b in &successful_boxes {
+        for b in successful_boxes {
             // Get the P2PK from the hex encoded constant string minus the first two characters which are a register type descriptor
             let oracle_address = raw_from_register_to_address(
                 &b.additional_registers.get_ordered_values()[0].base16_str(),
@@ -260,37 +289,96 @@ impl OraclePool {
             req["requests"]
                 .push(object! {
                     "address": oracle_address,
-                    "value": parameters.oracle_payout_price,
+                    "value": oracle_payout_price,
                 })
                 .ok();
         }
-        // Add the local oracle Datapoint box index into R4 of the first oracle payout box
-        req["requests"][1]["registers"] = object! {
-            "R4": Constant::from(local_datapoint_box_index as i32).base16_str()
-        };
+        // v0.2 pools hold the local oracle Datapoint box index in R4 of the first oracle payout box
+        if let Some(registers) = protocol.payout_registers(local_datapoint_box_index) {
+            req["requests"][1]["registers"] = registers;
+        }
         // Pay the local oracle double due to being Collector
         req["requests"][local_datapoint_box_index + 1]["value"] =
-            (parameters.oracle_payout_price * 2).into();
+            (oracle_payout_price * 2).into();
         // Filling out the rest of the json request
         req["inputsRaw"] = inputs_raw.into();
-        req["dataInputsRaw"] = serialize_boxes(&successful_boxes)?.into();
+        req["dataInputsRaw"] = serialize_boxes(successful_boxes)?.into();
         req["fee"] = tx_fee.into();
 
-        let result = send_transaction(&req)?;
+        let result = self.submit_action_tx("Collect Datapoints", &req)?;
         Ok(result)
     }
-}
 
-/// Given an `ErgoBox`, find its index in the input `Vec<ErgoBox>`
-/// If index cannot be found, then local oracle has not submit their
-/// own datapoint, and thus the function returns `None`
-fn find_box_index_in_list(
-    search_box: ErgoBox,
-    sorted_datapoint_boxes: &Vec<ErgoBox>,
-) -> Option<usize> {
-    sorted_datapoint_boxes
-        .iter()
-        .position(|b| b.clone() == search_box)
+    /// Generates and submits the "Slash Uncommitted" action tx for the given
+    /// oracle stake which did not commit a datapoint in the last epoch
+    pub fn action_slash_uncommitted(&self, stake: &OracleStake) -> Result<String> {
+        let mut req = self.build_slash_request(stake)?;
+        req["dataInputsRaw"] = vec![self.epoch_preparation_stage.get_serialized_box()?].into();
+
+        let result = self.submit_action_tx("Slash Uncommitted", &req)?;
+        Ok(result)
+    }
+
+    /// Generates and submits the "Slash Collector" action tx against the
+    /// latest collector, who failed to collect the given oracle stake's datapoint
+    pub fn action_slash_collector(
+        &self,
+        collector_stake: &OracleStake,
+        missed_stake: &OracleStake,
+    ) -> Result<String> {
+        let mut req = self.build_slash_request(collector_stake)?;
+        req["dataInputsRaw"] = vec![
+            self.epoch_preparation_stage.get_serialized_box()?,
+            serialized_box_from_id(&missed_stake.box_id)?,
+        ]
+        .into();
+
+        let result = self.submit_action_tx("Slash Collector", &req)?;
+        Ok(result)
+    }
+
+    /// Builds the shared tx request of the slashing actions, which moves the
+    /// collateral of the given stake's Datapoint box into a Pool Deposit box
+    fn build_slash_request(&self, stake: &OracleStake) -> Result<json::JsonValue> {
+        let parameters = PoolParameters::new();
+        let mut req = json::parse(BASIC_TRANSACTION_SEND_REQUEST)?;
+
+        if !stake.is_slashable() {
+            Err(SlashingError::InsufficientCollateral(stake.box_id.clone()))?;
+        }
+
+        // The Datapoint box keeps its registers and token
+        let registers = object! {
+            "R4": stake.registers[0].clone(),
+            "R5": stake.registers[1].clone(),
+            "R6": stake.registers[2].clone(),
+        };
+        let token_json = object! {
+            "tokenId": self.participant_token()?.to_string(),
+            "amount": 1
+        };
+
+        let mut inputs_raw = vec![serialized_box_from_id(&stake.box_id)?];
+        inputs_raw.append(&mut serialized_unspent_boxes_with_min_total(
+            parameters.base_fee,
+        )?);
+
+        req["requests"][0]["value"] = SLASHED_DATAPOINT_BOX_VALUE.into();
+        req["requests"][0]["address"] = self.datapoint_stage.contract_address.clone().into();
+        req["requests"][0]["registers"] = registers.into();
+        req["requests"][0]["assets"] = vec![token_json].into();
+        // The slashed collateral is moved into a Pool Deposit box
+        req["requests"]
+            .push(object! {
+                "address": self.pool_deposit_stage.contract_address.clone(),
+                "value": stake.collateral - SLASHED_DATAPOINT_BOX_VALUE,
+            })
+            .ok();
+        req["inputsRaw"] = inputs_raw.into();
+        req["fee"] = parameters.base_fee.into();
+
+        Ok(req)
+    }
 }
 
 /// Removes boxes which do not have a valid datapoint Long in R6.
@@ -343,57 +431,69 @@ pub fn average_datapoints(boxes: &Vec<ErgoBox>) -> Result<u64> {
     Ok(average as u64)
 }
 
-/// Verifies that the list of sorted Datapoint boxes passes the deviation check
-pub fn deviation_check(deviation_range: i64, datapoint_boxes: &Vec<ErgoBox>) -> Result<bool> {
-    let num = datapoint_boxes.len();
-    let max_datapoint =
-        unwrap_long(&datapoint_boxes[0].additional_registers.get_ordered_values()[2])?;
-    let min_datapoint = unwrap_long(
-        &datapoint_boxes[num - 1]
-            .additional_registers
-            .get_ordered_values()[2],
-    )?;
+/// Verifies that the list of sorted datapoints passes the deviation check
+pub fn deviation_check(deviation_range: i64, datapoints: &[i64]) -> bool {
+    let max_datapoint = datapoints[0];
+    let min_datapoint = datapoints[datapoints.len() - 1];
     let deviation_delta = max_datapoint * deviation_range / 100;
 
-    Ok(min_datapoint >= max_datapoint - deviation_delta)
+    min_datapoint >= max_datapoint - deviation_delta
 }
 
-/// Finds whether the first or the last value in a list of sorted Datapoint boxes
+/// Finds whether the first or the last value in a list of sorted datapoints
 /// deviates more compared to their adjacted datapoint, and then removes
-/// said datapoint which deviates further.
-pub fn remove_largest_local_deviation_datapoint(
-    datapoint_boxes: &Vec<ErgoBox>,
-) -> Result<Vec<ErgoBox>> {
-    let mut processed_boxes = datapoint_boxes.clone();
-
-    // Check if sufficient number of datapoint boxes to start removing
-    if datapoint_boxes.len() <= 2 {
+/// said datapoint which deviates further (returning the index range of the
+/// remaining datapoints).
+pub fn remove_largest_local_deviation_datapoint(datapoints: &[i64]) -> Result<Range<usize>> {
+    // Check if sufficient number of datapoints to start removing
+    if datapoints.len() <= 2 {
         Err(CollectionError::FailedToReachConsensus())?
-    } else {
-        // Deserialize all the datapoints in a list
-        let dp_len = datapoint_boxes.len();
-        let datapoints: Vec<i64> = datapoint_boxes
-            .iter()
-            .map(|_| {
-                unwrap_long(&datapoint_boxes[0].additional_registers.get_ordered_values()[2])
-                    .unwrap_or(0)
-            })
-            .collect();
-        // Check deviation by subtracting largest value by 2nd largest
-        let front_deviation = datapoints[0] - datapoints[1];
-        // Check deviation by subtracting 2nd smallest value by smallest
-        let back_deviation = datapoints[dp_len - 2] - datapoints[dp_len - 1];
-
-        // Remove largest datapoint if front deviation is greater
-        if front_deviation >= back_deviation {
-            processed_boxes.drain(0..1);
-        }
-        // Remove smallest datapoint if back deviation is greater
-        else {
-            processed_boxes.pop();
+    }
+    let dp_len = datapoints.len();
+    // Check deviation by subtracting largest value by 2nd largest
+    let front_deviation = datapoints[0] - datapoints[1];
+    // Check deviation by subtracting 2nd smallest value by smallest
+    let back_deviation = datapoints[dp_len - 2] - datapoints[dp_len - 1];
+
+    // Remove largest datapoint if front deviation is greater
+    if front_deviation >= back_deviation {
+        Ok(1..dp_len)
+    }
+    // Remove smallest datapoint if back deviation is greater
+    else {
+        Ok(0..dp_len - 1)
+    }
+}
+
+/// Produces the finalized datapoint based on a list of datapoints sorted in
+/// decreasing order.
+/// If the datapoints are outside of the deviation range then attempts to
+/// filter them until a list which is within deviation range is found.
+/// Returns the averaged datapoint and the index range of the successful
+/// datapoints.
+pub fn finalize_datapoint_values(
+    datapoints: &[i64],
+    deviation_range: i64,
+    consensus_num: i64,
+) -> Result<(u64, Range<usize>)> {
+    if datapoints.is_empty() {
+        Err(CollectionError::LocalOracleFailedToPostDatapoint())?;
+    }
+    let mut successful = 0..datapoints.len();
+    while !deviation_check(deviation_range, &datapoints[successful.clone()]) {
+        // Removing largest deviation outlier
+        let remaining = remove_largest_local_deviation_datapoint(&datapoints[successful.clone()])?;
+        successful = successful.start + remaining.start..successful.start + remaining.end;
+
+        if (successful.len() as i64) < consensus_num {
+            Err(CollectionError::FailedToReachConsensus())?;
         }
-        Ok(processed_boxes)
     }
+
+    // Return average + range of successful datapoints
+    let successful_datapoints = &datapoints[successful.clone()];
+    let average = successful_datapoints.iter().sum::<i64>() / successful_datapoints.len() as i64;
+    Ok((average as u64, successful))
 }
 
 // Function which produces the finalized datapoint based on a list of `ErgoBox`es.
@@ -406,16 +506,64 @@ pub fn finalize_datapoint(
     deviation_range: i64,
     consensus_num: i64,
 ) -> Result<(u64, Vec<ErgoBox>)> {
-    let mut successful_boxes = boxes.clone();
-    while !deviation_check(deviation_range, &successful_boxes)? {
-        // Removing largest deviation outlier
-        successful_boxes = remove_largest_local_deviation_datapoint(&successful_boxes)?;
+    let datapoints = boxes
+        .iter()
+        .map(|b| unwrap_long(&b.additional_registers.get_ordered_values()[2]))
+        .collect::<std::result::Result<Vec<i64>, _>>()?;
+    let (average, successful) =
+        finalize_datapoint_values(&datapoints, deviation_range, consensus_num)?;
 
-        if (successful_boxes.len() as i64) < consensus_num {
-            Err(CollectionError::FailedToReachConsensus())?;
-        }
+    // Return average + successful Datapoint boxes
+    Ok((average, boxes[successful].to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_consensus(result: Result<(u64, Range<usize>)>) -> bool {
+        matches!(
+            result.map_err(|e| e.downcast::<CollectionError>()),
+            Err(Ok(CollectionError::FailedToReachConsensus()))
+        )
     }
 
-    // Return average + successful Datapoint boxes
-    Ok((average_datapoints(&successful_boxes)?, successful_boxes))
+    #[test]
+    fn datapoints_within_deviation_are_averaged() {
+        assert_eq!(
+            finalize_datapoint_values(&[1030, 1020, 1000], 5, 3).unwrap(),
+            (1016, 0..3)
+        );
+    }
+
+    #[test]
+    fn outliers_are_removed_from_either_end() {
+        // The largest datapoint deviates further from its neighbour
+        assert_eq!(
+            finalize_datapoint_values(&[1500, 1020, 1010, 1000], 5, 3).unwrap(),
+            (1010, 1..4)
+        );
+        // The smallest datapoint deviates further from its neighbour
+        assert_eq!(
+            finalize_datapoint_values(&[1020, 1010, 1000, 500], 5, 3).unwrap(),
+            (1010, 0..3)
+        );
+    }
+
+    #[test]
+    fn consensus_failures_are_errors() {
+        // Removing the outlier leaves fewer datapoints than the consensus number
+        assert!(failed_consensus(finalize_datapoint_values(
+            &[1500, 1010, 1000],
+            5,
+            3
+        )));
+        // Too few datapoints to remove any outlier
+        assert!(failed_consensus(finalize_datapoint_values(
+            &[2000, 1000],
+            5,
+            1
+        )));
+        assert!(finalize_datapoint_values(&[], 5, 1).is_err());
+    }
 }
@@ -1,120 +1,146 @@
-use crate::node_interface::current_block_height;
-use crate::oracle_config::{get_core_api_port, get_node_url, PoolParameters};
-use crate::oracle_state::{OraclePool, PoolBoxState};
-use crate::print_action_results;
-use anyhow::anyhow;
-use crossbeam::Receiver;
-use json;
+use crate::api_security::{AccessError, PostApiAccess, RequestCredentials};
+use crate::api_v1;
+use crate::api_v1::{ApiError, ApiResult, PoolStage};
+use crate::oracle_config::{
+    get_core_api_bind_address, get_core_api_port, PoolParameters, PostApiSettings, TlsSettings,
+};
+use crate::oracle_state::OraclePool;
+use crate::tls::{new_tls_proxy, run_app, InternalConnection, TlsProxy};
+use serde::Serialize;
+use serde_json::json;
 use sincere;
-use std::env;
-use std::str::from_utf8;
+use sincere::app::context::Context;
+use std::sync::Arc;
 
-/// Starts the POST API server which can be made publicly available without security risk
+/// Returns the value of the request header `name` (if provided)
+fn request_header(context: &Context, name: &str) -> Option<String> {
+    context.request.get_header(name)
+}
+
+/// Sets the CORS headers of a POST API response if the request's origin is allowed
+fn set_cors_headers(context: &mut Context, access: &PostApiAccess) {
+    let origin = request_header(context, "Origin");
+    if let Some(allowed_origin) = access.cors_origin(origin.as_deref()) {
+        context
+            .response
+            .header(("Access-Control-Allow-Origin", allowed_origin))
+            .header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+            .header((
+                "Access-Control-Allow-Headers",
+                "Content-Type, X-Api-Key, X-Timestamp, X-Signature",
+            ))
+            .header(("Vary", "Origin"));
+    }
+}
+
+/// Creates the `TlsProxy` of an API server if TLS is enabled in the config
+pub fn api_tls_proxy(verify_clients: bool) -> Option<Arc<TlsProxy>> {
+    TlsSettings::new().map(|settings| {
+        Arc::new(
+            new_tls_proxy(&settings, verify_clients)
+                .expect("Failed to set up TLS for the API server."),
+        )
+    })
+}
+
+/// Checks whether the client of a POST API request may use the POST API
+fn check_post_access(
+    context: &Context,
+    access: &PostApiAccess,
+    tls: &Option<Arc<TlsProxy>>,
+) -> std::result::Result<(), AccessError> {
+    let credentials = RequestCredentials {
+        api_key: request_header(context, "X-Api-Key"),
+        timestamp: request_header(context, "X-Timestamp"),
+        signature: request_header(context, "X-Signature"),
+    };
+    // Connections forwarded by the TLS proxy come from the local machine,
+    // and any other connection to the local-only server is rejected
+    let peer = context.request.remote_addr();
+    let client = match tls.as_ref().map(|proxy| proxy.internal_connection(peer)) {
+        Some(InternalConnection::Forwarded(client)) => client,
+        Some(_) => return Err(AccessError::AddressNotAllowed(peer.ip())),
+        None => peer.ip(),
+    };
+    access.check(client, &credentials, context.request.body())
+}
+
+/// Starts the POST API server. Requests are checked against the `post_api_*`
+/// access settings, and the server binds to `post_api_bind_address` (local only by default).
 pub fn start_post_api() {
     let mut app = sincere::App::new();
-    let args: Vec<String> = env::args().collect();
+    let settings = PostApiSettings::new();
+    let bind_address = settings.bind_address.clone();
+    let access = Arc::new(PostApiAccess::new(settings));
+    let tls = api_tls_proxy(true);
+
+    // Answer CORS preflight requests
+    for path in &[
+        "/submitDatapoint",
+        "/v1/submitDatapoint",
+        "/governanceVote",
+        "/v1/governanceVote",
+    ] {
+        let preflight_access = access.clone();
+        app.options(path, move |context| {
+            set_cors_headers(context, &preflight_access);
+            context.response.status_code(204);
+        });
+    }
 
     // Accept a datapoint to be posted within a "Commit Datapoint" action tx
+    let (legacy_access, legacy_tls) = (access.clone(), tls.clone());
     app.post("/submitDatapoint", move |context| {
-        let op = OraclePool::new();
-        let res_post_json = from_utf8(context.request.body()).map(|t| json::parse(t));
-
-        // Check if oracle core is in `read only` mode
-        if args.len() > 1 && &args[1] == "--readonly" {
-            let error_json = object! {error: "Oracle Core is in `read only` mode."}.to_string();
+        set_cors_headers(context, &legacy_access);
 
+        // Reject the request if the client may not use the POST API
+        if let Err(e) = check_post_access(context, &legacy_access, &legacy_tls) {
+            let error_json = object! {error: e.to_string()}.to_string();
             context
                 .response
-                .header(("Access-Control-Allow-Origin", "*"))
-                .from_json(error_json).unwrap();
+                .status_code(e.status_code())
+                .from_json(error_json)
+                .unwrap();
+            return;
         }
 
-        // If the post request body is valid json
-        if let Ok(Ok(post_json)) = res_post_json {
-            // If the datapoint provided is a valid Integer
-            if let Ok(datapoint) = post_json["datapoint"].to_string().parse() {
-                // Check if in Live Epoch stage
-                if let PoolBoxState::LiveEpoch = op.check_oracle_pool_stage() {
-                    if let Ok(epoch_state) = op.get_live_epoch_state() {
-                    let old_datapoint = epoch_state.latest_pool_datapoint;
-
-                    // Difference calc
-                    let difference = datapoint as f64/old_datapoint as f64;
-                    let mut action_result = Err(anyhow!("No datapoint has been submit."));
-
-
-                    // If the new datapoint is twice as high, post the new datapoint
-                    if difference > 2.00 {
-                        action_result = op.action_commit_datapoint(datapoint);
-                    }
-                    // If the new datapoint is half, post the new datapoint
-                    else if difference < 0.50 {
-                        action_result = op.action_commit_datapoint(datapoint);
-                    }
-                    // If the new datapoint is 0.49% to 50% lower, post 0.49% lower than old
-                    else if difference < 0.9951 {
-                        let new_datapoint = (old_datapoint as f64 * 0.9951) as u64;
-                        action_result = op.action_commit_datapoint(new_datapoint);
-                    }
-                    // If the new datapoint is 0.49% to 100% higher, post 0.49% higher than old
-                    else if difference > 1.0049 {
-                        let new_datapoint = (old_datapoint as f64 * 1.0049) as u64;
-                        action_result = op.action_commit_datapoint(new_datapoint);
-                    }
-                    // Else if the difference is within 0.49% either way, post the new datapoint
-                    else {
-                        action_result = op.action_commit_datapoint(datapoint);
-                    }
-
-
-                    // Print action
-                    let action_name = "Submit Datapoint";
-                    print_action_results(&action_result, action_name);
-                    // If transaction succeeded being posted
-                    if let Ok(res) = action_result{
-                        let tx_id: String = res.chars().filter(|&c| c != '\"').collect();
-                        let resp_json = object! {tx_id: tx_id}.to_string();
-
-                    context
-                        .response
-                        .header(("Access-Control-Allow-Origin", "*")).from_json(resp_json).unwrap();
-                    }
-                    // If transaction failed being posted
-                    else {
-                        let error_json = object! {error: "Failed to post 'Commit Datapoint' action transaction."}.to_string();
-                        context
-                            .response
-                            .header(("Access-Control-Allow-Origin", "*")).from_json(error_json).unwrap();
-                    }
-                }
-                // Else if in Epoch Prep stage
-                else {
-                    let error_json = object! {error: "Unable to submit Datapoint. The Oracle Pool is currently in the Epoch Preparation Stage."}.to_string();
-
-                    context
-                        .response
-                        .header(("Access-Control-Allow-Origin", "*")).from_json(error_json).unwrap();
-                }
-            }
-            // If the datapoint provided is not a valid i32 Integer
-            else {
-                let error_json = object! {error: "Invalid Datapoint Provided. Please ensure that your request includes a valid Integer i32 'datapoint' field."}.to_string();
-
-                context
-                    .response
-                    .header(("Access-Control-Allow-Origin", "*")).from_json(error_json).unwrap();
-                }
-            }
+        // Other failures are returned with an `error` field (and status 200)
+        // as existing connectors expect
+        let resp_json = match api_v1::submit_datapoint(context.request.body()) {
+            Ok(resp) => serde_json::to_string(&resp).unwrap_or_default(),
+            Err(e) => object! {error: e.message}.to_string(),
+        };
+        context.response.from_json(resp_json).unwrap();
+    });
 
-        }
-        // If the post request body is not valid json
-        else {
-            let error_json = object! {error: "Invalid JSON Request Body."}.to_string();
+    // Cast/clear the local oracle's vote for a new payout price (governance pools only)
+    let (legacy_access, legacy_tls) = (access.clone(), tls.clone());
+    app.post("/governanceVote", move |context| {
+        set_cors_headers(context, &legacy_access);
 
+        // Reject the request if the client may not use the POST API
+        if let Err(e) = check_post_access(context, &legacy_access, &legacy_tls) {
+            let error_json = object! {error: e.to_string()}.to_string();
             context
                 .response
-                .header(("Access-Control-Allow-Origin", "*")).from_json(error_json).unwrap();
+                .status_code(e.status_code())
+                .from_json(error_json)
+                .unwrap();
+            return;
         }
+
+        let resp_json = match api_v1::governance_vote(context.request.body()) {
+            Ok(vote) => serde_json::to_string(&vote).unwrap_or_default(),
+            Err(e) => object! {error: e.message}.to_string(),
+        };
+        context.response.from_json(resp_json).unwrap();
+    });
+
+    // The `/v1` versions of the endpoints above
+    let request_tls = tls.clone();
+    api_v1::add_post_routes(&mut app, move |context| {
+        set_cors_headers(context, &access);
+        check_post_access(context, &access, &request_tls).map_err(ApiError::from)
     });
 
     // Start the POST API server with the port designated in the config + 1.
@@ -123,12 +149,37 @@ pub fn start_post_api() {
         .expect("Failed to parse oracle core port from config to u16."))
         + 1)
     .to_string();
-    let address = "0.0.0.0:".to_string() + &port;
-    app.run(&address, 1).ok();
+    let address = bind_address + ":" + &port;
+    run_app(&mut app, &address, tls);
+}
+
+/// Responds to a legacy GET request with the json of `result`. Failures are
+/// returned with an `error` field (and status 200) as existing connectors expect.
+fn respond_legacy<T: Serialize>(context: &mut Context, result: ApiResult<T>) {
+    let response_json = match result {
+        Ok(body) => serde_json::to_string(&body).unwrap_or_default(),
+        Err(e) => object! {error: e.message}.to_string(),
+    };
+    context
+        .response
+        .header(("Access-Control-Allow-Origin", "*"))
+        .from_json(response_json)
+        .unwrap();
 }
 
-/// Starts the GET API server which can be made publicly available without security risk
-pub fn start_get_api(repost_receiver: Receiver<bool>) {
+/// Responds to a legacy GET request with plain text
+fn respond_text(context: &mut Context, response_text: String) {
+    context
+        .response
+        .header(("Access-Control-Allow-Origin", "*"))
+        .from_text(response_text)
+        .unwrap();
+}
+
+/// Starts the GET API server which can be made publicly available without security risk.
+/// The legacy endpoints are served by the `/v1` API, with their responses kept in
+/// the format existing connectors expect.
+pub fn start_get_api() {
     let mut app = sincere::App::new();
 
     // Basic welcome endpoint
@@ -136,183 +187,115 @@ pub fn start_get_api(repost_receiver: Receiver<bool>) {
         let response_text = format!(
             "This is an Oracle Core. Please use one of the endpoints to interact with it.\n"
         );
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_text(response_text)
-            .unwrap();
+        respond_text(context, response_text);
     });
 
     // Basic oracle information
     app.get("/oracleInfo", move |context| {
-        let op = OraclePool::new();
-        let response_json = object! {
-            oracle_address: op.local_oracle_address,
-        };
-
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_json(response_json.dump())
-            .unwrap();
+        respond_legacy(context, api_v1::oracle_info(&OraclePool::new()));
     });
 
     // Basic information about the oracle pool
     app.get("/poolInfo", move |context| {
-        let op = OraclePool::new();
-        let parameters = PoolParameters::new();
-
-        let num_of_oracles = match op.datapoint_stage.number_of_boxes() {
-            Ok(n) => n,
-            Err(_) => 10,
-        };
-
-        let response_json = object! {
-            number_of_oracles: num_of_oracles,
-            live_epoch_address: op.live_epoch_stage.contract_address,
-            epoch_prep_address: op.epoch_preparation_stage.contract_address,
-            pool_deposits_address: op.pool_deposit_stage.contract_address,
-            datapoint_address: op.datapoint_stage.contract_address,
-            oracle_payout_price: parameters.oracle_payout_price,
-            live_epoch_length: parameters.live_epoch_length,
-            epoch_prep_length: parameters.epoch_preparation_length,
-            deviation_range: parameters.deviation_range,
-            consensus_num: parameters.consensus_num,
-            minimum_pool_box_value: parameters.minimum_pool_box_value,
-            oracle_pool_nft_id: op.oracle_pool_nft,
-            oracle_pool_participant_token_id: op.oracle_pool_participant_token,
-
-        };
-
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_json(response_json.dump())
-            .unwrap();
+        let result = api_v1::pool_info(&OraclePool::new(), &PoolParameters::new()).map(|info| {
+            api_v1::PoolInfo {
+                number_of_oracles: info.number_of_oracles.or(Some(10)),
+                ..info
+            }
+        });
+        respond_legacy(context, result);
     });
 
     // Basic information about node the oracle core is using
     app.get("/nodeInfo", move |context| {
-        let response_json = object! {
-            node_url: get_node_url(),
-        };
-
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_json(response_json.dump())
-            .unwrap();
+        respond_legacy(context, api_v1::node_info());
     });
 
-    // Status of the oracle
+    // Status of the oracle (defaults in observer mode)
     app.get("/oracleStatus", move |context| {
-        let op = OraclePool::new();
-
-        // Check whether waiting for datapoint to be submit to oracle core
-        let waiting_for_submit = match op.get_live_epoch_state() {
-            Ok(l) => !l.commit_datapoint_in_epoch,
-            Err(_) => false,
-        };
-        // Get latest datapoint the local oracle produced/submit
-        let self_datapoint = match op.get_datapoint_state() {
-            Ok(d) => d.datapoint,
-            Err(_) => 0,
-        };
-        // Get latest datapoint submit epoch
-        let datapoint_epoch = match op.get_datapoint_state() {
-            Ok(d) => d.origin_epoch_id,
-            Err(_) => "Null".to_string(),
-        };
-        // Get latest datapoint submit epoch
-        let datapoint_creation = match op.get_datapoint_state() {
-            Ok(d) => d.creation_height,
-            Err(_) => 0,
-        };
+        let status = api_v1::oracle_status(&OraclePool::new(), &PoolParameters::new()).ok();
+        let latest_datapoint = status.as_ref().and_then(|s| s.latest_datapoint.as_ref());
+        let response_json = json!({
+            "waiting_for_datapoint_submit": status.as_ref().map_or(false, |s| s.waiting_for_datapoint_submit),
+            "latest_datapoint": latest_datapoint.map_or(0, |d| d.datapoint),
+            "latest_datapoint_epoch": latest_datapoint.map_or_else(|| "Null".to_string(), |d| d.origin_epoch_id.clone()),
+            "latest_datapoint_creation_height": latest_datapoint.map_or(0, |d| d.creation_height),
+            "collector_rank": status.as_ref().and_then(|s| s.collector_rank),
+            "collection_height": status.as_ref().and_then(|s| s.collection_height),
+        });
+        respond_legacy(context, Ok(response_json));
+    });
 
-        let response_json = object! {
-            waiting_for_datapoint_submit: waiting_for_submit,
-            latest_datapoint: self_datapoint,
-            latest_datapoint_epoch: datapoint_epoch,
-            latest_datapoint_creation_height: datapoint_creation,
-        };
+    // Collateral held by each oracle (stake slashing pools only)
+    app.get("/oracleStakes", move |context| {
+        respond_legacy(
+            context,
+            api_v1::oracle_stakes(&OraclePool::new(), &PoolParameters::new()),
+        );
+    });
 
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_json(response_json.dump())
-            .unwrap();
+    // Oracle payout price votes (governance pools only)
+    app.get("/governanceState", move |context| {
+        respond_legacy(
+            context,
+            api_v1::governance_state(&OraclePool::new(), &PoolParameters::new()),
+        );
     });
 
     // Status of the oracle pool
     app.get("/poolStatus", move |context| {
-        let op = OraclePool::new();
-        let parameters = PoolParameters::new();
-
-        // Current stage of the oracle pool box
-        let current_stage = match op.check_oracle_pool_stage() {
-            PoolBoxState::LiveEpoch => "Live Epoch",
-            PoolBoxState::Preparation => "Epoch Preparation",
-        };
-
-        let mut funded_percentage = 0;
-        let mut latest_datapoint = 0;
-        let mut current_epoch_id = "".to_string();
-        let mut epoch_ends = 0;
-        if let Ok(l) = op.get_live_epoch_state() {
-            // The percentage that the pool is funded
-            funded_percentage = (l.funds / parameters.minimum_pool_box_value) * 100;
-            latest_datapoint = l.latest_pool_datapoint;
-            current_epoch_id = l.epoch_id;
-            epoch_ends = l.epoch_ends;
-        } else if let Ok(ep) = op.get_preparation_state() {
-            // The percentage that the pool is funded
-            funded_percentage = (ep.funds / parameters.minimum_pool_box_value) * 100;
-            latest_datapoint = ep.latest_pool_datapoint;
-            current_epoch_id = "Preparing Epoch Currently".to_string();
-            epoch_ends = ep.next_epoch_ends;
-        }
+        let result = api_v1::pool_status(&OraclePool::new(), &PoolParameters::new()).map(|s| {
+            let current_stage = match s.current_pool_stage {
+                PoolStage::LiveEpoch => "Live Epoch",
+                PoolStage::EpochPreparation => "Epoch Preparation",
+            };
+            json!({
+                "funded_percentage": s.funded_percentage as u64,
+                "current_pool_stage": current_stage,
+                "latest_datapoint": s.latest_datapoint,
+                "current_epoch_id": s.current_epoch_id.unwrap_or_else(|| "Preparing Epoch Currently".to_string()),
+                "epoch_ends": s.epoch_ends,
+                "next_epoch_starts": s.next_epoch_starts,
+                "next_collection": s.next_collection,
+                "average_block_interval": s.average_block_interval,
+                "epoch_ends_time": s.epoch_ends_time,
+                "next_epoch_starts_time": s.next_epoch_starts_time,
+                "next_collection_time": s.next_collection_time,
+            })
+        });
+        respond_legacy(context, result);
+    });
 
-        let response_json = object! {
-            funded_percentage: funded_percentage,
-            current_pool_stage: current_stage,
-            latest_datapoint: latest_datapoint,
-            current_epoch_id : current_epoch_id,
-            epoch_ends: epoch_ends,
-        };
+    // Latest datapoint posted by every oracle in the pool
+    app.get("/datapoints", move |context| {
+        respond_legacy(context, api_v1::datapoints(&OraclePool::new()));
+    });
 
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_json(response_json.dump())
-            .unwrap();
+    // Datapoints finalized by the oracle pool since the core was first started
+    app.get("/poolHistory", move |context| {
+        respond_legacy(context, api_v1::pool_history());
     });
 
     // Block height of the Ergo blockchain
     app.get("/blockHeight", move |context| {
-        let current_height =
-            current_block_height().expect("Please ensure that the Ergo node is running.");
-        let response_text = format!("{}", current_height);
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_text(response_text)
-            .unwrap();
+        match api_v1::block_height() {
+            Ok(height) => respond_text(context, height.block_height.to_string()),
+            Err(e) => respond_legacy::<()>(context, Err(e)),
+        }
     });
 
     // Whether the Core requires the Connector to repost a new Datapoint
     app.get("/requireDatapointRepost", move |context| {
-        let mut response_text = format!("false");
-        if let Ok(b) = repost_receiver.try_recv() {
-            response_text = b.to_string();
-        }
-        context
-            .response
-            .header(("Access-Control-Allow-Origin", "*"))
-            .from_text(response_text)
-            .unwrap();
+        let repost_required = api_v1::datapoint_repost()
+            .map(|r| r.repost_required)
+            .unwrap_or(false);
+        respond_text(context, repost_required.to_string());
     });
 
+    // The versioned API & its OpenAPI document
+    api_v1::add_get_routes(&mut app);
+
     // Start the API server with the port designated in the config.
-    app.run(&("0.0.0.0:".to_string() + &get_core_api_port()), 1)
-        .ok();
+    let address = get_core_api_bind_address() + ":" + &get_core_api_port();
+    run_app(&mut app, &address, api_tls_proxy(false));
 }
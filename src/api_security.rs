@@ -0,0 +1,244 @@
+/// This file holds the access control of the POST API server. Each request is
+/// checked against the `post_api_*` settings of the `oracle-config.yaml`:
+/// - The client IP address must be in `post_api_allowed_ips` (if set)
+/// - The `X-Api-Key` header must hold the `post_api_key` (if set)
+/// - The `X-Timestamp` & `X-Signature` headers must hold a recent timestamp
+///   and the signature of the request made with `post_api_signing_secret` (if set)
+/// - The client must not exceed `post_api_rate_limit` requests per minute
+use crate::oracle_config::PostApiSettings;
+use blake2b_simd::Params;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Number of seconds a signed request's timestamp may differ from the local clock
+static SIGNATURE_TIMESTAMP_WINDOW: u64 = 60;
+/// The window the rate limit applies to
+static RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AccessError {
+    #[error("Requests from {0} are not allowed.")]
+    AddressNotAllowed(IpAddr),
+    #[error(
+        "Missing or invalid API key. Please provide the `post_api_key` in the `X-Api-Key` header."
+    )]
+    InvalidApiKey(),
+    #[error("Missing or invalid request signature in the `X-Signature` header.")]
+    InvalidSignature(),
+    #[error("Missing or expired request timestamp in the `X-Timestamp` header.")]
+    InvalidTimestamp(),
+    #[error("Rate limit of {0} requests per minute exceeded.")]
+    RateLimited(u64),
+}
+
+impl AccessError {
+    /// The HTTP status code of the response to a rejected request
+    pub fn status_code(&self) -> u16 {
+        match self {
+            AccessError::AddressNotAllowed(_) => 403,
+            AccessError::RateLimited(_) => 429,
+            _ => 401,
+        }
+    }
+}
+
+/// The credentials a POST API request was sent with
+#[derive(Debug, Clone, Default)]
+pub struct RequestCredentials {
+    pub api_key: Option<String>,
+    pub timestamp: Option<String>,
+    pub signature: Option<String>,
+}
+
+/// Access control of the POST API server, shared between request handlers
+pub struct PostApiAccess {
+    pub settings: PostApiSettings,
+    /// Times of the recent requests of each client
+    requests: Mutex<HashMap<IpAddr, Vec<Instant>>>,
+}
+
+impl PostApiAccess {
+    pub fn new(settings: PostApiSettings) -> PostApiAccess {
+        PostApiAccess {
+            settings: settings,
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks whether a request from `client` may be processed
+    pub fn check(
+        &self,
+        client: IpAddr,
+        credentials: &RequestCredentials,
+        body: &[u8],
+    ) -> std::result::Result<(), AccessError> {
+        if !self.settings.allowed_ips.is_empty() && !self.settings.allowed_ips.contains(&client) {
+            return Err(AccessError::AddressNotAllowed(client));
+        }
+        self.check_rate_limit(client, Instant::now())?;
+        if let Some(api_key) = &self.settings.api_key {
+            match &credentials.api_key {
+                Some(key) if constant_time_eq(key.as_bytes(), api_key.as_bytes()) => (),
+                _ => return Err(AccessError::InvalidApiKey()),
+            }
+        }
+        if let Some(secret) = &self.settings.signing_secret {
+            check_signature(secret, credentials, body, unix_time())?;
+        }
+        Ok(())
+    }
+
+    /// Records a request from `client` and checks it against the rate limit
+    fn check_rate_limit(
+        &self,
+        client: IpAddr,
+        now: Instant,
+    ) -> std::result::Result<(), AccessError> {
+        let limit = self.settings.rate_limit_per_minute;
+        if limit == 0 {
+            return Ok(());
+        }
+        let mut requests = self.requests.lock().unwrap();
+        // Forget clients which have not sent a request within the window
+        requests.retain(|_, times| {
+            times.retain(|t| now.duration_since(*t) < RATE_LIMIT_WINDOW);
+            !times.is_empty()
+        });
+        let times = requests.entry(client).or_insert(vec![]);
+        if times.len() as u64 >= limit {
+            return Err(AccessError::RateLimited(limit));
+        }
+        times.push(now);
+        Ok(())
+    }
+
+    /// The `Access-Control-Allow-Origin` header value for a request from
+    /// `origin`, if the origin is allowed to make cross-origin requests
+    pub fn cors_origin(&self, origin: Option<&str>) -> Option<String> {
+        let origins = &self.settings.cors_origins;
+        if origins.iter().any(|o| o == "*") {
+            return Some("*".to_string());
+        }
+        origin
+            .filter(|origin| origins.iter().any(|o| o == origin))
+            .map(|origin| origin.to_string())
+    }
+}
+
+/// Signs a POST API request. The signature is the hex encoded keyed
+/// Blake2b-256 hash of `<timestamp>.<body>`, keyed with the signing secret.
+pub fn sign_request(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    Params::new()
+        .hash_length(32)
+        .key(secret.as_bytes())
+        .hash(&message)
+        .to_hex()
+        .to_string()
+}
+
+/// Checks the timestamp & signature of a request at the unix time `now`
+fn check_signature(
+    secret: &str,
+    credentials: &RequestCredentials,
+    body: &[u8],
+    now: u64,
+) -> std::result::Result<(), AccessError> {
+    let timestamp = credentials
+        .timestamp
+        .as_ref()
+        .ok_or(AccessError::InvalidTimestamp())?;
+    let time: u64 = timestamp
+        .parse()
+        .map_err(|_| AccessError::InvalidTimestamp())?;
+    if time.max(now) - time.min(now) > SIGNATURE_TIMESTAMP_WINDOW {
+        return Err(AccessError::InvalidTimestamp());
+    }
+    let expected = sign_request(secret, timestamp, body);
+    match &credentials.signature {
+        Some(sig) if constant_time_eq(sig.to_lowercase().as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(AccessError::InvalidSignature()),
+    }
+}
+
+/// Compares two byte strings without returning early on the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The current unix time in seconds
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> PostApiSettings {
+        PostApiSettings {
+            bind_address: "127.0.0.1".to_string(),
+            api_key: Some("key".to_string()),
+            signing_secret: None,
+            allowed_ips: vec!["127.0.0.1".parse().unwrap()],
+            rate_limit_per_minute: 2,
+            cors_origins: vec!["https://oracle.example.com".to_string()],
+        }
+    }
+
+    #[test]
+    fn requests_are_checked() {
+        let access = PostApiAccess::new(settings());
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let mut credentials = RequestCredentials::default();
+        assert_eq!(
+            access.check("10.0.0.2".parse().unwrap(), &credentials, b""),
+            Err(AccessError::AddressNotAllowed("10.0.0.2".parse().unwrap()))
+        );
+        assert_eq!(
+            access.check(local, &credentials, b""),
+            Err(AccessError::InvalidApiKey())
+        );
+        credentials.api_key = Some("key".to_string());
+        assert_eq!(access.check(local, &credentials, b""), Ok(()));
+        assert_eq!(
+            access.check(local, &credentials, b""),
+            Err(AccessError::RateLimited(2))
+        );
+        assert_eq!(access.cors_origin(Some("https://evil.example.com")), None);
+        assert_eq!(
+            access.cors_origin(Some("https://oracle.example.com")),
+            Some("https://oracle.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn signatures_are_checked() {
+        let body = br#"{"datapoint": 123456}"#;
+        let credentials = RequestCredentials {
+            api_key: None,
+            timestamp: Some("1000".to_string()),
+            signature: Some(sign_request("secret", "1000", body)),
+        };
+        assert_eq!(check_signature("secret", &credentials, body, 1030), Ok(()));
+        assert_eq!(
+            check_signature("secret", &credentials, body, 1100),
+            Err(AccessError::InvalidTimestamp())
+        );
+        assert_eq!(
+            check_signature("other", &credentials, body, 1000),
+            Err(AccessError::InvalidSignature())
+        );
+        assert_eq!(
+            check_signature("secret", &credentials, b"{}", 1000),
+            Err(AccessError::InvalidSignature())
+        );
+    }
+}
@@ -0,0 +1,953 @@
+/// This file holds the versioned `/v1` API of the oracle core. Unlike the
+/// legacy endpoints (which are kept as is for existing connectors), every
+/// `/v1` endpoint responds with a typed json body, failures use proper HTTP
+/// status codes along with a machine-readable `ApiErrorCode`, and the API is
+/// described by the OpenAPI document served at `/openapi.json`, which is
+/// generated from the response types below.
+use crate::api_security::AccessError;
+use crate::block_time::{current_block_times, EpochSchedule};
+use crate::funding::FundingForecast;
+use crate::governance::save_local_vote;
+use crate::node_interface::{current_block_height, raw_from_register_to_address};
+use crate::oracle_config::{get_node_url, is_dry_run_action, is_observer_mode, PoolParameters};
+use crate::oracle_state::{OraclePool, PoolBoxState};
+use crate::pool_history::get_pool_history;
+use crate::print_action_results;
+use crate::{BlockHeight, EpochID, NanoErg};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sincere::app::context::Context;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the core requires the connector to repost a new datapoint. Set when
+/// collecting the datapoints failed, and cleared once a datapoint is submitted,
+/// thus every endpoint reports it until the connector reposts.
+static REPOST_REQUIRED: AtomicBool = AtomicBool::new(false);
+
+/// A machine-readable code identifying why a request failed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ApiErrorCode {
+    /// The request body is not valid json/is missing fields
+    InvalidRequest,
+    /// The submitted datapoint is not a valid integer
+    InvalidDatapoint,
+    /// The request is missing valid POST API credentials
+    Unauthorized,
+    /// The client address may not use the POST API
+    Forbidden,
+    /// The client exceeded the POST API rate limit
+    RateLimited,
+    /// The core runs in observer mode and has no local oracle
+    NoLocalOracle,
+    /// The oracle pool does not use governance
+    NoGovernance,
+    /// The oracle pool is not in a stage which allows the request
+    WrongPoolStage,
+    /// The state of the oracle pool could not be read from the node
+    PoolStateUnavailable,
+    /// The Ergo node could not be reached
+    NodeUnavailable,
+    /// The action transaction failed to be created/posted
+    ActionFailed,
+}
+
+impl ApiErrorCode {
+    /// The HTTP status code of responses with this error code
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ApiErrorCode::InvalidRequest => 400,
+            ApiErrorCode::InvalidDatapoint => 400,
+            ApiErrorCode::Unauthorized => 401,
+            ApiErrorCode::Forbidden => 403,
+            ApiErrorCode::NoLocalOracle => 404,
+            ApiErrorCode::NoGovernance => 404,
+            ApiErrorCode::WrongPoolStage => 409,
+            ApiErrorCode::RateLimited => 429,
+            ApiErrorCode::ActionFailed => 500,
+            ApiErrorCode::PoolStateUnavailable => 503,
+            ApiErrorCode::NodeUnavailable => 503,
+        }
+    }
+}
+
+/// The error of a failed request
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ApiError {
+    pub code: ApiErrorCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: ApiErrorCode, message: &str) -> ApiError {
+        ApiError {
+            code: code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<AccessError> for ApiError {
+    fn from(e: AccessError) -> ApiError {
+        let code = match e {
+            AccessError::AddressNotAllowed(_) => ApiErrorCode::Forbidden,
+            AccessError::RateLimited(_) => ApiErrorCode::RateLimited,
+            _ => ApiErrorCode::Unauthorized,
+        };
+        ApiError::new(code, &e.to_string())
+    }
+}
+
+/// The body of every failed `/v1` response
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ErrorResponse {
+    pub error: ApiError,
+}
+
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// Information about the local oracle
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OracleInfo {
+    /// The local oracle's address (null in observer mode)
+    pub oracle_address: Option<String>,
+    pub observer_mode: bool,
+}
+
+/// Information about the oracle pool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PoolInfo {
+    pub number_of_oracles: Option<u64>,
+    pub live_epoch_address: String,
+    pub epoch_prep_address: String,
+    pub pool_deposits_address: String,
+    pub datapoint_address: String,
+    pub oracle_payout_price: NanoErg,
+    pub live_epoch_length: u64,
+    pub epoch_prep_length: u64,
+    pub deviation_range: u64,
+    pub consensus_num: u64,
+    pub minimum_pool_box_value: NanoErg,
+    pub stake_slashing: bool,
+    pub minimum_collateral: NanoErg,
+    pub governance: bool,
+    /// `v0.1` or `v0.2`
+    pub protocol_version: String,
+    pub oracle_pool_nft_id: String,
+    /// null for observers without the participant token configured
+    pub oracle_pool_participant_token_id: Option<String>,
+}
+
+/// Information about the node the oracle core uses
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct NodeInfo {
+    pub node_url: String,
+}
+
+/// A datapoint posted by an oracle
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Datapoint {
+    /// The oracle's address (null if the register could not be decoded)
+    pub oracle_address: Option<String>,
+    pub datapoint: u64,
+    pub origin_epoch_id: EpochID,
+    pub creation_height: BlockHeight,
+    pub posted_in_current_epoch: bool,
+}
+
+/// Status of the local oracle
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OracleStatus {
+    pub waiting_for_datapoint_submit: bool,
+    /// The latest datapoint the local oracle posted (null if it never posted one)
+    pub latest_datapoint: Option<Datapoint>,
+    /// Collector rank in the current epoch (1 collects first)
+    pub collector_rank: Option<u64>,
+    /// Height from which the local oracle may collect datapoints
+    pub collection_height: Option<BlockHeight>,
+}
+
+/// The stage the oracle pool box is in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PoolStage {
+    LiveEpoch,
+    EpochPreparation,
+}
+
+/// Status of the oracle pool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PoolStatus {
+    /// The pool's funds relative to the minimum pool box value (percent)
+    pub funded_percentage: f64,
+    pub current_pool_stage: PoolStage,
+    pub latest_datapoint: u64,
+    /// The id of the current Live Epoch (null during Epoch Preparation)
+    pub current_epoch_id: Option<EpochID>,
+    /// Height the current/upcoming epoch ends
+    pub epoch_ends: BlockHeight,
+    /// Height the next Live Epoch may start
+    pub next_epoch_starts: BlockHeight,
+    /// Height from which the datapoints of the current/upcoming epoch may be collected
+    pub next_collection: BlockHeight,
+    /// Average interval between the latest blocks in seconds (null if the
+    /// node headers are unavailable, as are the estimated times below)
+    pub average_block_interval: Option<f64>,
+    /// Estimated unix time (seconds) the current/upcoming epoch ends
+    pub epoch_ends_time: Option<u64>,
+    /// Estimated unix time (seconds) the next Live Epoch may start
+    pub next_epoch_starts_time: Option<u64>,
+    /// Estimated unix time (seconds) the datapoints may be collected
+    pub next_collection_time: Option<u64>,
+}
+
+/// The latest datapoint of every oracle in the pool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Datapoints {
+    pub current_epoch_id: Option<EpochID>,
+    pub datapoints: Vec<Datapoint>,
+}
+
+/// A datapoint finalized by the oracle pool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PoolHistoryEntry {
+    pub box_id: String,
+    pub datapoint: u64,
+    pub epoch_ends: BlockHeight,
+    pub creation_height: BlockHeight,
+}
+
+/// The datapoints finalized by the oracle pool (oldest first)
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PoolHistory {
+    pub history: Vec<PoolHistoryEntry>,
+}
+
+/// The collateral held by an oracle
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OracleStake {
+    pub oracle_address: Option<String>,
+    pub collateral: NanoErg,
+    pub latest_datapoint_epoch: EpochID,
+}
+
+/// The collateral of every oracle in the pool (stake slashing pools only)
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OracleStakes {
+    pub stake_slashing: bool,
+    pub minimum_collateral: NanoErg,
+    pub stakes: Vec<OracleStake>,
+    /// Uncommitted oracles which can be slashed (Epoch Preparation stage only)
+    pub uncommitted_oracles: Option<u64>,
+    /// Datapoints missed by the latest collector (Epoch Preparation stage only)
+    pub datapoints_missed_by_collector: Option<u64>,
+}
+
+/// An oracle's payout price vote
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OracleVote {
+    pub oracle_address: Option<String>,
+    pub payout_price: Option<NanoErg>,
+}
+
+/// The state of the oracle payout price vote (governance pools only)
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct GovernanceState {
+    pub governance: bool,
+    pub current_payout_price: Option<NanoErg>,
+    pub votes: Vec<OracleVote>,
+    pub vote_threshold: Option<u64>,
+    pub resulting_payout_price: Option<NanoErg>,
+    pub local_vote: Option<NanoErg>,
+}
+
+/// The current block height of the Ergo blockchain
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct BlockHeightResponse {
+    pub block_height: BlockHeight,
+}
+
+/// Whether the core requires the connector to repost a new datapoint
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DatapointRepost {
+    pub repost_required: bool,
+}
+
+/// The body of a datapoint submission
+#[derive(Debug, Clone, JsonSchema)]
+pub struct SubmitDatapointRequest {
+    pub datapoint: u64,
+}
+
+/// The result of a datapoint submission
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SubmitDatapointResponse {
+    /// Id of the posted "Commit Datapoint" tx (null when dry running)
+    pub tx_id: Option<String>,
+    /// The tx report when the "Commit Datapoint" action is dry run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<Value>,
+}
+
+/// The body of a payout price vote (governance pools only)
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct GovernanceVote {
+    /// The voted payout price in nanoErgs (null clears the vote)
+    pub payout_price: Option<NanoErg>,
+}
+
+fn pool_state_unavailable(e: anyhow::Error) -> ApiError {
+    ApiError::new(ApiErrorCode::PoolStateUnavailable, &e.to_string())
+}
+
+pub fn oracle_info(op: &OraclePool) -> ApiResult<OracleInfo> {
+    Ok(OracleInfo {
+        oracle_address: op.local_oracle.as_ref().map(|l| l.address.clone()),
+        observer_mode: is_observer_mode(),
+    })
+}
+
+pub fn pool_info(op: &OraclePool, parameters: &PoolParameters) -> ApiResult<PoolInfo> {
+    Ok(PoolInfo {
+        number_of_oracles: op.datapoint_stage.number_of_boxes().ok(),
+        live_epoch_address: op.live_epoch_stage.contract_address.clone(),
+        epoch_prep_address: op.epoch_preparation_stage.contract_address.clone(),
+        pool_deposits_address: op.pool_deposit_stage.contract_address.clone(),
+        datapoint_address: op.datapoint_stage.contract_address.clone(),
+        oracle_payout_price: op
+            .get_oracle_payout_price(parameters)
+            .unwrap_or(parameters.oracle_payout_price),
+        live_epoch_length: parameters.live_epoch_length,
+        epoch_prep_length: parameters.epoch_preparation_length,
+        deviation_range: parameters.deviation_range,
+        consensus_num: parameters.consensus_num,
+        minimum_pool_box_value: parameters.minimum_pool_box_value,
+        stake_slashing: parameters.stake_slashing,
+        minimum_collateral: parameters.minimum_collateral,
+        governance: parameters.governance,
+        protocol_version: parameters.protocol_version.name().to_string(),
+        oracle_pool_nft_id: op.oracle_pool_nft.clone(),
+        oracle_pool_participant_token_id: op.oracle_pool_participant_token.clone(),
+    })
+}
+
+pub fn node_info() -> ApiResult<NodeInfo> {
+    Ok(NodeInfo {
+        node_url: get_node_url(),
+    })
+}
+
+pub fn oracle_status(op: &OraclePool, parameters: &PoolParameters) -> ApiResult<OracleStatus> {
+    if op.local_oracle.is_none() {
+        return Err(ApiError::new(
+            ApiErrorCode::NoLocalOracle,
+            "The oracle core runs in observer mode and has no local oracle.",
+        ));
+    }
+    let live_epoch = op.get_live_epoch_state().ok();
+    let latest_datapoint = op.get_datapoint_state().ok().map(|d| Datapoint {
+        oracle_address: op.local_oracle.as_ref().map(|l| l.address.clone()),
+        datapoint: d.datapoint,
+        posted_in_current_epoch: live_epoch.as_ref().map(|l| &l.epoch_id)
+            == Some(&d.origin_epoch_id),
+        origin_epoch_id: d.origin_epoch_id,
+        creation_height: d.creation_height,
+    });
+    let election = live_epoch
+        .as_ref()
+        .and_then(|l| op.get_collector_election(l, parameters).ok());
+    Ok(OracleStatus {
+        waiting_for_datapoint_submit: live_epoch
+            .as_ref()
+            .map(|l| !l.commit_datapoint_in_epoch)
+            .unwrap_or(false),
+        latest_datapoint: latest_datapoint,
+        collector_rank: election
+            .as_ref()
+            .and_then(|e| e.local_oracle_rank)
+            .map(|r| r as u64 + 1),
+        collection_height: election.and_then(|e| e.local_collection_height),
+    })
+}
+
+pub fn pool_status(op: &OraclePool, parameters: &PoolParameters) -> ApiResult<PoolStatus> {
+    let stage = op.check_oracle_pool_stage();
+    let (funds, current_pool_stage, latest_datapoint, current_epoch_id, epoch_ends) = match stage {
+        PoolBoxState::LiveEpoch => {
+            let l = op.get_live_epoch_state().map_err(pool_state_unavailable)?;
+            (
+                l.funds,
+                PoolStage::LiveEpoch,
+                l.latest_pool_datapoint,
+                Some(l.epoch_id),
+                l.epoch_ends,
+            )
+        }
+        PoolBoxState::Preparation => {
+            let ep = op.get_preparation_state().map_err(pool_state_unavailable)?;
+            (
+                ep.funds,
+                PoolStage::EpochPreparation,
+                ep.latest_pool_datapoint,
+                None,
+                ep.next_epoch_ends,
+            )
+        }
+    };
+    let schedule = EpochSchedule::new(&stage, epoch_ends, parameters);
+    let block_times = current_block_times().ok();
+    let estimated_time = |height| block_times.map(|b| b.estimated_time(height));
+    Ok(PoolStatus {
+        funded_percentage: funded_percentage(funds, parameters),
+        current_pool_stage: current_pool_stage,
+        latest_datapoint: latest_datapoint,
+        current_epoch_id: current_epoch_id,
+        epoch_ends: schedule.epoch_ends,
+        next_epoch_starts: schedule.next_epoch_starts,
+        next_collection: schedule.next_collection,
+        average_block_interval: block_times.map(|b| b.average_block_interval),
+        epoch_ends_time: estimated_time(schedule.epoch_ends),
+        next_epoch_starts_time: estimated_time(schedule.next_epoch_starts),
+        next_collection_time: estimated_time(schedule.next_collection),
+    })
+}
+
+/// The pool's `funds` relative to the minimum pool box value (percent)
+fn funded_percentage(funds: NanoErg, parameters: &PoolParameters) -> f64 {
+    funds as f64 * 100.0 / parameters.minimum_pool_box_value.max(1) as f64
+}
+
+pub fn pool_funding(op: &OraclePool, parameters: &PoolParameters) -> ApiResult<FundingForecast> {
+    op.get_funding_forecast(parameters)
+        .map_err(pool_state_unavailable)
+}
+
+pub fn datapoints(op: &OraclePool) -> ApiResult<Datapoints> {
+    let current_epoch_id = op.get_live_epoch_state().map(|l| l.epoch_id).ok();
+    let datapoints = op
+        .get_all_datapoints()
+        .map_err(pool_state_unavailable)?
+        .into_iter()
+        .map(|d| Datapoint {
+            oracle_address: raw_from_register_to_address(&d.oracle_address_raw).ok(),
+            datapoint: d.datapoint,
+            posted_in_current_epoch: current_epoch_id.as_ref() == Some(&d.origin_epoch_id),
+            origin_epoch_id: d.origin_epoch_id,
+            creation_height: d.creation_height,
+        })
+        .collect();
+    Ok(Datapoints {
+        current_epoch_id: current_epoch_id,
+        datapoints: datapoints,
+    })
+}
+
+pub fn pool_history() -> ApiResult<PoolHistory> {
+    Ok(PoolHistory {
+        history: get_pool_history()
+            .into_iter()
+            .map(|e| PoolHistoryEntry {
+                box_id: e.box_id,
+                datapoint: e.datapoint,
+                epoch_ends: e.epoch_ends,
+                creation_height: e.creation_height,
+            })
+            .collect(),
+    })
+}
+
+pub fn oracle_stakes(op: &OraclePool, parameters: &PoolParameters) -> ApiResult<OracleStakes> {
+    let mut stakes = OracleStakes {
+        stake_slashing: parameters.stake_slashing,
+        minimum_collateral: parameters.minimum_collateral,
+        stakes: vec![],
+        uncommitted_oracles: None,
+        datapoints_missed_by_collector: None,
+    };
+    if !parameters.stake_slashing {
+        return Ok(stakes);
+    }
+    stakes.stakes = op
+        .get_oracle_stakes()
+        .map_err(pool_state_unavailable)?
+        .iter()
+        .map(|s| OracleStake {
+            oracle_address: raw_from_register_to_address(&s.oracle_address_raw).ok(),
+            collateral: s.collateral,
+            latest_datapoint_epoch: s.origin_epoch_id.clone(),
+        })
+        .collect();
+    if let Ok(slashing_state) = op.get_stake_slashing_state() {
+        stakes.uncommitted_oracles = Some(slashing_state.uncommitted.len() as u64);
+        stakes.datapoints_missed_by_collector =
+            Some(slashing_state.missed_by_collector.len() as u64);
+    }
+    Ok(stakes)
+}
+
+pub fn governance_state(
+    op: &OraclePool,
+    parameters: &PoolParameters,
+) -> ApiResult<GovernanceState> {
+    let mut state = GovernanceState {
+        governance: parameters.governance,
+        current_payout_price: None,
+        votes: vec![],
+        vote_threshold: None,
+        resulting_payout_price: None,
+        local_vote: None,
+    };
+    if !parameters.governance {
+        return Ok(state);
+    }
+    let gov_state = op.get_governance_state().map_err(pool_state_unavailable)?;
+    state.current_payout_price = Some(gov_state.current_payout_price);
+    state.votes = gov_state
+        .votes
+        .iter()
+        .map(|v| OracleVote {
+            oracle_address: raw_from_register_to_address(&v.oracle_address_raw).ok(),
+            payout_price: v.payout_price,
+        })
+        .collect();
+    state.vote_threshold = Some(gov_state.vote_threshold);
+    state.resulting_payout_price = Some(gov_state.resulting_payout_price);
+    state.local_vote = gov_state.local_vote;
+    Ok(state)
+}
+
+pub fn block_height() -> ApiResult<BlockHeightResponse> {
+    current_block_height()
+        .map(|h| BlockHeightResponse { block_height: h })
+        .map_err(|_| {
+            ApiError::new(
+                ApiErrorCode::NodeUnavailable,
+                "Please ensure that the Ergo node is running.",
+            )
+        })
+}
+
+pub fn datapoint_repost() -> ApiResult<DatapointRepost> {
+    Ok(DatapointRepost {
+        repost_required: REPOST_REQUIRED.load(Ordering::SeqCst),
+    })
+}
+
+/// Requires the connector to repost a new datapoint
+pub fn require_datapoint_repost() {
+    REPOST_REQUIRED.store(true, Ordering::SeqCst);
+}
+
+/// Commits a datapoint submitted to the POST API. The datapoint is capped to
+/// within 0.49% of the latest pool datapoint unless it differs by over 2x.
+pub fn submit_datapoint(body: &[u8]) -> ApiResult<SubmitDatapointResponse> {
+    let post_json = std::str::from_utf8(body)
+        .ok()
+        .and_then(|t| json::parse(t).ok())
+        .ok_or(ApiError::new(
+            ApiErrorCode::InvalidRequest,
+            "Invalid JSON Request Body.",
+        ))?;
+    // The datapoint may be provided as a json number or string
+    let request = SubmitDatapointRequest {
+        datapoint: post_json["datapoint"].to_string().parse().map_err(|_| {
+            ApiError::new(ApiErrorCode::InvalidDatapoint, "Invalid Datapoint Provided. Please ensure that your request includes a valid Integer i32 'datapoint' field.")
+        })?,
+    };
+    let datapoint = request.datapoint;
+
+    let op = OraclePool::new();
+    if let PoolBoxState::Preparation = op.check_oracle_pool_stage() {
+        return Err(ApiError::new(ApiErrorCode::WrongPoolStage, "Unable to submit Datapoint. The Oracle Pool is currently in the Epoch Preparation Stage."));
+    }
+    let old_datapoint = op
+        .get_live_epoch_state()
+        .map_err(pool_state_unavailable)?
+        .latest_pool_datapoint;
+
+    // Difference calc
+    let difference = datapoint as f64 / old_datapoint as f64;
+    let action_result =
+        // If the new datapoint is twice as high/half, post the new datapoint
+        if difference > 2.00 || difference < 0.50 {
+            op.action_commit_datapoint(datapoint)
+        }
+        // If the new datapoint is 0.49% to 50% lower, post 0.49% lower than old
+        else if difference < 0.9951 {
+            op.action_commit_datapoint((old_datapoint as f64 * 0.9951) as u64)
+        }
+        // If the new datapoint is 0.49% to 100% higher, post 0.49% higher than old
+        else if difference > 1.0049 {
+            op.action_commit_datapoint((old_datapoint as f64 * 1.0049) as u64)
+        }
+        // Else if the difference is within 0.49% either way, post the new datapoint
+        else {
+            op.action_commit_datapoint(datapoint)
+        };
+
+    // Print action
+    print_action_results(&action_result, "Submit Datapoint");
+    if action_result.is_ok() {
+        REPOST_REQUIRED.store(false, Ordering::SeqCst);
+    }
+    match action_result {
+        // If the action is set to dry run, return the tx report (no tx is posted)
+        Ok(report) if is_dry_run_action("Commit Datapoint") => Ok(SubmitDatapointResponse {
+            tx_id: None,
+            dry_run: Some(serde_json::from_str(&report).unwrap_or(Value::Null)),
+        }),
+        Ok(res) => Ok(SubmitDatapointResponse {
+            tx_id: Some(res.chars().filter(|&c| c != '\"').collect()),
+            dry_run: None,
+        }),
+        Err(_) => Err(ApiError::new(
+            ApiErrorCode::ActionFailed,
+            "Failed to post 'Commit Datapoint' action transaction.",
+        )),
+    }
+}
+
+/// Saves the local oracle's vote for a new payout price submitted to the POST
+/// API. The vote is included in every following datapoint commit.
+pub fn governance_vote(body: &[u8]) -> ApiResult<GovernanceVote> {
+    if !PoolParameters::new().governance {
+        return Err(ApiError::new(
+            ApiErrorCode::NoGovernance,
+            "The oracle pool does not use governance.",
+        ));
+    }
+    let post_json = std::str::from_utf8(body)
+        .ok()
+        .and_then(|t| json::parse(t).ok())
+        .ok_or(ApiError::new(
+            ApiErrorCode::InvalidRequest,
+            "Invalid JSON Request Body.",
+        ))?;
+    // A `null` payout price clears the vote
+    let payout_price = &post_json["payout_price"];
+    if !payout_price.is_null() && payout_price.as_u64().is_none() {
+        return Err(ApiError::new(ApiErrorCode::InvalidRequest, "Invalid Vote Provided. Please ensure that your request includes a valid Integer 'payout_price' field (or null to clear the vote)."));
+    }
+    let vote = GovernanceVote {
+        payout_price: payout_price.as_u64(),
+    };
+    save_local_vote(vote.payout_price)
+        .map_err(|_| ApiError::new(ApiErrorCode::ActionFailed, "Failed to save the vote."))?;
+    Ok(vote)
+}
+
+/// Responds to a `/v1` request with the json of the result and its status code
+pub fn respond<T: Serialize>(context: &mut Context, result: ApiResult<T>) {
+    let (status_code, body) = match result {
+        Ok(body) => (200, serde_json::to_string(&body)),
+        Err(e) => (
+            e.code.status_code(),
+            serde_json::to_string(&ErrorResponse { error: e }),
+        ),
+    };
+    context
+        .response
+        .status_code(status_code)
+        .header(("Access-Control-Allow-Origin", "*"))
+        .from_json(body.unwrap_or_default())
+        .unwrap();
+}
+
+/// Registers the `/v1` GET endpoints & `/openapi.json`
+pub fn add_get_routes(app: &mut sincere::App) {
+    for route in routes() {
+        if let Handler::Get(handler) = route.handler {
+            app.get(route.path, move |context| respond(context, handler()));
+        }
+    }
+    app.get("/openapi.json", move |context| {
+        respond::<Value>(context, Ok(openapi_document()))
+    });
+}
+
+/// Registers the `/v1` POST endpoints. Requests are only handled if
+/// `check_access` accepts the client.
+pub fn add_post_routes<F>(app: &mut sincere::App, check_access: F)
+where
+    F: Fn(&mut Context) -> ApiResult<()> + Clone + Send + Sync + 'static,
+{
+    for route in routes() {
+        if let Handler::Post(handler) = route.handler {
+            let check_access = check_access.clone();
+            app.post(route.path, move |context| {
+                let result = check_access(context).and_then(|_| handler(context.request.body()));
+                respond(context, result);
+            });
+        }
+    }
+}
+
+/// How a `/v1` endpoint is served
+#[derive(Clone, Copy)]
+enum Handler {
+    /// Served by the GET API server
+    Get(fn() -> ApiResult<Value>),
+    /// Served by the POST API server, with the request body
+    Post(fn(&[u8]) -> ApiResult<Value>),
+}
+
+impl Handler {
+    fn method(&self) -> &'static str {
+        match self {
+            Handler::Get(_) => "get",
+            Handler::Post(_) => "post",
+        }
+    }
+}
+
+/// A `/v1` endpoint, which is both served & described in the OpenAPI document
+struct Route {
+    path: &'static str,
+    summary: &'static str,
+    handler: Handler,
+    /// Schema of the json request body (POST endpoints only)
+    request: Option<fn(&mut SchemaGenerator) -> Schema>,
+    response: fn(&mut SchemaGenerator) -> Schema,
+    /// Error codes the endpoint may respond with
+    errors: &'static [ApiErrorCode],
+}
+
+/// The json of a typed endpoint result
+fn to_json<T: Serialize>(result: ApiResult<T>) -> ApiResult<Value> {
+    result.and_then(|body| {
+        serde_json::to_value(body)
+            .map_err(|e| ApiError::new(ApiErrorCode::ActionFailed, &e.to_string()))
+    })
+}
+
+/// Every `/v1` endpoint served by the GET & POST API servers
+fn routes() -> Vec<Route> {
+    use ApiErrorCode::*;
+    fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+        gen.subschema_for::<T>()
+    }
+    let get = |path: &'static str,
+               summary: &'static str,
+               handler: fn() -> ApiResult<Value>,
+               response: fn(&mut SchemaGenerator) -> Schema,
+               errors: &'static [ApiErrorCode]| Route {
+        path: path,
+        summary: summary,
+        handler: Handler::Get(handler),
+        request: None,
+        response: response,
+        errors: errors,
+    };
+    vec![
+        get(
+            "/v1/oracleInfo",
+            "Information about the local oracle",
+            || to_json(oracle_info(&OraclePool::new())),
+            schema::<OracleInfo>,
+            &[],
+        ),
+        get(
+            "/v1/poolInfo",
+            "Information about the oracle pool",
+            || to_json(pool_info(&OraclePool::new(), &PoolParameters::new())),
+            schema::<PoolInfo>,
+            &[],
+        ),
+        get(
+            "/v1/nodeInfo",
+            "Information about the node the core uses",
+            || to_json(node_info()),
+            schema::<NodeInfo>,
+            &[],
+        ),
+        get(
+            "/v1/oracleStatus",
+            "Status of the local oracle",
+            || to_json(oracle_status(&OraclePool::new(), &PoolParameters::new())),
+            schema::<OracleStatus>,
+            &[NoLocalOracle],
+        ),
+        get(
+            "/v1/poolStatus",
+            "Status of the oracle pool",
+            || to_json(pool_status(&OraclePool::new(), &PoolParameters::new())),
+            schema::<PoolStatus>,
+            &[PoolStateUnavailable],
+        ),
+        get(
+            "/v1/poolFunding",
+            "Forecast of the pool's funding runway",
+            || to_json(pool_funding(&OraclePool::new(), &PoolParameters::new())),
+            schema::<FundingForecast>,
+            &[PoolStateUnavailable],
+        ),
+        get(
+            "/v1/datapoints",
+            "The latest datapoint of every oracle",
+            || to_json(datapoints(&OraclePool::new())),
+            schema::<Datapoints>,
+            &[PoolStateUnavailable],
+        ),
+        get(
+            "/v1/poolHistory",
+            "The datapoints finalized by the pool",
+            || to_json(pool_history()),
+            schema::<PoolHistory>,
+            &[],
+        ),
+        get(
+            "/v1/oracleStakes",
+            "The collateral of every oracle",
+            || to_json(oracle_stakes(&OraclePool::new(), &PoolParameters::new())),
+            schema::<OracleStakes>,
+            &[PoolStateUnavailable],
+        ),
+        get(
+            "/v1/governanceState",
+            "The state of the payout price vote",
+            || to_json(governance_state(&OraclePool::new(), &PoolParameters::new())),
+            schema::<GovernanceState>,
+            &[PoolStateUnavailable],
+        ),
+        get(
+            "/v1/blockHeight",
+            "The current block height",
+            || to_json(block_height()),
+            schema::<BlockHeightResponse>,
+            &[NodeUnavailable],
+        ),
+        get(
+            "/v1/requireDatapointRepost",
+            "Whether a new datapoint must be submitted",
+            || to_json(datapoint_repost()),
+            schema::<DatapointRepost>,
+            &[],
+        ),
+        Route {
+            path: "/v1/submitDatapoint",
+            summary: "Submit a datapoint for the current epoch (POST API server)",
+            handler: Handler::Post(|body| to_json(submit_datapoint(body))),
+            request: Some(schema::<SubmitDatapointRequest>),
+            response: schema::<SubmitDatapointResponse>,
+            errors: &[
+                InvalidRequest,
+                InvalidDatapoint,
+                Unauthorized,
+                Forbidden,
+                RateLimited,
+                WrongPoolStage,
+                PoolStateUnavailable,
+                ActionFailed,
+            ],
+        },
+        Route {
+            path: "/v1/governanceVote",
+            summary: "Vote for a new oracle payout price (POST API server)",
+            handler: Handler::Post(|body| to_json(governance_vote(body))),
+            request: Some(schema::<GovernanceVote>),
+            response: schema::<GovernanceVote>,
+            errors: &[
+                InvalidRequest,
+                Unauthorized,
+                Forbidden,
+                RateLimited,
+                NoGovernance,
+                ActionFailed,
+            ],
+        },
+    ]
+}
+
+/// Generates the OpenAPI document describing the `/v1` API
+pub fn openapi_document() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let error_schema = gen.subschema_for::<ErrorResponse>();
+    let mut paths = Map::new();
+    for route in routes() {
+        let mut responses = Map::new();
+        responses.insert(
+            "200".to_string(),
+            json!({
+                "description": "Success",
+                "content": {"application/json": {"schema": (route.response)(&mut gen)}}
+            }),
+        );
+        for code in route.errors {
+            let description = serde_json::to_value(code).unwrap_or_default();
+            let status = code.status_code().to_string();
+            let response = responses.entry(status).or_insert(json!({
+                "description": "",
+                "content": {"application/json": {"schema": error_schema}}
+            }));
+            let codes = match response["description"].as_str() {
+                Some("") | None => description.as_str().unwrap_or_default().to_string(),
+                Some(d) => format!("{}, {}", d, description.as_str().unwrap_or_default()),
+            };
+            response["description"] = Value::String(codes);
+        }
+        let mut operation = json!({
+            "summary": route.summary,
+            "responses": responses,
+        });
+        if let Some(request) = route.request {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": {"application/json": {"schema": request(&mut gen)}}
+            });
+        }
+        paths.insert(
+            route.path.to_string(),
+            json!({ (route.handler.method()): operation }),
+        );
+    }
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "Oracle Core API",
+            "version": "1",
+        },
+        "paths": paths,
+        "components": {"schemas": gen.definitions()},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_serialized_with_codes() {
+        let error = ErrorResponse {
+            error: ApiError::new(ApiErrorCode::WrongPoolStage, "Wrong stage."),
+        };
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"error": {"code": "WRONG_POOL_STAGE", "message": "Wrong stage."}})
+        );
+        assert_eq!(ApiErrorCode::WrongPoolStage.status_code(), 409);
+        let access_error: ApiError = AccessError::RateLimited(30).into();
+        assert_eq!(access_error.code, ApiErrorCode::RateLimited);
+    }
+
+    #[test]
+    fn openapi_document_describes_all_routes() {
+        let document = openapi_document();
+        let routes = routes();
+        for route in &routes {
+            let operation = &document["paths"][route.path][route.handler.method()];
+            assert!(operation["responses"]["200"].is_object(), "{}", route.path);
+        }
+        // Every served path is unique & documented
+        assert_eq!(document["paths"].as_object().unwrap().len(), routes.len());
+        let schemas = &document["components"]["schemas"];
+        assert!(schemas["PoolStatus"]["properties"]["current_pool_stage"].is_object());
+        assert!(schemas["ApiErrorCode"].is_object());
+        assert_eq!(
+            document["paths"]["/v1/submitDatapoint"]["post"]["responses"]["409"]["description"],
+            "WRONG_POOL_STAGE"
+        );
+    }
+}
@@ -0,0 +1,470 @@
+/// This file holds the `backtest` subcommand which replays recorded price
+/// series through the datapoint finalization of the configured pool, in
+/// order to evaluate how the pool would have behaved historically.
+///
+/// Every oracle is given a series of records (a timestamp in unix seconds
+/// and the datapoints of the connector's sources at that time) as a CSV
+/// (`timestamp,datapoint[,datapoint...]`) or JSONL (`{"timestamp": ...,
+/// "datapoint": ...}` or `{"timestamp": ..., "datapoints": [...]}`) file.
+/// Epochs are simulated with the configured `live_epoch_length` &
+/// `epoch_preparation_length`. In every Live Epoch each oracle submits the
+/// first record within the epoch, aggregated like a connector aggregates
+/// its sources (the median of the sources within 5% of their median), and
+/// the epoch is finalized with the configured protocol version,
+/// `deviation_range` & `consensus_num`.
+use crate::block_time::EXPECTED_BLOCK_INTERVAL;
+use crate::oracle_config::PoolParameters;
+use crate::Result;
+use anyhow::anyhow;
+use median::median_of;
+use thiserror::Error;
+
+#[path = "../connectors/connector-lib/src/median.rs"]
+mod median;
+
+/// Maximum deviation of a source from the median of all sources of a record
+/// (as a fraction) for it to be used, matching the connector default
+static MAX_SOURCE_DEVIATION: f64 = 0.05;
+
+#[derive(Error, Debug)]
+pub enum BacktestError {
+    #[error("Line {line} of `{file}` is not a valid record: {reason}")]
+    InvalidRecord {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+    #[error("`{0}` holds no records.")]
+    EmptySeries(String),
+}
+
+/// A recorded datapoint (or the datapoints of several sources)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub timestamp: u64,
+    pub datapoints: Vec<i64>,
+}
+
+/// The recorded datapoints of an oracle (or the reference price)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// Records sorted by timestamp
+    pub records: Vec<Record>,
+}
+
+impl Series {
+    /// Reads a `Series` from a CSV or JSONL file
+    pub fn from_file(path: &str) -> Result<Series> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read `{}`: {}", path, e))?;
+        Ok(Series::parse(path, &contents)?)
+    }
+
+    /// Parses a `Series` from the contents of a CSV or JSONL file. Empty
+    /// lines, `#` comments and a CSV header are skipped.
+    pub fn parse(name: &str, contents: &str) -> std::result::Result<Series, BacktestError> {
+        let mut records = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| BacktestError::InvalidRecord {
+                file: name.to_string(),
+                line: i + 1,
+                reason: reason.to_string(),
+            };
+            let record = if line.starts_with('{') {
+                parse_json_record(line).map_err(|e| invalid(&e))?
+            } else {
+                match parse_csv_record(line) {
+                    Ok(record) => record,
+                    // The header of a CSV file
+                    Err(_) if records.is_empty() && i == 0 => continue,
+                    Err(e) => return Err(invalid(&e)),
+                }
+            };
+            records.push(record);
+        }
+        if records.is_empty() {
+            return Err(BacktestError::EmptySeries(name.to_string()));
+        }
+        records.sort_by_key(|r| r.timestamp);
+        Ok(Series {
+            name: name.to_string(),
+            records: records,
+        })
+    }
+
+    /// The datapoint the oracle submits in the Live Epoch between `start` &
+    /// `end` (the first record within the epoch)
+    pub fn submitted_datapoint(&self, start: u64, end: u64) -> Option<i64> {
+        self.records
+            .iter()
+            .find(|r| r.timestamp >= start && r.timestamp < end)
+            .and_then(|r| aggregate_sources(&r.datapoints))
+    }
+
+    /// The latest datapoint at or before `time`
+    pub fn datapoint_at(&self, time: u64) -> Option<i64> {
+        self.records
+            .iter()
+            .take_while(|r| r.timestamp <= time)
+            .last()
+            .and_then(|r| aggregate_sources(&r.datapoints))
+    }
+}
+
+fn parse_csv_record(line: &str) -> std::result::Result<Record, String> {
+    let mut fields = line.split(',').map(|f| f.trim());
+    let timestamp = fields
+        .next()
+        .and_then(|t| t.parse::<u64>().ok())
+        .ok_or("The timestamp must be a unix timestamp in seconds.".to_string())?;
+    let datapoints = fields
+        .filter(|f| !f.is_empty())
+        .map(|f| f.parse::<i64>())
+        .collect::<std::result::Result<Vec<i64>, _>>()
+        .map_err(|_| "Datapoints must be integers.".to_string())?;
+    if datapoints.is_empty() {
+        return Err("The record holds no datapoint.".to_string());
+    }
+    Ok(Record {
+        timestamp: timestamp,
+        datapoints: datapoints,
+    })
+}
+
+fn parse_json_record(line: &str) -> std::result::Result<Record, String> {
+    let record = json::parse(line).map_err(|e| e.to_string())?;
+    let timestamp = record["timestamp"]
+        .as_u64()
+        .ok_or("The timestamp must be a unix timestamp in seconds.".to_string())?;
+    let datapoints = if record["datapoints"].is_array() {
+        record["datapoints"].members().map(|d| d.as_i64()).collect()
+    } else {
+        record["datapoint"].as_i64().map(|d| vec![d])
+    }
+    .ok_or("Datapoints must be integers.".to_string())?;
+    if datapoints.is_empty() {
+        return Err("The record holds no datapoint.".to_string());
+    }
+    Ok(Record {
+        timestamp: timestamp,
+        datapoints: datapoints,
+    })
+}
+
+/// Aggregates the datapoints of a connector's sources into the datapoint it
+/// submits
+pub fn aggregate_sources(datapoints: &[i64]) -> Option<i64> {
+    let median = median_of(&mut datapoints.to_vec())?;
+    let mut accepted: Vec<i64> = datapoints
+        .iter()
+        .copied()
+        .filter(|d| {
+            (*d as f64 - median as f64).abs() / (median as f64).abs().max(1.0)
+                <= MAX_SOURCE_DEVIATION
+        })
+        .collect();
+    median_of(&mut accepted)
+}
+
+/// The outcome of a simulated epoch
+#[derive(Debug, Clone, PartialEq)]
+pub enum EpochOutcome {
+    /// The finalized datapoint and the oracles (indexes) whose datapoints
+    /// were excluded by the deviation check
+    Finalized {
+        datapoint: u64,
+        excluded: Vec<usize>,
+    },
+    /// No datapoint could be finalized
+    ConsensusFailed(String),
+}
+
+/// A simulated epoch
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochResult {
+    pub live_epoch_start: u64,
+    pub live_epoch_end: u64,
+    /// The submitted datapoints by oracle (index)
+    pub datapoints: Vec<(usize, i64)>,
+    pub outcome: EpochOutcome,
+    /// The reference price at the end of the Live Epoch
+    pub reference: Option<i64>,
+}
+
+impl EpochResult {
+    /// The error of the finalized datapoint versus the reference price (in percent)
+    pub fn error_percent(&self) -> Option<f64> {
+        match (&self.outcome, self.reference) {
+            (EpochOutcome::Finalized { datapoint, .. }, Some(reference)) if reference != 0 => {
+                Some((*datapoint as f64 - reference as f64) / reference as f64 * 100.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Simulates the epochs covered by the oracle series, finalizing each epoch
+/// following the pool `parameters`
+pub fn run_backtest(
+    oracles: &[Series],
+    reference: Option<&Series>,
+    parameters: &PoolParameters,
+    block_interval: f64,
+) -> Vec<EpochResult> {
+    let timestamps = oracles
+        .iter()
+        .flat_map(|o| o.records.iter().map(|r| r.timestamp));
+    let (first, last) = match (timestamps.clone().min(), timestamps.max()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return vec![],
+    };
+    let live_length = (parameters.live_epoch_length as f64 * block_interval) as u64;
+    let epoch_length = ((parameters.live_epoch_length + parameters.epoch_preparation_length) as f64
+        * block_interval) as u64;
+    let rules = parameters.protocol();
+    let mut epochs = vec![];
+    let mut live_epoch_start = first;
+    while live_epoch_start <= last {
+        let live_epoch_end = live_epoch_start + live_length;
+        let mut datapoints: Vec<(usize, i64)> = oracles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, o)| {
+                o.submitted_datapoint(live_epoch_start, live_epoch_end)
+                    .map(|d| (i, d))
+            })
+            .collect();
+        // Datapoints are finalized in decreasing order
+        datapoints.sort_by(|a, b| b.1.cmp(&a.1));
+        let required = rules.min_datapoints(parameters) as usize;
+        let outcome = if datapoints.len() < required {
+            EpochOutcome::ConsensusFailed(format!(
+                "Only {} of the required {} oracles submitted a datapoint.",
+                datapoints.len(),
+                required
+            ))
+        } else {
+            let values: Vec<i64> = datapoints.iter().map(|(_, d)| *d).collect();
+            match rules.finalize_datapoints(&values, parameters) {
+                Ok((datapoint, successful)) => EpochOutcome::Finalized {
+                    datapoint: datapoint,
+                    excluded: datapoints
+                        .iter()
+                        .enumerate()
+                        .filter(|(position, _)| !successful.contains(position))
+                        .map(|(_, (oracle, _))| *oracle)
+                        .collect(),
+                },
+                Err(e) => EpochOutcome::ConsensusFailed(e.to_string()),
+            }
+        };
+        epochs.push(EpochResult {
+            live_epoch_start: live_epoch_start,
+            live_epoch_end: live_epoch_end,
+            datapoints: datapoints,
+            outcome: outcome,
+            reference: reference.and_then(|r| r.datapoint_at(live_epoch_end)),
+        });
+        live_epoch_start += epoch_length.max(1);
+    }
+    epochs
+}
+
+/// Runs `backtest` given the CLI args which follow `backtest` and prints the results
+pub fn run_backtest_command(args: &[String]) {
+    let usage = "Usage: oracle-core backtest [--reference <file>] [--block-interval <seconds>] <oracle datapoints file>...";
+    let mut oracle_files = vec![];
+    let mut reference_file = None;
+    let mut block_interval = EXPECTED_BLOCK_INTERVAL;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reference" => match args.next() {
+                Some(file) => reference_file = Some(file.clone()),
+                None => return println!("{}", usage),
+            },
+            "--block-interval" => match args.next().and_then(|s| s.parse::<f64>().ok()) {
+                Some(interval) if interval > 0.0 => block_interval = interval,
+                _ => return println!("{}", usage),
+            },
+            file => oracle_files.push(file.to_string()),
+        }
+    }
+    if oracle_files.is_empty() {
+        return println!("{}", usage);
+    }
+    let series = oracle_files
+        .iter()
+        .map(|f| Series::from_file(f))
+        .collect::<Result<Vec<Series>>>()
+        .and_then(|oracles| {
+            let reference = reference_file
+                .as_ref()
+                .map(|f| Series::from_file(f))
+                .transpose()?;
+            Ok((oracles, reference))
+        });
+    let (oracles, reference) = match series {
+        Ok(series) => series,
+        Err(e) => return println!("Failed to read the datapoints.\nError: {}", e),
+    };
+    let parameters = PoolParameters::new();
+    let epochs = run_backtest(&oracles, reference.as_ref(), &parameters, block_interval);
+    print_backtest(&oracles, &epochs, &parameters);
+}
+
+fn print_backtest(oracles: &[Series], epochs: &[EpochResult], parameters: &PoolParameters) {
+    println!(
+        "Backtest of {} oracles ({} protocol, live epoch length {}, deviation range {}%, consensus number {})",
+        oracles.len(),
+        parameters.protocol_version.name(),
+        parameters.live_epoch_length,
+        parameters.deviation_range,
+        parameters.consensus_num
+    );
+    for (i, epoch) in epochs.iter().enumerate() {
+        let reference = epoch
+            .reference
+            .map(|r| format!(", reference {}", r))
+            .unwrap_or_default();
+        match &epoch.outcome {
+            EpochOutcome::Finalized {
+                datapoint,
+                excluded,
+            } => {
+                let excluded = excluded
+                    .iter()
+                    .map(|o| oracles[*o].name.clone())
+                    .collect::<Vec<String>>();
+                println!(
+                    "Epoch {} ({}): finalized {} from {} datapoints{}{}{}",
+                    i,
+                    epoch.live_epoch_start,
+                    datapoint,
+                    epoch.datapoints.len(),
+                    reference,
+                    epoch
+                        .error_percent()
+                        .map(|e| format!(" (error {:+.3}%)", e))
+                        .unwrap_or_default(),
+                    if excluded.is_empty() {
+                        "".to_string()
+                    } else {
+                        format!(", excluded {}", excluded.join(", "))
+                    }
+                );
+            }
+            EpochOutcome::ConsensusFailed(reason) => println!(
+                "Epoch {} ({}): consensus failed{}: {}",
+                i, epoch.live_epoch_start, reference, reason
+            ),
+        }
+    }
+
+    let finalized = epochs
+        .iter()
+        .filter(|e| matches!(e.outcome, EpochOutcome::Finalized { .. }))
+        .count();
+    println!("\nSummary\n-------");
+    println!("Epochs Simulated: {}", epochs.len());
+    println!("Epochs Finalized: {}", finalized);
+    println!("Consensus Failures: {}", epochs.len() - finalized);
+    for (o, oracle) in oracles.iter().enumerate() {
+        let submitted = epochs
+            .iter()
+            .filter(|e| e.datapoints.iter().any(|(i, _)| *i == o))
+            .count();
+        let excluded = epochs
+            .iter()
+            .filter(|e| match &e.outcome {
+                EpochOutcome::Finalized { excluded, .. } => excluded.contains(&o),
+                _ => false,
+            })
+            .count();
+        println!(
+            "{}: {} datapoints submitted, {} excluded",
+            oracle.name, submitted, excluded
+        );
+    }
+    let errors: Vec<f64> = epochs.iter().filter_map(|e| e.error_percent()).collect();
+    if !errors.is_empty() {
+        let mean = errors.iter().map(|e| e.abs()).sum::<f64>() / errors.len() as f64;
+        let max = errors.iter().fold(0.0, |max: f64, e| max.max(e.abs()));
+        println!("Mean Absolute Error: {:.3}%", mean);
+        println!("Max Absolute Error: {:.3}%", max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters() -> PoolParameters {
+        let mut parameters = PoolParameters::for_tests("");
+        parameters.live_epoch_length = 5;
+        parameters.epoch_preparation_length = 5;
+        parameters
+    }
+
+    #[test]
+    fn records_are_parsed() {
+        let series = Series::parse(
+            "oracle.csv",
+            "timestamp,datapoint\n1000,500,510\n# comment\n\n900,400",
+        )
+        .unwrap();
+        assert_eq!(series.records[0].timestamp, 900);
+        assert_eq!(series.records[1].datapoints, vec![500, 510]);
+        let series = Series::parse(
+            "oracle.jsonl",
+            "{\"timestamp\": 900, \"datapoint\": 400}\n{\"timestamp\": 1000, \"datapoints\": [500, 510]}",
+        )
+        .unwrap();
+        assert_eq!(series.records[1].datapoints, vec![500, 510]);
+        assert!(Series::parse("oracle.csv", "900,400\n1000,abc").is_err());
+        assert!(Series::parse("oracle.csv", "timestamp,datapoint").is_err());
+        // The outlier source is ignored
+        assert_eq!(aggregate_sources(&[1000, 1010, 2000]), Some(1005));
+    }
+
+    #[test]
+    fn epochs_are_finalized() {
+        let oracle = |name: &str, records: &str| Series::parse(name, records).unwrap();
+        // Epochs start every 1200 seconds, with a Live Epoch of 600 seconds
+        let oracles = vec![
+            oracle("a", "0,1000\n1200,1000\n2400,1000"),
+            oracle("b", "0,1010\n1200,1010"),
+            oracle("c", "0,1020\n1200,1500"),
+            oracle("d", "0,1030\n1300,1020\n3000,1000"),
+        ];
+        let reference = oracle("reference", "0,1000");
+        let epochs = run_backtest(&oracles, Some(&reference), &parameters(), 120.0);
+        assert_eq!(epochs.len(), 3);
+        assert_eq!(
+            epochs[0].outcome,
+            EpochOutcome::Finalized {
+                datapoint: 1015,
+                excluded: vec![]
+            }
+        );
+        assert_eq!(epochs[0].error_percent(), Some(1.5));
+        // The outlier of oracle c is excluded
+        assert_eq!(
+            epochs[1].outcome,
+            EpochOutcome::Finalized {
+                datapoint: 1010,
+                excluded: vec![2]
+            }
+        );
+        // Only oracle a submits within the Live Epoch (oracle d posts too late)
+        assert!(matches!(
+            epochs[2].outcome,
+            EpochOutcome::ConsensusFailed(_)
+        ));
+    }
+}
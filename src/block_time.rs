@@ -0,0 +1,145 @@
+/// This file holds the estimation of block times. The average interval
+/// between blocks is computed from the timestamps of the latest block headers
+/// held by the node, which is then used to estimate the wall-clock time at
+/// which a given (future) block height will be reached, such as the end of
+/// the current epoch.
+use crate::node_interface::last_block_headers;
+use crate::oracle_config::PoolParameters;
+use crate::oracle_state::PoolBoxState;
+use crate::BlockHeight;
+use anyhow::anyhow;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The expected average time between blocks (in seconds), used if the node
+/// headers are unavailable
+pub static EXPECTED_BLOCK_INTERVAL: f64 = 120.0;
+/// Number of latest block headers the average block interval is computed over
+static BLOCK_INTERVAL_SAMPLE_SIZE: u64 = 30;
+/// How long fetched block times are reused before asking the node again
+static BLOCK_TIMES_CACHE_DURATION: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref CACHED_BLOCK_TIMES: Mutex<Option<(Instant, BlockTimes)>> = Mutex::new(None);
+}
+
+/// The latest block and the average interval between blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockTimes {
+    pub tip_height: BlockHeight,
+    /// Unix timestamp of the latest block (in milliseconds)
+    pub tip_timestamp: u64,
+    /// Average interval between the latest blocks (in seconds)
+    pub average_block_interval: f64,
+}
+
+impl BlockTimes {
+    /// Computes the block times from `(height, timestamp)` pairs of block
+    /// headers (timestamps in milliseconds). Returns `None` if less than two
+    /// headers are provided.
+    pub fn from_headers(headers: &[(BlockHeight, u64)]) -> Option<BlockTimes> {
+        let first = headers.iter().min_by_key(|h| h.0)?;
+        let last = headers.iter().max_by_key(|h| h.0)?;
+        if last.0 <= first.0 {
+            return None;
+        }
+        let interval_ms = last.1.saturating_sub(first.1) as f64 / (last.0 - first.0) as f64;
+        Some(BlockTimes {
+            tip_height: last.0,
+            tip_timestamp: last.1,
+            average_block_interval: interval_ms / 1000.0,
+        })
+    }
+
+    /// Estimated unix time (in seconds) at which the block at `height` is/was mined
+    pub fn estimated_time(&self, height: BlockHeight) -> u64 {
+        let blocks = height as f64 - self.tip_height as f64;
+        let time_ms = self.tip_timestamp as f64 + blocks * self.average_block_interval * 1000.0;
+        (time_ms.max(0.0) / 1000.0) as u64
+    }
+}
+
+/// The block heights of the upcoming milestones of the oracle pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpochSchedule {
+    /// Height the current/upcoming Live Epoch ends
+    pub epoch_ends: BlockHeight,
+    /// Height the Epoch Preparation stage ends & the next Live Epoch may start
+    pub next_epoch_starts: BlockHeight,
+    /// Height from which the datapoints of the current/upcoming epoch may be collected
+    pub next_collection: BlockHeight,
+}
+
+impl EpochSchedule {
+    /// The schedule of the pool in `stage`, where `epoch_ends` is the end
+    /// height of the Live Epoch (or the next one during Epoch Preparation)
+    pub fn new(
+        stage: &PoolBoxState,
+        epoch_ends: BlockHeight,
+        parameters: &PoolParameters,
+    ) -> EpochSchedule {
+        let next_epoch_starts = match stage {
+            PoolBoxState::LiveEpoch => epoch_ends + parameters.epoch_preparation_length,
+            PoolBoxState::Preparation => epoch_ends.saturating_sub(parameters.live_epoch_length),
+        };
+        EpochSchedule {
+            epoch_ends: epoch_ends,
+            next_epoch_starts: next_epoch_starts,
+            next_collection: epoch_ends,
+        }
+    }
+}
+
+/// Returns the current `BlockTimes`, fetching the latest block headers from
+/// the node if the cached block times are outdated
+pub fn current_block_times() -> crate::Result<BlockTimes> {
+    let mut cache = CACHED_BLOCK_TIMES.lock().unwrap();
+    if let Some((fetched, block_times)) = *cache {
+        if fetched.elapsed() < BLOCK_TIMES_CACHE_DURATION {
+            return Ok(block_times);
+        }
+    }
+    let headers = last_block_headers(BLOCK_INTERVAL_SAMPLE_SIZE)?;
+    let block_times = BlockTimes::from_headers(&headers)
+        .ok_or(anyhow!("Not enough block headers to estimate block times."))?;
+    *cache = Some((Instant::now(), block_times));
+    Ok(block_times)
+}
+
+/// Returns the current average block interval (in seconds), falling back to
+/// the `EXPECTED_BLOCK_INTERVAL` if it cannot be computed
+pub fn average_block_interval() -> f64 {
+    current_block_times()
+        .map(|b| b.average_block_interval)
+        .unwrap_or(EXPECTED_BLOCK_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_times_are_estimated_from_headers() {
+        let headers: Vec<(BlockHeight, u64)> = (0..30)
+            .map(|i| (288670 + i, 1600000000000 + i * 100000))
+            .collect();
+        let block_times = BlockTimes::from_headers(&headers).unwrap();
+        assert_eq!(block_times.tip_height, 288699);
+        assert_eq!(block_times.average_block_interval, 100.0);
+        assert_eq!(block_times.estimated_time(288699), 1600002900);
+        assert_eq!(block_times.estimated_time(288709), 1600003900);
+        assert_eq!(block_times.estimated_time(288689), 1600001900);
+        assert_eq!(BlockTimes::from_headers(&headers[..1]), None);
+    }
+
+    #[test]
+    fn epoch_schedule_follows_pool_stage() {
+        let parameters = PoolParameters::for_tests("");
+        let live = EpochSchedule::new(&PoolBoxState::LiveEpoch, 288699, &parameters);
+        assert_eq!(live.next_epoch_starts, 288709);
+        assert_eq!(live.next_collection, 288699);
+        let prep = EpochSchedule::new(&PoolBoxState::Preparation, 288729, &parameters);
+        assert_eq!(prep.next_epoch_starts, 288709);
+        assert_eq!(prep.next_collection, 288729);
+    }
+}
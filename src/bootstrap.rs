@@ -0,0 +1,454 @@
+/// This file holds the `bootstrap` subcommands which create a new oracle pool
+/// on-chain. The pool parameters/node settings are read from the
+/// `oracle-config.yaml`, and every step writes its results (token ids and
+/// contract addresses) back into it, leaving a ready-to-use config file.
+///
+/// The steps must be performed in order, waiting for each tx to be
+/// confirmed before moving on to the next step:
+/// 1. `mint-pool-nft`
+/// 2. `mint-oracle-tokens <number of oracles>`
+/// 3. `compile-contracts`
+/// 4. `pool <initial datapoint>`
+/// 5. `oracle <oracle address>` (for every oracle in the pool)
+use crate::encoding::encode_group_element_list;
+use crate::node_interface::{
+    address_to_raw_for_register, address_to_tree, compile_contract, current_block_height,
+    generate_transaction, submit_transaction,
+};
+use crate::oracle_config::{get_config_str, set_config_value, PoolParameters};
+use crate::oracle_state::SLASHED_DATAPOINT_BOX_VALUE;
+use crate::protocol::ProtocolVersion;
+use crate::templates::BASIC_TRANSACTION_SEND_REQUEST;
+use crate::{NanoErg, P2SAddress, Result, TokenID};
+use anyhow::anyhow;
+use ergo_lib::chain::Base16Str;
+use ergo_lib::ergotree_ir::mir::constant::Constant;
+use json::JsonValue;
+use std::path::Path;
+
+/// Value of each oracle's initial Datapoint box (non stake slashing pools)
+static DATAPOINT_BOX_VALUE: NanoErg = 1000000;
+/// Fee used for all bootstrap txs
+static BOOTSTRAP_TX_FEE: NanoErg = 2000000;
+/// Placeholder epoch id held in R5 of the initial Datapoint boxes and R8 of the
+/// initial Epoch Preparation box. Both must match so that no oracle counts as
+/// uncommitted before the first epoch.
+static PLACEHOLDER_EPOCH_ID: &str = "0e0101";
+/// Placeholder collector held in R6 of the initial Epoch Preparation box (the
+/// secp256k1 generator). No oracle uses it as their key, so no Datapoint box
+/// can be slashed as the collector before the first epoch.
+static PLACEHOLDER_COLLECTOR: &str =
+    "070279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+/// The compiled contract addresses of a pool
+pub struct PoolContracts {
+    pub live_epoch: P2SAddress,
+    pub epoch_preparation: P2SAddress,
+    pub datapoint: P2SAddress,
+    pub pool_deposit: P2SAddress,
+}
+
+/// Runs a `bootstrap` subcommand given the CLI args which follow `bootstrap`
+pub fn run_bootstrap_command(args: &[String]) {
+    let res = match (args.get(0).map(|s| s.as_str()), args.get(1)) {
+        (Some("mint-pool-nft"), _) => mint_pool_nft(),
+        (Some("mint-oracle-tokens"), Some(num)) => match num.parse::<u64>() {
+            Ok(n) => mint_oracle_tokens(n),
+            Err(_) => Err(anyhow!("Invalid number of oracles: {}", num)),
+        },
+        (Some("compile-contracts"), _) => compile_pool_contracts(),
+        (Some("pool"), Some(datapoint)) => match datapoint.parse::<i64>() {
+            Ok(d) => bootstrap_epoch_preparation_box(d),
+            Err(_) => Err(anyhow!("Invalid initial datapoint: {}", datapoint)),
+        },
+        (Some("oracle"), Some(address)) => bootstrap_datapoint_box(address),
+        _ => {
+            print_bootstrap_usage();
+            return;
+        }
+    };
+    match res {
+        Ok(message) => println!("{}", message),
+        Err(e) => println!("Bootstrap step failed.\nError: {:?}", e),
+    }
+}
+
+fn print_bootstrap_usage() {
+    println!("Usage (steps must be run in order, waiting for each tx to confirm):");
+    println!("oracle-core bootstrap mint-pool-nft");
+    println!("oracle-core bootstrap mint-oracle-tokens <number_of_oracles>");
+    println!("oracle-core bootstrap compile-contracts");
+    println!("oracle-core bootstrap pool <initial_datapoint>");
+    println!("oracle-core bootstrap oracle <oracle_address>");
+}
+
+/// Mints the singleton pool NFT and saves its id as `oracle_pool_nft`
+fn mint_pool_nft() -> Result<String> {
+    let token_id = mint_token(1, "POOL", "Oracle pool NFT")?;
+    set_config_value("oracle_pool_nft", &token_id)?;
+    Ok(format!(
+        "Minted the oracle pool NFT: {}\nSaved as `oracle_pool_nft` in oracle-config.yaml.",
+        token_id
+    ))
+}
+
+/// Mints one participant token per oracle and saves the token id as `oracle_pool_participant_token`
+fn mint_oracle_tokens(number_of_oracles: u64) -> Result<String> {
+    let token_id = mint_token(number_of_oracles, "ORACLE", "Oracle pool participant token")?;
+    set_config_value("oracle_pool_participant_token", &token_id)?;
+    set_config_value("number_of_oracles", &number_of_oracles.to_string())?;
+    Ok(format!(
+        "Minted {} oracle pool participant tokens: {}\nSaved as `oracle_pool_participant_token` (and the `number_of_oracles`) in oracle-config.yaml.",
+        number_of_oracles, token_id
+    ))
+}
+
+/// Mints a new token into the node wallet (at the local `oracle_address`).
+/// Returns the token id, which is the id of the first input box of the tx.
+fn mint_token(amount: u64, name: &str, description: &str) -> Result<TokenID> {
+    let req = object! {
+        "requests": [
+            {
+                "address": get_config_str("oracle_address")?,
+                "amount": amount,
+                "name": name,
+                "description": description,
+                "decimals": 0,
+            }
+        ],
+        "fee": BOOTSTRAP_TX_FEE,
+        "inputsRaw": [],
+        "dataInputsRaw": [],
+    };
+    let signed_tx = generate_transaction(&req)?;
+    let token_id = signed_tx["inputs"][0]["boxId"]
+        .as_str()
+        .ok_or(anyhow!("Generated tx has no inputs."))?
+        .to_string();
+    submit_transaction(&signed_tx)?;
+    Ok(token_id)
+}
+
+/// Compiles the pool contracts with the parameters/token ids in the config
+/// and saves the resulting addresses
+fn compile_pool_contracts() -> Result<String> {
+    let contracts = compile_contracts(
+        &PoolParameters::new(),
+        &contract_sources(&PoolParameters::new()),
+        &bootstrap_config_str("oracle_pool_nft")?,
+        &bootstrap_config_str("oracle_pool_participant_token")?,
+    )?;
+    set_config_value("live_epoch_contract_address", &contracts.live_epoch)?;
+    set_config_value(
+        "epoch_preparation_contract_address",
+        &contracts.epoch_preparation,
+    )?;
+    set_config_value("datapoint_contract_address", &contracts.datapoint)?;
+    set_config_value("pool_deposit_contract_address", &contracts.pool_deposit)?;
+    Ok(format!("Compiled the pool contracts:\nLive Epoch: {}\nEpoch Preparation: {}\nDatapoint: {}\nPool Deposit: {}\nSaved the addresses in oracle-config.yaml.",
+        contracts.live_epoch, contracts.epoch_preparation, contracts.datapoint, contracts.pool_deposit
+    ))
+}
+
+/// Compiles the given contract sources, filling in the pool parameters/token
+/// ids as contract constants.
+pub fn compile_contracts(
+    parameters: &PoolParameters,
+    sources: &ContractSources,
+    pool_nft: &TokenID,
+    participant_token: &TokenID,
+) -> Result<PoolContracts> {
+    let mut constants = pool_constants(parameters, pool_nft, participant_token);
+
+    // Each contract depends on the script hash of the previously compiled one
+    let live_epoch = compile_contract(&with_constants(&sources.live_epoch, &constants))?;
+    constants.push(("liveEpochScriptHash", script_hash_constant(&live_epoch)?));
+    let epoch_preparation =
+        compile_contract(&with_constants(&sources.epoch_preparation, &constants))?;
+    constants.push((
+        "epochPrepScriptHash",
+        script_hash_constant(&epoch_preparation)?,
+    ));
+    let pool_deposit = compile_contract(&with_constants(&sources.pool_deposit, &constants))?;
+    constants.push((
+        "poolDepositScriptHash",
+        script_hash_constant(&pool_deposit)?,
+    ));
+    let datapoint = compile_contract(&with_constants(&sources.datapoint, &constants))?;
+
+    Ok(PoolContracts {
+        live_epoch: live_epoch,
+        epoch_preparation: epoch_preparation,
+        datapoint: datapoint,
+        pool_deposit: pool_deposit,
+    })
+}
+
+/// The contract constants defined by the pool parameters/token ids
+pub fn pool_constants(
+    parameters: &PoolParameters,
+    pool_nft: &TokenID,
+    participant_token: &TokenID,
+) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "oracleTokenId",
+            format!("fromBase16(\"{}\")", participant_token),
+        ),
+        ("poolTokenId", format!("fromBase16(\"{}\")", pool_nft)),
+        (
+            "oracleReward",
+            format!("{}L", parameters.oracle_payout_price),
+        ),
+        (
+            "epochPeriod",
+            (parameters.live_epoch_length + parameters.epoch_preparation_length).to_string(),
+        ),
+        ("livePeriod", parameters.live_epoch_length.to_string()),
+        (
+            "prepPeriod",
+            parameters.epoch_preparation_length.to_string(),
+        ),
+        ("buffer", parameters.buffer_length.to_string()),
+        ("maxDeviation", parameters.deviation_range.to_string()),
+        ("minOracleBoxes", parameters.consensus_num.to_string()),
+        ("numOracles", parameters.number_of_oracles.to_string()),
+        (
+            "minPoolBoxValue",
+            format!("{}L", parameters.minimum_pool_box_value),
+        ),
+        ("minBoxValue", format!("{}L", SLASHED_DATAPOINT_BOX_VALUE)),
+        (
+            "minCollateral",
+            format!("{}L", parameters.minimum_collateral),
+        ),
+    ]
+}
+
+/// The ErgoScript sources of each contract of a pool variant
+pub struct ContractSources {
+    /// Where the sources were read from
+    pub origin: String,
+    pub live_epoch: String,
+    pub epoch_preparation: String,
+    pub datapoint: String,
+    pub pool_deposit: String,
+}
+
+impl ContractSources {
+    /// Reads the contract sources from a directory laid out like the ones in
+    /// `smart-contracts/` (`live_epoch.es`, `epoch_prep.es`, `data_point.es`, `pool_deposit.es`)
+    pub fn from_dir(dir: &str) -> Result<ContractSources> {
+        let read = |file: &str| {
+            let path = Path::new(dir).join(file);
+            std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))
+        };
+        Ok(ContractSources {
+            origin: dir.to_string(),
+            live_epoch: read("live_epoch.es")?,
+            epoch_preparation: read("epoch_prep.es")?,
+            datapoint: read("data_point.es")?,
+            pool_deposit: read("pool_deposit.es")?,
+        })
+    }
+
+    fn bundled(
+        origin: &str,
+        live_epoch: &str,
+        epoch_preparation: &str,
+        datapoint: &str,
+        pool_deposit: &str,
+    ) -> ContractSources {
+        ContractSources {
+            origin: format!("bundled smart-contracts/{}", origin),
+            live_epoch: live_epoch.to_string(),
+            epoch_preparation: epoch_preparation.to_string(),
+            datapoint: datapoint.to_string(),
+            pool_deposit: pool_deposit.to_string(),
+        }
+    }
+}
+
+/// Returns the bundled contract sources matching the pool variant used
+pub fn contract_sources(parameters: &PoolParameters) -> ContractSources {
+    if parameters.protocol_version == ProtocolVersion::V0_1 {
+        ContractSources::bundled(
+            "v0.1",
+            include_str!("../smart-contracts/v0.1/live_epoch.es"),
+            include_str!("../smart-contracts/v0.1/epoch_prep.es"),
+            include_str!("../smart-contracts/v0.1/data_point.es"),
+            include_str!("../smart-contracts/v0.1/pool_deposit.es"),
+        )
+    } else if parameters.governance {
+        ContractSources::bundled(
+            "v0.2-governance-stake-slashing",
+            include_str!("../smart-contracts/v0.2-governance-stake-slashing/live_epoch.es"),
+            include_str!("../smart-contracts/v0.2-governance-stake-slashing/epoch_prep.es"),
+            include_str!("../smart-contracts/v0.2-governance-stake-slashing/data_point.es"),
+            include_str!("../smart-contracts/v0.2-governance-stake-slashing/pool_deposit.es"),
+        )
+    } else if parameters.stake_slashing {
+        ContractSources::bundled(
+            "v0.2-stake-slashing",
+            include_str!("../smart-contracts/v0.2-stake-slashing/live_epoch.es"),
+            include_str!("../smart-contracts/v0.2-stake-slashing/epoch_prep.es"),
+            include_str!("../smart-contracts/v0.2-stake-slashing/data_point.es"),
+            include_str!("../smart-contracts/v0.2-stake-slashing/pool_deposit.es"),
+        )
+    } else {
+        ContractSources::bundled(
+            "v0.2",
+            include_str!("../smart-contracts/v0.2/live_epoch.es"),
+            include_str!("../smart-contracts/v0.2/epoch_prep.es"),
+            include_str!("../smart-contracts/v0.2/data_point.es"),
+            include_str!("../smart-contracts/v0.2/pool_deposit.es"),
+        )
+    }
+}
+
+/// Defines the given constants at the top of the contract's block (after the
+/// line which opens it). Only constants which the contract references are defined.
+pub fn with_constants(source: &str, constants: &Vec<(&str, String)>) -> String {
+    let definitions: String = constants
+        .iter()
+        .filter(|(name, _)| references_identifier(source, name))
+        .map(|(name, value)| format!("  val {} = {}\n", name, value))
+        .collect();
+    let block_start = source
+        .find('{')
+        .and_then(|i| source[i..].find('\n').map(|j| i + j + 1));
+    match block_start {
+        Some(i) => format!("{}{}{}", &source[..i], definitions, &source[i..]),
+        None => source.to_string(),
+    }
+}
+
+/// Whether the source references the identifier as a whole word
+pub fn references_identifier(source: &str, identifier: &str) -> bool {
+    source
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|word| word == identifier)
+}
+
+/// Returns the blake2b256 hash of the contract's ErgoTree as an ErgoScript constant
+fn script_hash_constant(address: &P2SAddress) -> Result<String> {
+    let tree_bytes = base16::decode(&address_to_tree(address)?)?;
+    let hash = blake2b_simd::Params::new()
+        .hash_length(32)
+        .hash(&tree_bytes);
+    Ok(format!(
+        "fromBase16(\"{}\")",
+        base16::encode_lower(hash.as_bytes())
+    ))
+}
+
+/// Creates the initial Epoch Preparation box holding the pool NFT
+fn bootstrap_epoch_preparation_box(initial_datapoint: i64) -> Result<String> {
+    let parameters = PoolParameters::new();
+
+    // The epoch end height is set to the current height so that a new epoch can be created right away
+    let mut registers = object! {
+        "R4": Constant::from(initial_datapoint).base16_str(),
+        "R5": Constant::from(current_block_height()? as i32).base16_str(),
+    };
+    // Stake slashing pools start with a placeholder collector/no collected oracles/placeholder
+    // epoch id, which satisfy neither of the slashing spending paths
+    if parameters.stake_slashing {
+        registers["R6"] = PLACEHOLDER_COLLECTOR.into();
+        registers["R7"] = encode_group_element_list(&vec![])?.into();
+        registers["R8"] = PLACEHOLDER_EPOCH_ID.into();
+    }
+    if parameters.governance {
+        registers["R9"] = Constant::from(parameters.oracle_payout_price as i64)
+            .base16_str()
+            .into();
+    }
+
+    let req = bootstrap_box_request(
+        &bootstrap_config_str("epoch_preparation_contract_address")?,
+        parameters.minimum_pool_box_value,
+        &bootstrap_config_str("oracle_pool_nft")?,
+        registers,
+    )?;
+    let tx_id = submit_transaction(&generate_transaction(&req)?)?;
+    Ok(format!(
+        "Created the Epoch Preparation box.\nTransaction Id: {}",
+        tx_id
+    ))
+}
+
+/// Creates the initial Datapoint box of the given oracle holding a participant token
+fn bootstrap_datapoint_box(oracle_address: &str) -> Result<String> {
+    let parameters = PoolParameters::new();
+    let registers = object! {
+        "R4": address_to_raw_for_register(&oracle_address.to_string())?,
+        "R5": PLACEHOLDER_EPOCH_ID,
+        "R6": Constant::from(1i64).base16_str(),
+    };
+    let value = if parameters.stake_slashing {
+        parameters.minimum_collateral
+    } else {
+        DATAPOINT_BOX_VALUE
+    };
+
+    let req = bootstrap_box_request(
+        &bootstrap_config_str("datapoint_contract_address")?,
+        value,
+        &bootstrap_config_str("oracle_pool_participant_token")?,
+        registers,
+    )?;
+    let tx_id = submit_transaction(&generate_transaction(&req)?)?;
+    Ok(format!(
+        "Created the Datapoint box for oracle {}.\nTransaction Id: {}",
+        oracle_address, tx_id
+    ))
+}
+
+/// Reads a config value written by a previous bootstrap step
+fn bootstrap_config_str(key: &str) -> Result<String> {
+    get_config_str(key).map_err(|e| {
+        anyhow!(
+            "{} Please ensure the previous bootstrap steps were completed.",
+            e
+        )
+    })
+}
+
+/// Builds a tx request creating a single box holding one of the given token
+fn bootstrap_box_request(
+    address: &str,
+    value: NanoErg,
+    token_id: &str,
+    registers: JsonValue,
+) -> Result<JsonValue> {
+    let mut req = json::parse(BASIC_TRANSACTION_SEND_REQUEST)?;
+    let token_json = object! {
+        "tokenId": token_id,
+        "amount": 1
+    };
+    req["requests"][0]["address"] = address.into();
+    req["requests"][0]["value"] = value.into();
+    req["requests"][0]["registers"] = registers;
+    req["requests"][0]["assets"] = vec![token_json].into();
+    req["fee"] = BOOTSTRAP_TX_FEE.into();
+    Ok(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_are_defined_only_if_referenced() {
+        let source = "{ // This box:\n  sigmaProp(SELF.value >= minPoolBoxValue)\n}";
+        let constants = vec![
+            ("minPoolBoxValue", "100L".to_string()),
+            ("minBoxValue", "1L".to_string()),
+        ];
+        assert_eq!(
+            with_constants(source, &constants),
+            "{ // This box:\n  val minPoolBoxValue = 100L\n  sigmaProp(SELF.value >= minPoolBoxValue)\n}"
+        );
+    }
+}
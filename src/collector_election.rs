@@ -0,0 +1,174 @@
+/// This file holds the logic for electing which oracle collects the
+/// datapoints of an epoch. Every oracle which committed a datapoint is
+/// deterministically ranked by the hash of the epoch id and its public key.
+/// The top ranked oracle may collect as soon as the epoch ends, while each
+/// following rank waits an additional `collector_backoff_length` blocks, so
+/// that oracles only step in if the oracles ranked above them failed to collect.
+/// The same ranking (over every oracle holding a stake) decides when each
+/// oracle may issue slashing txs during the Epoch Preparation stage.
+use crate::actions::current_epoch_boxes_filter;
+use crate::node_interface::address_to_raw_for_register;
+use crate::oracle_config::PoolParameters;
+use crate::oracle_state::{LiveEpochState, OraclePool, StakeSlashingState};
+use crate::{BlockHeight, EpochID, Result};
+use ergo_lib::chain::Base16Str;
+
+/// The collector election of the current Live Epoch
+#[derive(Debug, Clone)]
+pub struct CollectorElection {
+    /// The rank of the local oracle (0 collects first), `None` if the local
+    /// oracle did not commit a datapoint in the current epoch
+    pub local_oracle_rank: Option<usize>,
+    /// The number of oracles which committed a datapoint in the current epoch
+    pub number_of_candidates: usize,
+    /// The block height from which the local oracle may collect datapoints
+    pub local_collection_height: Option<BlockHeight>,
+}
+
+impl OraclePool {
+    /// Ranks the oracles which committed a datapoint in the current epoch
+    /// to decide when the local oracle may attempt to collect datapoints
+    pub fn get_collector_election(
+        &self,
+        live_epoch_state: &LiveEpochState,
+        parameters: &PoolParameters,
+    ) -> Result<CollectorElection> {
+        let candidates: Vec<String> =
+            current_epoch_boxes_filter(&self.datapoint_stage.get_boxes()?, live_epoch_state)
+                .iter()
+                .map(|b| b.additional_registers.get_ordered_values()[0].base16_str())
+                .collect();
+        // Observers have no local oracle to rank
+        let local_oracle_rank = match &self.local_oracle {
+            Some(local_oracle) => collector_rank(
+                &live_epoch_state.epoch_id,
+                &address_to_raw_for_register(&local_oracle.address)?,
+                &candidates,
+            ),
+            None => None,
+        };
+        let local_collection_height = local_oracle_rank.map(|rank| {
+            live_epoch_state.epoch_ends + rank as u64 * parameters.collector_backoff_length
+        });
+
+        Ok(CollectorElection {
+            local_oracle_rank: local_oracle_rank,
+            number_of_candidates: candidates.len(),
+            local_collection_height: local_collection_height,
+        })
+    }
+
+    /// Ranks every oracle holding a stake by the previous epoch id to decide
+    /// from which block height the local oracle may issue slashing txs.
+    /// Returns `None` if the local oracle does not hold a stake.
+    pub fn get_local_slashing_height(
+        &self,
+        slashing_state: &StakeSlashingState,
+        parameters: &PoolParameters,
+    ) -> Result<Option<BlockHeight>> {
+        let candidates: Vec<String> = slashing_state
+            .stakes
+            .iter()
+            .map(|s| s.oracle_address_raw.clone())
+            .collect();
+        let local_oracle_raw = address_to_raw_for_register(&self.local_oracle()?.address)?;
+        let rank = collector_rank(
+            &slashing_state.previous_epoch_id,
+            &local_oracle_raw,
+            &candidates,
+        );
+        Ok(rank.map(|r| {
+            slashing_state.preparation_height + r as u64 * parameters.collector_backoff_length
+        }))
+    }
+}
+
+/// Returns the collector rank of the given oracle (hex-encoded `GroupElement`
+/// register value) out of the candidates, or `None` if it is not a candidate.
+/// Oracles are ordered by the blake2b256 hash of the epoch id + their public key.
+pub fn collector_rank(
+    epoch_id: &EpochID,
+    oracle_raw: &String,
+    candidates: &Vec<String>,
+) -> Option<usize> {
+    if !candidates.contains(oracle_raw) {
+        return None;
+    }
+    let mut ranked = candidates.clone();
+    ranked.sort_by_key(|c| election_hash(epoch_id, c));
+    ranked.dedup();
+    ranked.iter().position(|c| c == oracle_raw)
+}
+
+/// Hashes the epoch id together with the oracle's public key
+fn election_hash(epoch_id: &EpochID, oracle_raw: &String) -> Vec<u8> {
+    let mut bytes = base16::decode(epoch_id).unwrap_or_default();
+    bytes.append(&mut base16::decode(oracle_raw).unwrap_or_default());
+    blake2b_simd::Params::new()
+        .hash_length(32)
+        .hash(&bytes)
+        .as_bytes()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static EPOCH_A: &str = "14e10314b0b33f13667871c62b0e86904cb6aee854630af4296b567b18875185";
+    static EPOCH_B: &str = "0d742ecb0d3ffc9cf3104d3da89cf758d200e10e9c4889284c22ea659bcefcc4";
+
+    fn candidates() -> Vec<String> {
+        (1..=5)
+            .map(|i| format!("0702{}", format!("{:02x}", i).repeat(32)))
+            .collect()
+    }
+
+    #[test]
+    fn every_candidate_gets_a_unique_rank() {
+        let candidates = candidates();
+        let mut ranks: Vec<usize> = candidates
+            .iter()
+            .map(|c| collector_rank(&EPOCH_A.to_string(), c, &candidates).unwrap())
+            .collect();
+        ranks.sort();
+        assert_eq!(ranks, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rank_does_not_depend_on_candidate_order() {
+        let candidates = candidates();
+        let mut reversed = candidates.clone();
+        reversed.reverse();
+        for c in &candidates {
+            assert_eq!(
+                collector_rank(&EPOCH_A.to_string(), c, &candidates),
+                collector_rank(&EPOCH_A.to_string(), c, &reversed)
+            );
+        }
+    }
+
+    #[test]
+    fn ranking_changes_between_epochs() {
+        let candidates = candidates();
+        let ranks_a: Vec<Option<usize>> = candidates
+            .iter()
+            .map(|c| collector_rank(&EPOCH_A.to_string(), c, &candidates))
+            .collect();
+        let ranks_b: Vec<Option<usize>> = candidates
+            .iter()
+            .map(|c| collector_rank(&EPOCH_B.to_string(), c, &candidates))
+            .collect();
+        assert_ne!(ranks_a, ranks_b);
+    }
+
+    #[test]
+    fn non_candidate_has_no_rank() {
+        let candidates = candidates();
+        let outsider = format!("0703{}", "ff".repeat(32));
+        assert_eq!(
+            collector_rank(&EPOCH_A.to_string(), &outsider, &candidates),
+            None
+        );
+    }
+}
@@ -0,0 +1,255 @@
+/// This file holds the `config verify` subcommand which checks that the
+/// contract addresses in the `oracle-config.yaml` match the ErgoScript
+/// sources compiled with the configured token ids/pool parameters. This
+/// catches configs whose epoch length, deviation range or consensus number
+/// differ from the values baked into the on-chain contracts.
+///
+/// By default the bundled sources of the configured protocol version/pool
+/// variant are used. Pools created from other sources can be verified by
+/// passing their directory with `--sources <dir>`.
+use crate::bootstrap::{
+    compile_contracts, contract_sources, pool_constants, references_identifier, ContractSources,
+};
+use crate::encoding::decode_vlq;
+use crate::node_interface::address_to_tree;
+use crate::oracle_config::{get_config_str, PoolParameters};
+use crate::Result;
+use anyhow::anyhow;
+use ergo_lib::chain::Base16Str;
+use ergo_lib::ergotree_ir::mir::constant::Constant;
+use ergo_lib::ergotree_ir::serialization::sigma_byte_reader;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+
+/// The verification result of a single pool contract
+#[derive(Debug, Clone)]
+pub struct ContractVerification {
+    pub name: &'static str,
+    pub configured_address: String,
+    pub compiled_address: String,
+    /// Whether the configured address has the same ErgoTree as the compiled source
+    pub matches: bool,
+    /// The constants (name = value) whose values were not found in the
+    /// configured contract's ErgoTree
+    pub missing_constants: Vec<String>,
+}
+
+/// Runs `config verify` given the CLI args which follow `verify` and prints the results
+pub fn run_config_verify(args: &[String]) {
+    let sources = match (args.get(0).map(|s| s.as_str()), args.get(1)) {
+        (None, _) => contract_sources(&PoolParameters::new()),
+        (Some("--sources"), Some(dir)) => match ContractSources::from_dir(dir) {
+            Ok(sources) => sources,
+            Err(e) => {
+                println!("Failed to read the contract sources.\nError: {:?}", e);
+                return;
+            }
+        },
+        _ => {
+            println!("Usage: oracle-core config verify [--sources <contract source directory>]");
+            return;
+        }
+    };
+    println!(
+        "Verifying the configured contracts against the {} sources.",
+        sources.origin
+    );
+    match verify_contracts(&sources) {
+        Ok(verifications) => {
+            for v in &verifications {
+                print_verification(v);
+            }
+            if verifications.iter().all(|v| v.matches) {
+                println!("All configured contracts match the compiled sources.");
+            } else {
+                println!("Some configured contracts do not match the compiled sources.\nPlease check the pool parameters/token ids in your `oracle-config.yaml`.\nIf the pool was created from different contract sources (ie. another protocol version), verify against them with `--sources <dir>`.");
+            }
+        }
+        Err(e) => println!("Failed to verify the configured contracts.\nError: {:?}", e),
+    }
+}
+
+fn print_verification(v: &ContractVerification) {
+    println!("{}: {}", v.name, if v.matches { "OK" } else { "MISMATCH" });
+    if !v.matches {
+        println!("  Configured: {}", v.configured_address);
+        println!("  Compiled:   {}", v.compiled_address);
+        for c in &v.missing_constants {
+            println!("  Constant not found in configured contract: {}", c);
+        }
+    }
+}
+
+/// Compiles the given contract sources with the configured token
+/// ids/pool parameters and compares them with the configured addresses
+pub fn verify_contracts(sources: &ContractSources) -> Result<Vec<ContractVerification>> {
+    let parameters = PoolParameters::new();
+    let pool_nft = get_config_str("oracle_pool_nft")?;
+    let participant_token = get_config_str("oracle_pool_participant_token")?;
+    let compiled = compile_contracts(&parameters, sources, &pool_nft, &participant_token)?;
+    let constants = pool_constants(&parameters, &pool_nft, &participant_token);
+
+    let contracts = vec![
+        (
+            "Live Epoch",
+            "live_epoch_contract_address",
+            &sources.live_epoch,
+            compiled.live_epoch,
+        ),
+        (
+            "Epoch Preparation",
+            "epoch_preparation_contract_address",
+            &sources.epoch_preparation,
+            compiled.epoch_preparation,
+        ),
+        (
+            "Datapoint",
+            "datapoint_contract_address",
+            &sources.datapoint,
+            compiled.datapoint,
+        ),
+        (
+            "Pool Deposit",
+            "pool_deposit_contract_address",
+            &sources.pool_deposit,
+            compiled.pool_deposit,
+        ),
+    ];
+
+    let mut verifications = vec![];
+    for (name, key, source, compiled_address) in contracts {
+        let configured_address = get_config_str(key)?;
+        let configured_tree = address_to_tree(&configured_address)?;
+        let compiled_tree = address_to_tree(&compiled_address)?;
+        let referenced: Vec<(&str, String)> = constants
+            .iter()
+            .filter(|(n, _)| references_identifier(source, n))
+            .cloned()
+            .collect();
+        verifications.push(ContractVerification {
+            name: name,
+            configured_address: configured_address,
+            compiled_address: compiled_address,
+            matches: configured_tree == compiled_tree,
+            missing_constants: missing_constants(&configured_tree, &referenced),
+        });
+    }
+    Ok(verifications)
+}
+
+/// Returns the constants (formatted as `name = value`) which are not among the
+/// constants of the hex-encoded ErgoTree. Numeric constants may be serialized
+/// as either `Int` or `Long` by the compiler, so both are checked. If the
+/// tree's constants are not segregated, the serialized values are searched
+/// for at byte boundaries of the tree instead.
+pub fn missing_constants(tree: &String, constants: &Vec<(&str, String)>) -> Vec<String> {
+    let tree_constants = tree_constants(tree).ok();
+    constants
+        .iter()
+        .filter(|(_, value)| {
+            !constant_encodings(value)
+                .iter()
+                .any(|e| match &tree_constants {
+                    Some(tree_constants) => tree_constants.contains(e),
+                    None => contains_bytes(tree, e),
+                })
+        })
+        .map(|(name, value)| format!("{} = {}", name, value))
+        .collect()
+}
+
+/// Parses the segregated constants of a hex-encoded ErgoTree, returning each
+/// constant's serialized value as hex
+pub fn tree_constants(tree: &str) -> Result<Vec<String>> {
+    let header = u8::from_str_radix(tree.get(0..2).unwrap_or(""), 16)?;
+    if header & 0x10 == 0 {
+        return Err(anyhow!("The ErgoTree constants are not segregated."));
+    }
+    let mut pos = 2;
+    // Trees with the size flag hold their size before the constants
+    if header & 0x08 != 0 {
+        pos += decode_vlq(&tree[pos..])?.1;
+    }
+    let (count, len) = decode_vlq(&tree[pos..])?;
+    pos += len;
+
+    // The constants are parsed one after the other from a single reader
+    let bytes = base16::decode(&tree[pos..])?;
+    let mut reader = sigma_byte_reader::from_bytes(&bytes);
+    let mut constants = vec![];
+    for _ in 0..count {
+        let constant = Constant::sigma_parse(&mut reader)
+            .map_err(|e| anyhow!("Failed to parse ErgoTree constant: {:?}", e))?;
+        constants.push(base16::encode_lower(&constant.sigma_serialize_bytes()));
+    }
+    Ok(constants)
+}
+
+/// Whether the hex-encoded bytes appear in the hex-encoded tree at a byte boundary
+fn contains_bytes(tree: &str, bytes: &str) -> bool {
+    (0..tree.len())
+        .step_by(2)
+        .any(|i| tree[i..].starts_with(bytes))
+}
+
+/// The possible serialized values of a constant within an ErgoTree
+fn constant_encodings(value: &String) -> Vec<String> {
+    if let Some(hex) = value
+        .strip_prefix("fromBase16(\"")
+        .and_then(|v| v.strip_suffix("\")"))
+    {
+        return match base16::decode(hex) {
+            Ok(bytes) => vec![Constant::from(bytes).base16_str()],
+            Err(_) => vec![],
+        };
+    }
+    match value.trim_end_matches('L').parse::<i64>() {
+        Ok(n) => vec![
+            Constant::from(n as i32).base16_str(),
+            Constant::from(n).base16_str(),
+        ],
+        Err(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Starts with `040a`, the serialized Int 5
+    static TOKEN_ID: &str = "040a27dd9d8a35aac1e3167d58858c0a8b4059b277da790552e37eba22df9b90";
+
+    fn constants() -> Vec<(&'static str, String)> {
+        vec![
+            ("epochPeriod", "20".to_string()),
+            ("maxDeviation", "5".to_string()),
+            ("oracleTokenId", format!("fromBase16(\"{}\")", TOKEN_ID)),
+        ]
+    }
+
+    #[test]
+    fn constants_missing_from_segregated_trees_are_reported() {
+        let tree = format!("10020428{}{}d1ed", "0e20", TOKEN_ID);
+        assert_eq!(
+            tree_constants(&tree).unwrap(),
+            vec!["0428".to_string(), format!("0e20{}", TOKEN_ID)]
+        );
+        // The Int 5 only appears within the token id, which does not count
+        assert_eq!(
+            missing_constants(&tree, &constants()),
+            vec!["maxDeviation = 5".to_string()]
+        );
+    }
+
+    #[test]
+    fn unsegregated_trees_are_searched_at_byte_boundaries() {
+        // The Int 5 only appears at an odd offset
+        let tree = "000428a040a0".to_string();
+        assert_eq!(
+            missing_constants(&tree, &constants()),
+            vec![
+                "maxDeviation = 5".to_string(),
+                format!("oracleTokenId = fromBase16(\"{}\")", TOKEN_ID)
+            ]
+        );
+    }
+}
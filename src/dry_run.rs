@@ -0,0 +1,187 @@
+/// This file holds the logic for "dry running" actions. Instead of posting
+/// the action tx, the full tx request is decoded into a human readable json
+/// report which is printed/returned for debugging & reviewing action logic.
+use crate::encoding::decode_group_element_list;
+use crate::node_interface::generate_transaction;
+use crate::oracle_state::OraclePool;
+use crate::Result;
+use anyhow::anyhow;
+use ergo_lib::chain::address::{Address, AddressEncoder, NetworkPrefix};
+use ergo_lib::chain::ergo_box::ErgoBox;
+use ergo_lib::chain::Base16Str;
+use ergo_lib::ergotree_ir::mir::constant::Constant;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_offchain_utilities::encoding::{unwrap_hex_encoded_string, unwrap_int, unwrap_long};
+use json::JsonValue;
+
+impl OraclePool {
+    /// Builds a json report of the tx which the given tx request would produce,
+    /// including decoded inputs, data-inputs, output registers and the fee.
+    /// The tx is signed by the node wallet for the pre-flight check, but is never submitted.
+    pub fn dry_run_report(&self, action_name: &str, tx_request_json: &JsonValue) -> Result<String> {
+        let network = network_prefix(&self.live_epoch_stage.contract_address)?;
+        let inputs = decode_raw_boxes(&tx_request_json["inputsRaw"], network)?;
+        let data_inputs = decode_raw_boxes(&tx_request_json["dataInputsRaw"], network)?;
+
+        let mut outputs = vec![];
+        for request in tx_request_json["requests"].members() {
+            outputs.push(object! {
+                "address": request["address"].clone(),
+                "value": request["value"].clone(),
+                "assets": request["assets"].clone(),
+                "registers": decode_register_request(&request["registers"])?,
+            });
+        }
+
+        // Check whether the tx would pass the pre-flight check if it were posted
+        let preflight = match generate_transaction(tx_request_json) {
+            Ok(signed_tx) => match self.preflight_check(&signed_tx) {
+                Ok(_) => "Passed".to_string(),
+                Err(e) => e.to_string(),
+            },
+            Err(e) => format!("Failed to generate tx: {:?}", e),
+        };
+
+        let report = object! {
+            "action": action_name,
+            "dry_run": true,
+            "inputs": inputs,
+            "data_inputs": data_inputs,
+            "outputs": outputs,
+            "fee": tx_request_json["fee"].clone(),
+            "preflight_check": preflight,
+        };
+        Ok(report.pretty(2))
+    }
+}
+
+/// The network prefix of the pool's addresses, derived from one of them (ie. a contract address)
+fn network_prefix(address: &str) -> Result<NetworkPrefix> {
+    for network in vec![NetworkPrefix::Mainnet, NetworkPrefix::Testnet] {
+        if AddressEncoder::new(network)
+            .parse_address_from_str(address)
+            .is_ok()
+        {
+            return Ok(network);
+        }
+    }
+    Err(anyhow!(
+        "Failed to derive the network from the address `{}`.",
+        address
+    ))
+}
+
+/// Decodes a list of serialized boxes (as used in `inputsRaw`/`dataInputsRaw`) into json
+fn decode_raw_boxes(raw_boxes: &JsonValue, network: NetworkPrefix) -> Result<Vec<JsonValue>> {
+    let mut boxes = vec![];
+    for raw in raw_boxes.members() {
+        let bytes = base16::decode(&raw.to_string())?;
+        let b = ErgoBox::sigma_parse_bytes(bytes)?;
+        boxes.push(box_to_json(&b, network));
+    }
+    Ok(boxes)
+}
+
+/// Converts an `ErgoBox` into json with its registers decoded
+fn box_to_json(b: &ErgoBox, network: NetworkPrefix) -> JsonValue {
+    let box_id: String = b.box_id().into();
+    let address = Address::recreate_from_ergo_tree(&b.ergo_tree)
+        .map(|a| AddressEncoder::new(network).address_to_str(&a))
+        .unwrap_or("Unknown".to_string());
+    let mut registers = object! {};
+    for (i, constant) in b
+        .additional_registers
+        .get_ordered_values()
+        .iter()
+        .enumerate()
+    {
+        registers[format!("R{}", i + 4)] = constant_to_json(constant);
+    }
+    object! {
+        "box_id": box_id,
+        "address": address,
+        "value": b.value.as_u64().clone(),
+        "creation_height": b.creation_height,
+        "registers": registers,
+    }
+}
+
+/// Decodes the hex-encoded register constants of an output request
+fn decode_register_request(registers: &JsonValue) -> Result<JsonValue> {
+    let mut decoded = object! {};
+    for (register, value) in registers.entries() {
+        let constant = Constant::sigma_parse_bytes(base16::decode(&value.to_string())?)?;
+        decoded[register] = object! {
+            "raw": value.clone(),
+            "decoded": constant_to_json(&constant),
+        };
+    }
+    Ok(decoded)
+}
+
+/// Converts a register constant into json holding its type and value.
+/// Only the register types used by the pool contracts are decoded.
+fn constant_to_json(constant: &Constant) -> JsonValue {
+    let raw = constant.base16_str();
+    if let Ok(i) = unwrap_int(constant) {
+        return object! {"type": "Int", "value": i};
+    }
+    if let Ok(l) = unwrap_long(constant) {
+        return object! {"type": "Long", "value": l};
+    }
+    if raw.starts_with("07") {
+        return object! {"type": "GroupElement", "value": raw[2..].to_string()};
+    }
+    if let Ok(list) = decode_group_element_list(&raw) {
+        let points: Vec<String> = list.iter().map(|ge| ge[2..].to_string()).collect();
+        return object! {"type": "Coll[GroupElement]", "value": points};
+    }
+    if let Ok(hex) = unwrap_hex_encoded_string(constant) {
+        return object! {"type": "Coll[Byte]", "value": hex};
+    }
+    object! {"type": "Unknown", "raw": raw}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_requests_are_decoded_with_types() {
+        let registers = object! {
+            "R4": Constant::from(2000000i64).base16_str(),
+            "R5": Constant::from(130i32).base16_str(),
+            "R6": "1300",
+        };
+        let decoded = decode_register_request(&registers).unwrap();
+        assert_eq!(
+            decoded["R4"]["decoded"],
+            object! {"type": "Long", "value": 2000000}
+        );
+        assert_eq!(
+            decoded["R5"]["decoded"],
+            object! {"type": "Int", "value": 130}
+        );
+        assert_eq!(decoded["R6"]["decoded"]["type"], "Coll[GroupElement]");
+        assert_eq!(decoded["R6"]["raw"], "1300");
+    }
+
+    #[test]
+    fn invalid_raw_boxes_fail_to_decode() {
+        assert!(decode_raw_boxes(&json::array![], NetworkPrefix::Mainnet)
+            .unwrap()
+            .is_empty());
+        assert!(decode_raw_boxes(&json::array!["zz"], NetworkPrefix::Mainnet).is_err());
+        assert!(decode_raw_boxes(&json::array!["00"], NetworkPrefix::Mainnet).is_err());
+    }
+
+    #[test]
+    fn network_prefix_is_derived_from_address() {
+        let mainnet_address = "9iHyKxXs2ZNLMp9N9gbUT9V8gTbsV7HED1C1VhttMfBUMPDyF7r";
+        assert!(matches!(
+            network_prefix(mainnet_address),
+            Ok(NetworkPrefix::Mainnet)
+        ));
+        assert!(network_prefix("invalid").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+/// This file holds helpers for encoding/decoding register values which
+/// are not covered by `ergo_offchain_utilities::encoding`.
+use crate::Result;
+use anyhow::anyhow;
+
+/// Sigma type code of a `GroupElement`
+static GROUP_ELEMENT_TYPE_CODE: &str = "07";
+/// Sigma type code of a `Coll[GroupElement]`
+static GROUP_ELEMENT_COLL_TYPE_CODE: &str = "13";
+/// Length of a hex-encoded compressed EC point
+static EC_POINT_HEX_LENGTH: usize = 66;
+
+/// Given a list of hex-encoded `GroupElement` register values (as produced by
+/// `address_to_raw_for_register`), encode them as a single `Coll[GroupElement]`
+/// register value.
+pub fn encode_group_element_list(group_elements: &Vec<String>) -> Result<String> {
+    let mut encoded = GROUP_ELEMENT_COLL_TYPE_CODE.to_string();
+    encoded.push_str(&encode_vlq(group_elements.len() as u64));
+    for ge in group_elements {
+        if !ge.starts_with(GROUP_ELEMENT_TYPE_CODE) || ge.len() != EC_POINT_HEX_LENGTH + 2 {
+            return Err(anyhow!("Invalid GroupElement register value: {}", ge));
+        }
+        encoded.push_str(&ge[2..]);
+    }
+    Ok(encoded)
+}
+
+/// Given a hex-encoded `Coll[GroupElement]` register value, decode it into
+/// a list of hex-encoded `GroupElement` register values.
+pub fn decode_group_element_list(encoded: &str) -> Result<Vec<String>> {
+    if !encoded.starts_with(GROUP_ELEMENT_COLL_TYPE_CODE) {
+        return Err(anyhow!(
+            "Register value is not a Coll[GroupElement]: {}",
+            encoded
+        ));
+    }
+    let (length, vlq_hex_length) = decode_vlq(&encoded[2..])?;
+    let points = &encoded[2 + vlq_hex_length..];
+    if points.len() != length as usize * EC_POINT_HEX_LENGTH {
+        return Err(anyhow!(
+            "Malformed Coll[GroupElement] register value: {}",
+            encoded
+        ));
+    }
+    Ok((0..length as usize)
+        .map(|i| {
+            GROUP_ELEMENT_TYPE_CODE.to_string()
+                + &points[i * EC_POINT_HEX_LENGTH..(i + 1) * EC_POINT_HEX_LENGTH]
+        })
+        .collect())
+}
+
+/// Encodes an unsigned integer as hex-encoded VLQ bytes
+fn encode_vlq(mut n: u64) -> String {
+    let mut bytes = vec![];
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(byte);
+            break;
+        }
+        bytes.push(byte | 0x80);
+    }
+    base16::encode_lower(&bytes)
+}
+
+/// Decodes hex-encoded VLQ bytes at the start of the input.
+/// Returns the integer and the number of hex characters which were read.
+pub fn decode_vlq(hex: &str) -> Result<(u64, usize)> {
+    let mut n: u64 = 0;
+    for i in 0..10 {
+        let byte_hex = hex
+            .get(i * 2..i * 2 + 2)
+            .ok_or(anyhow!("Unexpected end of VLQ encoded value."))?;
+        let byte = u8::from_str_radix(byte_hex, 16)?;
+        n |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((n, i * 2 + 2));
+        }
+    }
+    Err(anyhow!("VLQ encoded value is too long."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static GE_A: &str = "0702a2b2d6e4b1a2bfd47e3b5f2e6e0b14d6d5c2f8e5b3c26d0a6e4f8d9a1b2c3d4e";
+    static GE_B: &str = "0703f1e2d3c4b5a69788796a5b4c3d2e1f0a1b2c3d4e5f60718293a4b5c6d7e8f90a";
+
+    #[test]
+    fn group_element_list_round_trip() {
+        let list = vec![GE_A.to_string(), GE_B.to_string()];
+        let encoded = encode_group_element_list(&list).unwrap();
+        assert_eq!(&encoded[0..4], "1302");
+        assert_eq!(decode_group_element_list(&encoded).unwrap(), list);
+    }
+
+    #[test]
+    fn empty_group_element_list() {
+        let encoded = encode_group_element_list(&vec![]).unwrap();
+        assert_eq!(encoded, "1300");
+        assert!(decode_group_element_list(&encoded).unwrap().is_empty());
+    }
+
+    #[test]
+    fn vlq_encoding() {
+        assert_eq!(encode_vlq(1), "01");
+        assert_eq!(encode_vlq(300), "ac02");
+        assert_eq!(decode_vlq("ac02").unwrap(), (300, 4));
+    }
+}
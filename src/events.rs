@@ -0,0 +1,555 @@
+/// This file holds the `/events` stream of the oracle core. An `EventMonitor`
+/// polls the state of the oracle pool every few seconds and publishes a
+/// `PoolEvent` for every change it observes, while the actions posted by the
+/// core publish their own events. Clients subscribe via server-sent events
+/// (SSE) on the events API port, so they can react to changes immediately
+/// rather than polling the GET API.
+use crate::api::api_tls_proxy;
+use crate::api_v1::PoolStage;
+use crate::funding::FundingForecast;
+use crate::node_interface::{current_block_height, raw_from_register_to_address, tx_confirmations};
+use crate::oracle_config::{
+    get_core_api_bind_address, get_events_api_port, get_funding_alert_epochs, PoolParameters,
+};
+use crate::oracle_state::{OracleDatapoint, OraclePool, PoolBoxState};
+use crate::pool_history::{latest_pool_history_entry, PoolHistoryEntry};
+use crate::tls::{InternalConnection, InternalListener};
+use crate::{BlockDuration, BlockHeight, EpochID, NanoErg, TxId};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How often the `EventMonitor` polls the state of the oracle pool
+pub static EVENT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of recent events kept to be replayed to reconnecting clients
+static MAX_RECENT_EVENTS: usize = 100;
+/// Number of blocks a submitted action tx is tracked for until it is confirmed
+static MAX_TX_TRACKING_BLOCKS: u64 = 30;
+/// How often a comment is sent to idle event streams to keep them open
+static KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A change in the state of the oracle pool/oracle core
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PoolEvent {
+    /// A new block was mined
+    NewBlock { height: BlockHeight },
+    /// The oracle pool box moved to another stage
+    StageChanged { stage: PoolStage },
+    /// A new Live Epoch was started
+    EpochStarted {
+        epoch_id: EpochID,
+        epoch_ends: BlockHeight,
+    },
+    /// An oracle committed a datapoint
+    DatapointCommitted {
+        oracle_address: Option<String>,
+        datapoint: u64,
+        epoch_id: EpochID,
+        creation_height: BlockHeight,
+    },
+    /// The datapoints of an epoch were collected into a finalized datapoint
+    EpochCollected {
+        box_id: String,
+        datapoint: u64,
+        creation_height: BlockHeight,
+    },
+    /// The funding runway of the pool dropped to `funding_alert_epochs` epochs or less
+    FundingLow {
+        funds: NanoErg,
+        pending_deposits: NanoErg,
+        epochs_remaining: u64,
+        blocks_remaining: BlockDuration,
+    },
+    /// The core requested the connector to repost its datapoint
+    RepostRequested,
+    /// The core posted an action tx
+    ActionSubmitted { action: String, tx_id: TxId },
+    /// An action tx posted by the core was confirmed
+    ActionConfirmed {
+        action: String,
+        tx_id: TxId,
+        confirmations: u64,
+    },
+}
+
+impl PoolEvent {
+    /// The name of the event (the `event` field of the SSE message)
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolEvent::NewBlock { .. } => "new_block",
+            PoolEvent::StageChanged { .. } => "stage_changed",
+            PoolEvent::EpochStarted { .. } => "epoch_started",
+            PoolEvent::DatapointCommitted { .. } => "datapoint_committed",
+            PoolEvent::EpochCollected { .. } => "epoch_collected",
+            PoolEvent::FundingLow { .. } => "funding_low",
+            PoolEvent::RepostRequested => "repost_requested",
+            PoolEvent::ActionSubmitted { .. } => "action_submitted",
+            PoolEvent::ActionConfirmed { .. } => "action_confirmed",
+        }
+    }
+
+    /// Formats the event as an SSE message
+    pub fn to_sse_message(&self, id: u64) -> String {
+        format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            id,
+            self.name(),
+            serde_json::to_string(self).unwrap_or_default()
+        )
+    }
+}
+
+/// An action tx which is awaiting confirmation
+struct PendingTx {
+    action: String,
+    tx_id: TxId,
+    /// Height the tx was first checked at
+    first_seen: Option<BlockHeight>,
+}
+
+/// The published events and their subscribers
+struct EventBus {
+    next_id: u64,
+    recent: VecDeque<(u64, PoolEvent)>,
+    subscribers: Vec<Sender<(u64, PoolEvent)>>,
+    pending_txs: Vec<PendingTx>,
+}
+
+lazy_static! {
+    static ref EVENT_BUS: Mutex<EventBus> = Mutex::new(EventBus {
+        next_id: 1,
+        recent: VecDeque::new(),
+        subscribers: vec![],
+        pending_txs: vec![],
+    });
+}
+
+/// Publishes an event to every subscriber
+pub fn publish(event: PoolEvent) {
+    let mut bus = EVENT_BUS.lock().unwrap();
+    let id = bus.next_id;
+    bus.next_id += 1;
+    bus.subscribers
+        .retain(|s| s.send((id, event.clone())).is_ok());
+    bus.recent.push_back((id, event));
+    if bus.recent.len() > MAX_RECENT_EVENTS {
+        bus.recent.pop_front();
+    }
+}
+
+/// Subscribes to the published events. Returns the recent events after
+/// `last_event_id` (if provided) along with the receiver of new events.
+pub fn subscribe(
+    last_event_id: Option<u64>,
+) -> (Vec<(u64, PoolEvent)>, Receiver<(u64, PoolEvent)>) {
+    let mut bus = EVENT_BUS.lock().unwrap();
+    let (sender, receiver) = unbounded();
+    bus.subscribers.push(sender);
+    let missed = match last_event_id {
+        Some(last_id) => bus
+            .recent
+            .iter()
+            .filter(|(id, _)| *id > last_id)
+            .cloned()
+            .collect(),
+        None => vec![],
+    };
+    (missed, receiver)
+}
+
+/// Publishes an `ActionSubmitted` event and tracks the tx until it is confirmed
+pub fn action_submitted(action: &str, tx_id: &str) {
+    let tx_id: TxId = tx_id.chars().filter(|&c| c != '\"').collect();
+    EVENT_BUS.lock().unwrap().pending_txs.push(PendingTx {
+        action: action.to_string(),
+        tx_id: tx_id.clone(),
+        first_seen: None,
+    });
+    publish(PoolEvent::ActionSubmitted {
+        action: action.to_string(),
+        tx_id: tx_id,
+    });
+}
+
+/// The state of the oracle pool which events are derived from
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolSnapshot {
+    pub height: BlockHeight,
+    pub stage: PoolStage,
+    /// Id & end height of the current Live Epoch
+    pub live_epoch: Option<(EpochID, BlockHeight)>,
+    /// The latest finalized datapoint
+    pub collection: Option<PoolHistoryEntry>,
+    pub datapoints: Vec<OracleDatapoint>,
+    pub funding: Option<FundingForecast>,
+}
+
+impl PoolSnapshot {
+    pub fn new(op: &OraclePool) -> Option<PoolSnapshot> {
+        Some(PoolSnapshot {
+            height: current_block_height().ok()?,
+            stage: match op.check_oracle_pool_stage() {
+                PoolBoxState::LiveEpoch => PoolStage::LiveEpoch,
+                PoolBoxState::Preparation => PoolStage::EpochPreparation,
+            },
+            live_epoch: op
+                .get_live_epoch_state()
+                .map(|l| (l.epoch_id, l.epoch_ends))
+                .ok(),
+            collection: latest_pool_history_entry(op).ok(),
+            datapoints: op.get_all_datapoints().unwrap_or_default(),
+            funding: op.get_funding_forecast(&PoolParameters::new()).ok(),
+        })
+    }
+
+    /// The events which took place between the `previous` snapshot and this
+    /// one, using `address_of` to convert oracle addresses held in registers.
+    /// A funding alert is raised once the runway drops to `funding_alert_epochs`.
+    pub fn events_since(
+        &self,
+        previous: &PoolSnapshot,
+        funding_alert_epochs: u64,
+        address_of: impl Fn(&String) -> Option<String>,
+    ) -> Vec<PoolEvent> {
+        let mut events = vec![];
+        if self.height > previous.height {
+            events.push(PoolEvent::NewBlock {
+                height: self.height,
+            });
+        }
+        if self.stage != previous.stage {
+            events.push(PoolEvent::StageChanged { stage: self.stage });
+        }
+        if let Some((epoch_id, epoch_ends)) = &self.live_epoch {
+            if previous.live_epoch.as_ref().map(|l| &l.0) != Some(epoch_id) {
+                events.push(PoolEvent::EpochStarted {
+                    epoch_id: epoch_id.clone(),
+                    epoch_ends: *epoch_ends,
+                });
+            }
+        }
+        if let Some(collection) = &self.collection {
+            let collected_before = previous
+                .collection
+                .as_ref()
+                .map(|c| c.is_same_collection(collection))
+                .unwrap_or(false);
+            if !collected_before {
+                events.push(PoolEvent::EpochCollected {
+                    box_id: collection.box_id.clone(),
+                    datapoint: collection.datapoint,
+                    creation_height: collection.creation_height,
+                });
+            }
+        }
+        if let Some(funding) = &self.funding {
+            let was_low = previous
+                .funding
+                .as_ref()
+                .map(|f| f.is_low(funding_alert_epochs))
+                .unwrap_or(false);
+            if funding.is_low(funding_alert_epochs) && !was_low {
+                events.push(PoolEvent::FundingLow {
+                    funds: funding.funds,
+                    pending_deposits: funding.pending_deposits,
+                    epochs_remaining: funding.epochs_remaining.unwrap_or(0),
+                    blocks_remaining: funding.blocks_remaining.unwrap_or(0),
+                });
+            }
+        }
+        for d in &self.datapoints {
+            if !previous.datapoints.contains(d) {
+                events.push(PoolEvent::DatapointCommitted {
+                    oracle_address: address_of(&d.oracle_address_raw),
+                    datapoint: d.datapoint,
+                    epoch_id: d.origin_epoch_id.clone(),
+                    creation_height: d.creation_height,
+                });
+            }
+        }
+        events
+    }
+}
+
+/// Publishes the changes of the oracle pool state
+pub struct EventMonitor {
+    previous: Option<PoolSnapshot>,
+    funding_alert_epochs: u64,
+}
+
+impl EventMonitor {
+    pub fn new() -> EventMonitor {
+        EventMonitor {
+            previous: None,
+            funding_alert_epochs: get_funding_alert_epochs(),
+        }
+    }
+
+    /// Polls the state of the oracle pool and publishes the events since the previous poll
+    pub fn update(&mut self, op: &OraclePool) {
+        let snapshot = match PoolSnapshot::new(op) {
+            Some(s) => s,
+            None => return,
+        };
+        if let Some(previous) = &self.previous {
+            let address_of = |raw: &String| raw_from_register_to_address(raw).ok();
+            for event in snapshot.events_since(previous, self.funding_alert_epochs, address_of) {
+                publish(event);
+            }
+        }
+        check_pending_txs(snapshot.height);
+        self.previous = Some(snapshot);
+    }
+}
+
+/// Publishes an `ActionConfirmed` event for every tracked action tx which
+/// was confirmed, and stops tracking txs which were not confirmed in time
+fn check_pending_txs(height: BlockHeight) {
+    let pending_txs: Vec<PendingTx> = std::mem::take(&mut EVENT_BUS.lock().unwrap().pending_txs);
+    let mut still_pending = vec![];
+    for mut tx in pending_txs {
+        let first_seen = *tx.first_seen.get_or_insert(height);
+        match tx_confirmations(&tx.tx_id) {
+            Ok(Some(confirmations)) if confirmations > 0 => publish(PoolEvent::ActionConfirmed {
+                action: tx.action,
+                tx_id: tx.tx_id,
+                confirmations: confirmations,
+            }),
+            _ if height > first_seen + MAX_TX_TRACKING_BLOCKS => (),
+            _ => still_pending.push(tx),
+        }
+    }
+    EVENT_BUS.lock().unwrap().pending_txs.extend(still_pending);
+}
+
+/// Runs the `EventMonitor` (blocks the thread)
+pub fn run_event_monitor(op: OraclePool) {
+    let mut monitor = EventMonitor::new();
+    loop {
+        monitor.update(&op);
+        thread::sleep(EVENT_POLL_INTERVAL);
+    }
+}
+
+/// Starts the events API server which streams the published events as
+/// server-sent events on `/events`
+pub fn start_events_api() {
+    let address = get_core_api_bind_address() + ":" + &get_events_api_port();
+    // If TLS is enabled, the server only listens locally behind the TLS proxy
+    let tls = api_tls_proxy(false);
+    let listener = match &tls {
+        Some(proxy) => {
+            let internal_listener =
+                InternalListener::reserve().expect("Failed to reserve a local API server port.");
+            proxy
+                .clone()
+                .spawn(&address, internal_listener.address())
+                .expect("Failed to start the TLS events API listener.");
+            internal_listener.into_listener()
+        }
+        None => TcpListener::bind(&address).expect("Failed to start the events API server."),
+    };
+    for mut stream in listener.incoming().flatten() {
+        // Connections which did not come through the TLS proxy are rejected
+        if let (Some(proxy), Ok(peer)) = (&tls, stream.peer_addr()) {
+            match proxy.internal_connection(peer) {
+                InternalConnection::Forwarded(_) => (),
+                InternalConnection::Probe(token) => {
+                    respond_to_probe(&mut stream, &token).ok();
+                    continue;
+                }
+                InternalConnection::Rejected => continue,
+            }
+        }
+        thread::spawn(move || {
+            handle_events_request(stream).ok();
+        });
+    }
+}
+
+/// Responds to the request of the `TlsProxy` verifying the server
+fn respond_to_probe(stream: &mut TcpStream, token: &str) -> std::io::Result<()> {
+    read_request_head(stream)?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        token.len(),
+        token
+    )
+}
+
+/// Reads the head of an HTTP request, returning the request path and the
+/// `Last-Event-ID` header (if provided)
+fn read_request_head(stream: &mut TcpStream) -> std::io::Result<(String, Option<u64>)> {
+    let mut head = vec![];
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8192 {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let path = head
+        .lines()
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .unwrap_or("")
+        .split('?')
+        .next()
+        .unwrap_or("")
+        .to_string();
+    let last_event_id = head
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Last-Event-ID"))
+        .and_then(|(_, value)| value.trim().parse().ok());
+    Ok((path, last_event_id))
+}
+
+/// Streams the published events to a client until it disconnects
+fn handle_events_request(mut stream: TcpStream) -> std::io::Result<()> {
+    let (path, last_event_id) = read_request_head(&mut stream)?;
+    if path != "/events" {
+        return stream.write_all(
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+    }
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\nAccess-Control-Allow-Origin: *\r\n\r\n")?;
+
+    let (missed, receiver) = subscribe(last_event_id);
+    for (id, event) in missed {
+        stream.write_all(event.to_sse_message(id).as_bytes())?;
+    }
+    loop {
+        match receiver.recv_timeout(KEEP_ALIVE_INTERVAL) {
+            Ok((id, event)) => stream.write_all(event.to_sse_message(id).as_bytes())?,
+            Err(RecvTimeoutError::Timeout) => stream.write_all(b": keep-alive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datapoint(oracle: &str, epoch_id: &str) -> OracleDatapoint {
+        OracleDatapoint {
+            oracle_address_raw: oracle.to_string(),
+            datapoint: 251821000,
+            origin_epoch_id: epoch_id.to_string(),
+            creation_height: 288677,
+        }
+    }
+
+    fn snapshot() -> PoolSnapshot {
+        PoolSnapshot {
+            height: 288680,
+            stage: PoolStage::EpochPreparation,
+            live_epoch: None,
+            collection: None,
+            datapoints: vec![datapoint("a", "e0")],
+            funding: None,
+        }
+    }
+
+    #[test]
+    fn events_are_derived_from_snapshots() {
+        let previous = snapshot();
+        let address_of = |raw: &String| Some(raw.clone());
+        assert!(previous.events_since(&previous, 10, address_of).is_empty());
+
+        let mut next = snapshot();
+        next.height += 1;
+        next.stage = PoolStage::LiveEpoch;
+        next.live_epoch = Some(("e1".to_string(), 288690));
+        next.datapoints.push(datapoint("b", "e1"));
+        let events = next.events_since(&previous, 10, address_of);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], PoolEvent::NewBlock { height: 288681 });
+        assert_eq!(
+            events[1],
+            PoolEvent::StageChanged {
+                stage: PoolStage::LiveEpoch
+            }
+        );
+        assert_eq!(events[2].name(), "epoch_started");
+        assert_eq!(
+            events[3],
+            PoolEvent::DatapointCommitted {
+                oracle_address: Some("b".to_string()),
+                datapoint: 251821000,
+                epoch_id: "e1".to_string(),
+                creation_height: 288677,
+            }
+        );
+    }
+
+    #[test]
+    fn funding_alert_is_raised_once() {
+        let forecast = |funds| FundingForecast {
+            funds: funds,
+            pending_deposits: 0,
+            minimum_pool_box_value: 10000000,
+            funded_ratio: funds as f64 / 10000000.0,
+            cost_per_epoch: 5000000,
+            epochs_remaining: Some((funds - 10000000) / 5000000 + 1),
+            blocks_remaining: Some(((funds - 10000000) / 5000000 + 1) * 30),
+            seconds_remaining: None,
+        };
+        let address_of = |raw: &String| Some(raw.clone());
+        let mut previous = snapshot();
+        previous.funding = Some(forecast(30000000));
+        let mut next = snapshot();
+        next.funding = Some(forecast(25000000));
+        assert_eq!(
+            next.events_since(&previous, 4, address_of),
+            vec![PoolEvent::FundingLow {
+                funds: 25000000,
+                pending_deposits: 0,
+                epochs_remaining: 4,
+                blocks_remaining: 120,
+            }]
+        );
+        assert!(next.events_since(&previous, 3, address_of).is_empty());
+        assert!(next.events_since(&next, 4, address_of).is_empty());
+    }
+
+    #[test]
+    fn collect_funds_is_not_a_collection() {
+        let collection = |box_id: &str, epoch_ends| PoolHistoryEntry {
+            box_id: box_id.to_string(),
+            datapoint: 251821000,
+            epoch_ends: epoch_ends,
+            creation_height: 288690,
+        };
+        let address_of = |raw: &String| Some(raw.clone());
+        let mut previous = snapshot();
+        previous.collection = Some(collection("a", 288699));
+        let mut next = snapshot();
+        next.collection = Some(collection("b", 288699));
+        assert!(next.events_since(&previous, 10, address_of).is_empty());
+        next.collection = Some(collection("c", 288709));
+        assert_eq!(
+            next.events_since(&previous, 10, address_of)[0].name(),
+            "epoch_collected"
+        );
+    }
+
+    #[test]
+    fn events_are_formatted_as_sse_messages() {
+        let event = PoolEvent::NewBlock { height: 288681 };
+        assert_eq!(
+            event.to_sse_message(7),
+            "id: 7\nevent: new_block\ndata: {\"type\":\"new_block\",\"height\":288681}\n\n"
+        );
+    }
+}
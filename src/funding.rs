@@ -0,0 +1,135 @@
+/// This file holds the funding forecast of the oracle pool. Every epoch the
+/// collector pays each oracle which committed a datapoint the oracle payout
+/// price, and itself the payout price once more, out of the pool box. The
+/// forecast projects how many more epochs the pool box (plus the Ergs waiting
+/// in pool deposit boxes) can pay for before its value drops below the
+/// `minimum_pool_box_value`, assuming every oracle commits in every epoch.
+use crate::block_time::average_block_interval;
+use crate::oracle_config::PoolParameters;
+use crate::oracle_state::OraclePool;
+use crate::{BlockDuration, NanoErg, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The projected runway of the oracle pool's funds
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct FundingForecast {
+    /// nanoErgs held in the pool box
+    pub funds: NanoErg,
+    /// nanoErgs held in pool deposit boxes which have not been collected yet
+    pub pending_deposits: NanoErg,
+    pub minimum_pool_box_value: NanoErg,
+    /// The pool box funds relative to the minimum pool box value (1.0 = 100%)
+    pub funded_ratio: f64,
+    /// nanoErgs paid out of the pool box each epoch
+    pub cost_per_epoch: NanoErg,
+    /// Number of epochs until the funds (including pending deposits) drop
+    /// below the minimum pool box value (null if epochs cost nothing)
+    pub epochs_remaining: Option<u64>,
+    /// Estimated number of blocks until the funds drop below the minimum
+    pub blocks_remaining: Option<BlockDuration>,
+    /// Estimated number of seconds until the funds drop below the minimum
+    pub seconds_remaining: Option<u64>,
+}
+
+impl FundingForecast {
+    /// Whether the runway is at most `alert_epochs` epochs
+    pub fn is_low(&self, alert_epochs: u64) -> bool {
+        self.epochs_remaining
+            .map(|epochs| epochs <= alert_epochs)
+            .unwrap_or(false)
+    }
+}
+
+impl OraclePool {
+    /// Forecast the funding runway of the oracle pool
+    pub fn get_funding_forecast(&self, parameters: &PoolParameters) -> Result<FundingForecast> {
+        let funds = match self.get_live_epoch_state() {
+            Ok(l) => l.funds,
+            Err(_) => self.get_preparation_state()?.funds,
+        };
+        let pending_deposits = self
+            .get_pool_deposits_state()
+            .map(|d| d.total_nanoergs)
+            .unwrap_or(0);
+        Ok(forecast_funding(
+            funds,
+            pending_deposits,
+            self.get_oracle_payout_price(parameters)?,
+            self.datapoint_stage.number_of_boxes()?,
+            parameters,
+            average_block_interval(),
+        ))
+    }
+}
+
+/// Forecast the funding runway of a pool paying `payout_price` to each of
+/// `number_of_oracles` oracles (and the collector) every epoch, where blocks
+/// are mined every `block_interval` seconds (on average)
+pub fn forecast_funding(
+    funds: NanoErg,
+    pending_deposits: NanoErg,
+    payout_price: NanoErg,
+    number_of_oracles: u64,
+    parameters: &PoolParameters,
+    block_interval: f64,
+) -> FundingForecast {
+    let minimum = parameters.minimum_pool_box_value;
+    let cost_per_epoch = payout_price.saturating_mul(number_of_oracles + 1);
+    let total_funds = funds.saturating_add(pending_deposits);
+    // The number of epochs `n` after which `total_funds - n * cost_per_epoch < minimum`
+    let epochs_remaining = match (total_funds.checked_sub(minimum), cost_per_epoch) {
+        (_, 0) => None,
+        (None, _) => Some(0),
+        (Some(surplus), cost) => Some(surplus / cost + 1),
+    };
+    let epoch_length = parameters.live_epoch_length + parameters.epoch_preparation_length;
+    let blocks_remaining = epochs_remaining.map(|e| e.saturating_mul(epoch_length));
+    let funded_ratio = if minimum == 0 {
+        1.0
+    } else {
+        funds as f64 / minimum as f64
+    };
+
+    FundingForecast {
+        funds: funds,
+        pending_deposits: pending_deposits,
+        minimum_pool_box_value: minimum,
+        funded_ratio: funded_ratio,
+        cost_per_epoch: cost_per_epoch,
+        epochs_remaining: epochs_remaining,
+        blocks_remaining: blocks_remaining,
+        seconds_remaining: blocks_remaining.map(|b| (b as f64 * block_interval) as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters() -> PoolParameters {
+        PoolParameters::for_tests("")
+    }
+
+    #[test]
+    fn funding_runway_is_forecast() {
+        let parameters = parameters();
+        // 4 oracles + the collector cost 5000000 per epoch, leaving 16000000 above the minimum
+        let forecast = forecast_funding(21000000, 5000000, 1000000, 4, &parameters, 120.0);
+        assert_eq!(forecast.funded_ratio, 2.1);
+        assert_eq!(forecast.cost_per_epoch, 5000000);
+        assert_eq!(forecast.epochs_remaining, Some(4));
+        assert_eq!(forecast.blocks_remaining, Some(120));
+        assert_eq!(forecast.seconds_remaining, Some(14400));
+        assert!(forecast.is_low(4));
+        assert!(!forecast.is_low(3));
+
+        let underfunded = forecast_funding(5000000, 0, 1000000, 4, &parameters, 120.0);
+        assert_eq!(underfunded.funded_ratio, 0.5);
+        assert_eq!(underfunded.epochs_remaining, Some(0));
+
+        let free = forecast_funding(5000000, 0, 0, 4, &parameters, 120.0);
+        assert_eq!(free.epochs_remaining, None);
+        assert!(!free.is_low(10));
+    }
+}
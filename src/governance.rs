@@ -0,0 +1,194 @@
+/// This file holds the logic for governance over the oracle payout price
+/// (governance pools only). Oracles vote by including a new payout price
+/// in R7 of their Datapoint box, and if 75%+ of all oracles in the pool vote
+/// for the same price it is applied when the next epoch starts. As defined
+/// in the Governance Stake Slashing Pool spec, the payout price is the only
+/// pool parameter which can be governed; all other parameters are hard-coded
+/// in the contracts.
+use crate::oracle_config::PoolParameters;
+use crate::oracle_state::OraclePool;
+use crate::{NanoErg, Result};
+use anyhow::anyhow;
+use ergo_lib::chain::Base16Str;
+use ergo_offchain_utilities::encoding::{unwrap_hex_encoded_string, unwrap_long};
+use std::path::Path;
+
+/// File which holds the local oracle's vote so it persists across restarts
+static LOCAL_VOTE_FILE: &str = "governanceVote.json";
+
+/// The governance state of the oracle pool
+#[derive(Debug, Clone)]
+pub struct GovernanceState {
+    /// The oracle payout price currently in effect on-chain
+    pub current_payout_price: NanoErg,
+    /// The votes held in the Datapoint boxes posted in the current/latest epoch
+    pub votes: Vec<OracleVote>,
+    /// The number of votes (out of all oracles) required for a new payout price to pass
+    pub vote_threshold: u64,
+    /// The payout price which will be applied when the next epoch starts
+    /// given the current votes
+    pub resulting_payout_price: NanoErg,
+    /// The vote which the local oracle includes in its datapoint commits
+    pub local_vote: Option<NanoErg>,
+}
+
+/// The vote held in R7 of a single oracle's Datapoint box
+#[derive(Debug, Clone)]
+pub struct OracleVote {
+    /// The oracle's address held in R4 as a hex-encoded `GroupElement` register value
+    pub oracle_address_raw: String,
+    pub payout_price: Option<NanoErg>,
+}
+
+impl OraclePool {
+    /// Get the oracle payout price currently in effect. For governance pools
+    /// this is held on-chain (Live Epoch R7/Epoch Preparation R9), otherwise
+    /// it is the `oracle_payout_price` from the config.
+    pub fn get_oracle_payout_price(&self, parameters: &PoolParameters) -> Result<NanoErg> {
+        if !parameters.governance {
+            return Ok(parameters.oracle_payout_price);
+        }
+        // Held in R7 of the Live Epoch box/R9 of the Epoch Preparation box
+        let price_reg = match self.live_epoch_stage.get_box() {
+            Ok(epoch_box) => epoch_box
+                .additional_registers
+                .get_ordered_values()
+                .get(3)
+                .cloned()
+                .ok_or_else(|| anyhow!("The Live Epoch box is missing the payout price in R7."))?,
+            Err(_) => self
+                .epoch_preparation_stage
+                .get_box()?
+                .additional_registers
+                .get_ordered_values()
+                .get(5)
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow!("The Epoch Preparation box is missing the payout price in R9.")
+                })?,
+        };
+        Ok(unwrap_long(&price_reg)? as NanoErg)
+    }
+
+    /// Get the governance state of the pool (governance pools only)
+    pub fn get_governance_state(&self) -> Result<GovernanceState> {
+        let parameters = PoolParameters::new();
+        if !parameters.governance {
+            return Err(anyhow!("The oracle pool does not use governance."));
+        }
+        let current_payout_price = self.get_oracle_payout_price(&parameters)?;
+
+        // Votes are counted from the Datapoint boxes of the live/latest epoch
+        let epoch_id = match self.get_live_epoch_state() {
+            Ok(l) => l.epoch_id,
+            Err(_) => self.get_stake_slashing_state()?.previous_epoch_id,
+        };
+        let mut votes = vec![];
+        for b in self.datapoint_stage.get_boxes()? {
+            let regs = b.additional_registers.get_ordered_values();
+            let (oracle_address_reg, origin_epoch_id_reg) = match (regs.get(0), regs.get(1)) {
+                (Some(r4), Some(r5)) => (r4, r5),
+                _ => continue,
+            };
+            if unwrap_hex_encoded_string(origin_epoch_id_reg).ok() != Some(epoch_id.clone()) {
+                continue;
+            }
+            votes.push(OracleVote {
+                oracle_address_raw: oracle_address_reg.base16_str(),
+                payout_price: regs
+                    .get(3)
+                    .and_then(|r| unwrap_long(r).ok())
+                    .map(|p| p as u64),
+            });
+        }
+
+        let vote_prices: Vec<Option<NanoErg>> = votes.iter().map(|v| v.payout_price).collect();
+        let threshold = vote_threshold(parameters.number_of_oracles);
+        let governance_state = GovernanceState {
+            current_payout_price: current_payout_price,
+            vote_threshold: threshold,
+            resulting_payout_price: resulting_payout_price(
+                &vote_prices,
+                current_payout_price,
+                threshold,
+            ),
+            votes: votes,
+            local_vote: get_local_vote(),
+        };
+        Ok(governance_state)
+    }
+}
+
+/// The number of votes (75% of all oracles in the pool, rounded up) required
+/// for a new payout price to pass. Mirrors `voteThreshold` in the contract.
+pub fn vote_threshold(number_of_oracles: u64) -> u64 {
+    (number_of_oracles * 3 + 3) / 4
+}
+
+/// Given the votes of the collected oracles, returns the payout price which
+/// must be used for the upcoming epoch.
+pub fn resulting_payout_price(
+    votes: &Vec<Option<NanoErg>>,
+    current_price: NanoErg,
+    threshold: u64,
+) -> NanoErg {
+    votes
+        .iter()
+        .filter_map(|v| *v)
+        .find(|price| votes.iter().filter(|v| **v == Some(*price)).count() as u64 >= threshold)
+        .unwrap_or(current_price)
+}
+
+/// Saves the local oracle's vote for a new payout price. `None` clears the vote.
+pub fn save_local_vote(payout_price: Option<NanoErg>) -> Result<()> {
+    let vote_json = object! {
+        payout_price: payout_price,
+    };
+    std::fs::write(LOCAL_VOTE_FILE, json::stringify_pretty(vote_json, 4))?;
+    Ok(())
+}
+
+/// Returns the local oracle's vote for a new payout price, if one was cast
+pub fn get_local_vote() -> Option<NanoErg> {
+    if !Path::new(LOCAL_VOTE_FILE).exists() {
+        return None;
+    }
+    let vote_json = json::parse(&std::fs::read_to_string(LOCAL_VOTE_FILE).ok()?).ok()?;
+    vote_json["payout_price"].as_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vote_threshold_rounds_up() {
+        assert_eq!(vote_threshold(1), 1);
+        assert_eq!(vote_threshold(4), 3);
+        assert_eq!(vote_threshold(5), 4);
+        assert_eq!(vote_threshold(8), 6);
+    }
+
+    #[test]
+    fn passed_vote_changes_price() {
+        let votes = vec![Some(3000000), Some(3000000), Some(3000000), None];
+        assert_eq!(resulting_payout_price(&votes, 2000000, 3), 3000000);
+    }
+
+    #[test]
+    fn failed_vote_keeps_price() {
+        let votes = vec![Some(3000000), Some(3000000), Some(4000000), None];
+        assert_eq!(resulting_payout_price(&votes, 2000000, 3), 2000000);
+        assert_eq!(resulting_payout_price(&vec![], 2000000, 3), 2000000);
+    }
+
+    #[test]
+    fn threshold_counts_all_oracles() {
+        // 3 of the 4 collected oracles voted, but the pool holds 8 oracles
+        let votes = vec![Some(3000000), Some(3000000), Some(3000000), None];
+        assert_eq!(
+            resulting_payout_price(&votes, 2000000, vote_threshold(8)),
+            2000000
+        );
+    }
+}
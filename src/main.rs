@@ -1,19 +1,40 @@
 #[macro_use]
 extern crate json;
+#[macro_use]
+extern crate lazy_static;
 
 mod actions;
 mod api;
+mod api_security;
+mod api_v1;
+mod backtest;
+mod block_time;
+mod bootstrap;
+mod collector_election;
+mod config_verify;
+mod dry_run;
+mod encoding;
+mod events;
+mod funding;
+mod governance;
 mod node_interface;
 mod oracle_config;
 mod oracle_state;
+mod pool_history;
+mod preflight;
+mod protocol;
 mod scans;
 mod templates;
+mod tls;
 
 use anyhow::Error;
-use crossbeam::channel::bounded;
 use log::info;
 use node_interface::current_block_height;
-use oracle_config::{get_pool_deposits_contract_address, PoolParameters};
+use oracle_config::{
+    get_funding_alert_epochs, get_pool_deposits_contract_address, is_dry_run_action,
+    is_observer_mode, PoolParameters,
+};
+use preflight::PreflightError;
 use std::env;
 use std::thread;
 use std::time::Duration;
@@ -50,25 +71,70 @@ fn main() {
     simple_logging::log_to_file("oracle-core.log", log::LevelFilter::Info).ok();
     log_panics::init();
     let args: Vec<String> = env::args().collect();
+
+    // Bootstrap a new oracle pool on-chain
+    if args.len() > 1 && &args[1] == "bootstrap" {
+        bootstrap::run_bootstrap_command(&args[2..]);
+        return;
+    }
+
+    // Verify the configured contract addresses against the bundled sources
+    if args.len() > 2 && &args[1] == "config" && &args[2] == "verify" {
+        config_verify::run_config_verify(&args[3..]);
+        return;
+    }
+
+    // Replay recorded datapoints through the finalization of the configured pool
+    if args.len() > 1 && &args[1] == "backtest" {
+        backtest::run_backtest_command(&args[2..]);
+        return;
+    }
+
+    // Cast/clear the local oracle's payout price vote (governance pools only)
+    if args.len() > 1 && (&args[1] == "--vote" || &args[1] == "--clear-vote") {
+        cast_local_vote(&args);
+        return;
+    }
+
     let op = oracle_state::OraclePool::new();
-    let (repost_sender, repost_receiver) = bounded(1);
 
     // Start Oracle Core GET API Server
     thread::Builder::new()
         .name("Oracle Core GET API Thread".to_string())
         .spawn(|| {
-            api::start_get_api(repost_receiver);
+            api::start_get_api();
         })
         .ok();
 
-    // Start Oracle Core POST API Server
+    // Start the `/events` stream server & the monitor which publishes pool state changes
     thread::Builder::new()
-        .name("Oracle Core POST API Thread".to_string())
+        .name("Oracle Core Events API Thread".to_string())
         .spawn(|| {
-            api::start_post_api();
+            events::start_events_api();
+        })
+        .ok();
+    let monitor_op = op.clone();
+    thread::Builder::new()
+        .name("Oracle Core Event Monitor Thread".to_string())
+        .spawn(move || {
+            events::run_event_monitor(monitor_op);
         })
         .ok();
 
+    // Start Oracle Core POST API Server (observers cannot submit datapoints)
+    let observer = is_observer_mode();
+    if !observer {
+        thread::Builder::new()
+            .name("Oracle Core POST API Thread".to_string())
+            .spawn(|| {
+                api::start_post_api();
+            })
+            .ok();
+    }
+
+    // Datapoint boxes which have been slashed but are not yet confirmed spent
+    let mut pending_slashes: Vec<String> = vec![];
+
     loop {
         let parameters = oracle_config::PoolParameters::new();
         let height = current_block_height().unwrap_or(0);
@@ -78,9 +144,12 @@ fn main() {
             print_and_log(&mess);
         }
 
-        // If in `read only` mode
-        if args.len() > 1 && &args[1] == "--readonly" {
-            print_and_log("\n===============\nREAD ONLY MODE\n===============\nThe oracle core is running in `read only` mode.\nThis means that no transactions will be created and posted by the core.\nThis mode is intended to be used for easily reading the current state of the oracle pool protocol.");
+        // Record the latest finalized datapoint in the pool history
+        pool_history::record_pool_history(&op).ok();
+
+        // If in observer (`read only`) mode
+        if observer {
+            print_and_log("\n===============\nOBSERVER MODE\n===============\nThe oracle core is running in observer (`read only`) mode.\nThis means that no transactions will be created and posted by the core, and no local oracle or wallet is used.\nThis mode is intended to be used for easily reading the current state of the oracle pool protocol.");
         } else {
             let res_prep_state = op.get_preparation_state();
             let res_live_state = op.get_live_epoch_state();
@@ -99,6 +168,19 @@ fn main() {
                     }
                 }
 
+                // Slash the collateral of misbehaving oracles
+                if parameters.stake_slashing {
+                    if let Ok(slashing_state) = op.get_stake_slashing_state() {
+                        slash_misbehaving_oracles(
+                            &op,
+                            &slashing_state,
+                            height,
+                            &parameters,
+                            &mut pending_slashes,
+                        );
+                    }
+                }
+
                 // Check epoch prep state
                 let is_funded = prep_state.funds >= parameters.minimum_pool_box_value;
                 let epoch_prep_over =
@@ -109,6 +191,12 @@ fn main() {
                 if !is_funded {
                     println!("The Oracle Pool is underfunded.\nTo continue operation of the oracle pool, please submit funds to: {}.", get_pool_deposits_contract_address());
                 }
+                // The Pool will soon be underfunded
+                else if let Ok(forecast) = op.get_funding_forecast(&parameters) {
+                    if forecast.is_low(get_funding_alert_epochs()) {
+                        println!("The Oracle Pool only holds enough funds for {} more epochs (~{} blocks).\nTo continue operation of the oracle pool, please submit funds to: {}.", forecast.epochs_remaining.unwrap_or(0), forecast.blocks_remaining.unwrap_or(0), get_pool_deposits_contract_address());
+                    }
+                }
 
                 // Check if height is prior to next epoch expected end
                 // height and that the pool is funded.
@@ -131,21 +219,37 @@ fn main() {
 
             // If the pool is in the Live Epoch stage
             if let Ok(epoch_state) = res_live_state {
+                // Only the top ranked collector attempts to collect once the epoch
+                // ends, with every following rank waiting an additional backoff
+                let collection_height = op
+                    .get_collector_election(&epoch_state, &parameters)
+                    .ok()
+                    .and_then(|e| e.local_collection_height)
+                    .unwrap_or(epoch_state.epoch_ends);
+
                 // Check for opportunity to Collect Datapoints
-                if height >= epoch_state.epoch_ends && epoch_state.commit_datapoint_in_epoch {
+                if height >= collection_height && epoch_state.commit_datapoint_in_epoch {
                     let action_res = op.action_collect_datapoints();
 
                     // If `Collect Datapoints` action fails
                     if let Err(e) = action_res {
+                        // If a contract condition failed the local pre-flight check
+                        // then the core built an invalid tx, so reposting won't help
+                        if let Some(PreflightError::ContractConditionFailed { .. }) =
+                            e.downcast_ref::<PreflightError>()
+                        {
+                            print_failed_action("Collect Datapoints", &e);
+                        }
                         // Trigger a datapoint repost
-                        if let Ok(dps) = datapoint_state {
+                        else if let Ok(dps) = datapoint_state {
                             // If its been at least 5 blocks since local oracle's previous datapoint posting, then repost
                             if height >= (dps.creation_height + 5) {
                                 println!(
                                     "{:?}\nTriggering a datapoint repost from the Connector.",
                                     e
                                 );
-                                repost_sender.try_send(true).ok();
+                                api_v1::require_datapoint_repost();
+                                events::publish(events::PoolEvent::RepostRequested);
                             } else {
                                 println!(
                                     "{:?}\nDatapoint has been reposted recently. Waiting for other oracles to repost before retrying once again.",
@@ -170,10 +274,92 @@ fn main() {
     }
 }
 
+/// Issues a single "Slash Uncommitted" or "Slash Collector" action tx against
+/// another oracle who misbehaved during the last epoch. Oracles take turns
+/// based on their collector rank, and no new slash is issued until the
+/// previously slashed Datapoint box has been spent.
+fn slash_misbehaving_oracles(
+    op: &oracle_state::OraclePool,
+    slashing_state: &oracle_state::StakeSlashingState,
+    height: BlockHeight,
+    parameters: &PoolParameters,
+    pending_slashes: &mut Vec<String>,
+) {
+    // Slashed Datapoint boxes are replaced once the slash tx is confirmed
+    pending_slashes.retain(|id| slashing_state.stakes.iter().any(|s| &s.box_id == id));
+    if !pending_slashes.is_empty() {
+        return;
+    }
+    match op.get_local_slashing_height(slashing_state, parameters) {
+        Ok(Some(slashing_height)) if height >= slashing_height => (),
+        _ => return,
+    }
+
+    let local_oracle_raw = op
+        .local_oracle()
+        .and_then(|l| node_interface::address_to_raw_for_register(&l.address))
+        .unwrap_or_default();
+
+    let (action_name, slashed_stake, action_res) = if let Some(stake) = slashing_state
+        .uncommitted
+        .iter()
+        .find(|s| s.oracle_address_raw != local_oracle_raw)
+    {
+        ("Slash Uncommitted", stake.clone(), op.action_slash_uncommitted(stake))
+    }
+    // A single missed Datapoint box is sufficient proof to slash the collector
+    else if let (Some(collector_stake), Some(missed_stake)) = (
+        slashing_state.slashable_collector_stake(),
+        slashing_state.missed_by_collector.first(),
+    ) {
+        if collector_stake.oracle_address_raw == local_oracle_raw {
+            return;
+        }
+        let action_res = op.action_slash_collector(&collector_stake, missed_stake);
+        ("Slash Collector", collector_stake, action_res)
+    } else {
+        return;
+    };
+
+    if action_res.is_ok() && !is_dry_run_action(action_name) {
+        pending_slashes.push(slashed_stake.box_id);
+    }
+    print_action_results(&action_res, action_name);
+}
+
+/// Saves the local oracle's vote from the `--vote <payout price>`/`--clear-vote` CLI args.
+/// The vote is included in every following datapoint commit.
+fn cast_local_vote(args: &Vec<String>) {
+    let vote = if &args[1] == "--clear-vote" {
+        None
+    } else {
+        match args.get(2).map(|p| p.parse::<NanoErg>()) {
+            Some(Ok(price)) => Some(price),
+            _ => {
+                println!("Please provide a valid payout price in nanoErgs, ie. `--vote 2000000`.");
+                return;
+            }
+        }
+    };
+    match governance::save_local_vote(vote) {
+        Ok(_) => match vote {
+            Some(price) => print_and_log(&format!(
+                "Your vote for a new oracle payout price of {} nanoErgs has been saved.\nIt will be included in your following datapoint commits.",
+                price
+            )),
+            None => print_and_log("Your payout price vote has been cleared."),
+        },
+        Err(e) => println!("Failed to save your vote.\nError: {:?}", e),
+    }
+}
+
 /// Prints The Results Of An Action, Whether It Failed/Succeeded
 pub fn print_action_results(action_res: &Result<String>, action_name: &str) {
-    if let Ok(tx_id) = action_res {
+    if let (Ok(report), true) = (action_res, is_dry_run_action(action_name)) {
+        print_dry_run_action(&action_name, &report);
+    } else if let Ok(tx_id) = action_res {
         print_successful_action(&action_name, &tx_id);
+        events::action_submitted(action_name, tx_id);
     } else if let Err(e) = action_res {
         print_failed_action(&action_name, &e);
     }
@@ -197,6 +383,15 @@ fn print_successful_action(action_name: &str, tx_id: &str) {
     print_action_response(&message);
 }
 
+/// Prints A Dry Run Action Message
+fn print_dry_run_action(action_name: &str, report: &str) {
+    let message = format!(
+        "`{}` Transaction Dry Run (Not Posted).\nTransaction: {}",
+        action_name, report
+    );
+    print_action_response(&message);
+}
+
 /// Prints A Message With `---`s added
 fn print_action_response(message: &str) {
     let mess = format!(        "--------------------------------------------------\n{}\n--------------------------------------------------",
@@ -214,7 +409,11 @@ fn print_info(
     // Clear screen
     print!("\x1B[2J\x1B[1;1H");
 
-    let datapoint_state = op.get_datapoint_state()?;
+    // Observers have no local oracle Datapoint box
+    let datapoint_state = match op.local_oracle {
+        Some(_) => Some(op.get_datapoint_state()?),
+        None => None,
+    };
     let deposits_state = op.get_pool_deposits_state()?;
     let res_prep_state = op.get_preparation_state();
     let res_live_state = op.get_live_epoch_state();
@@ -233,6 +432,12 @@ fn print_info(
     info_string.push_str("\n========================================================\n");
     info_string.push_str(&format!("Pool Deposits State\n--------------------\nNumber Of Deposit Boxes: {}\nTotal nanoErgs In Deposit Boxes: {}\n", deposits_state.number_of_boxes, deposits_state.total_nanoergs));
 
+    if let Ok(forecast) = op.get_funding_forecast(parameters) {
+        info_string.push_str(&format!("\nPool Funding Forecast\n---------------------\nFunded Ratio: {:.2}%\nCost Per Epoch: {}\nEpochs Remaining: {}\nBlocks Remaining: {}\n",
+            forecast.funded_ratio * 100.0, forecast.cost_per_epoch, forecast.epochs_remaining.map(|e| e.to_string()).unwrap_or("Unlimited".to_string()), forecast.blocks_remaining.map(|b| b.to_string()).unwrap_or("Unlimited".to_string())
+        ));
+    }
+
     if let Ok(prep_state) = res_prep_state {
         info_string.push_str(&format!("\nEpoch Preparation State\n------------------------\nTotal Pool Funds: {}\nLatest Pool Datapoint: {}\nNext Epoch Ends: {}\n",
             prep_state.funds, prep_state.latest_pool_datapoint, prep_state.next_epoch_ends
@@ -241,13 +446,41 @@ fn print_info(
         info_string.push_str(&format!("\nLive Epoch State\n-----------------\nTotal Pool Funds: {}\nLatest Pool Datapoint: {}\nLive Epoch ID: {}\nCommit Datapoint In Live Epoch: {}\nLive Epoch Ends: {}\n",
             live_state.funds, live_state.latest_pool_datapoint, live_state.epoch_id, live_state.commit_datapoint_in_epoch, live_state.epoch_ends
         ));
+        if let Ok(election) = op.get_collector_election(&live_state, parameters) {
+            if let (Some(rank), Some(collection_height)) = (election.local_oracle_rank, election.local_collection_height) {
+                info_string.push_str(&format!("Your Collector Rank: {}/{}\nYou May Collect From: {}\n",
+                    rank + 1, election.number_of_candidates, collection_height
+                ));
+            }
+        }
     } else {
         info_string.push_str("Failed to find Epoch Preparation Box or Live Epoch Box.");
         info_string.push_str("\n========================================================\n");
     }
 
-    info_string.push_str(&format!("\nOracle Datapoint State\n--------------------\nYour Latest Datapoint: {}\nDatapoint Origin Epoch ID: {}\nSubmitted At: {}", datapoint_state.datapoint, datapoint_state.origin_epoch_id, datapoint_state.creation_height
-        ));
+    if parameters.stake_slashing {
+        if let Ok(slashing_state) = op.get_stake_slashing_state() {
+            info_string.push_str(&format!("\nStake Slashing State\n--------------------\nOracles With Collateral: {}\nUncommitted Oracles: {}\nDatapoints Missed By Collector: {}\n",
+                slashing_state.stakes.iter().filter(|s| s.collateral >= parameters.minimum_collateral).count(), slashing_state.uncommitted.len(), slashing_state.missed_by_collector.len()
+            ));
+        }
+    }
+
+    if parameters.governance {
+        if let Ok(gov_state) = op.get_governance_state() {
+            let votes_cast = gov_state.votes.iter().filter(|v| v.payout_price.is_some()).count();
+            info_string.push_str(&format!("\nGovernance State\n----------------\nCurrent Oracle Payout Price: {}\nVotes Cast: {}/{}\nVote Threshold: {}\nResulting Payout Price: {}\nYour Vote: {:?}\n",
+                gov_state.current_payout_price, votes_cast, gov_state.votes.len(), gov_state.vote_threshold, gov_state.resulting_payout_price, gov_state.local_vote
+            ));
+        }
+    }
+
+    if let Some(datapoint_state) = datapoint_state {
+        info_string.push_str(&format!("\nOracle Datapoint State\n--------------------\nYour Latest Datapoint: {}\nDatapoint Origin Epoch ID: {}\nSubmitted At: {}", datapoint_state.datapoint, datapoint_state.origin_epoch_id, datapoint_state.creation_height
+            ));
+    } else if let Ok(datapoints) = op.get_all_datapoints() {
+        info_string.push_str(&format!("\nOracle Datapoints State\n--------------------\nOracles In Pool: {}", datapoints.len()));
+    }
     info_string.push_str("\n========================================================\n");
 
     // Prints and logs the info String
@@ -1,4 +1,6 @@
-use crate::oracle_config::{get_node_api_key, get_node_ip, get_node_port};
+use crate::oracle_config::{
+    get_node_api_header, get_node_api_key, get_node_ip, get_node_port, get_node_url,
+};
 use ergo_lib::chain::ergo_box::ErgoBox;
 use ergo_node_interface::node_interface::{NodeError, NodeInterface};
 use ergo_offchain_utilities::{BlockHeight, P2PKAddressString, P2SAddressString, ScanID, TxId};
@@ -45,12 +47,18 @@ pub fn get_scan_boxes(scan_id: &String) -> Result<Vec<ErgoBox>> {
     new_node_interface().scan_boxes(scan_id)
 }
 
-/// Generates (and sends) a tx using the node endpoints.
+/// Generates (but does not send) a signed tx using the node endpoints.
 /// Input must be a json formatted request with rawInputs (and rawDataInputs)
 /// manually selected or will be automatically selected by wallet.
+/// Returns the resulting signed transaction json.
+pub fn generate_transaction(tx_request_json: &JsonValue) -> Result<JsonValue> {
+    new_node_interface().generate_transaction(&tx_request_json.dump())
+}
+
+/// Submits an already signed transaction json to the node.
 /// Returns the resulting `TxId`.
-pub fn send_transaction(tx_request_json: &JsonValue) -> Result<TxId> {
-    new_node_interface().generate_and_submit_transaction(&tx_request_json.dump())
+pub fn submit_transaction(signed_tx_json: &JsonValue) -> Result<TxId> {
+    new_node_interface().submit_transaction(&signed_tx_json.dump())
 }
 
 /// Given a P2S Ergo address, extract the hex-encoded serialized ErgoTree (script)
@@ -110,3 +118,54 @@ pub fn serialized_box_from_id(box_id: &String) -> Result<String> {
 pub fn current_block_height() -> Result<BlockHeight> {
     new_node_interface().current_block_height()
 }
+
+/// Returns the number of confirmations of a tx posted by the node wallet, or
+/// `None` if the wallet does not know the tx (ie. it is still unconfirmed)
+pub fn tx_confirmations(tx_id: &str) -> crate::Result<Option<u64>> {
+    let resp = reqwest::blocking::Client::new()
+        .get(&(get_node_url() + "/wallet/transactionById"))
+        .query(&[("id", tx_id)])
+        .header("api_key", get_node_api_header())
+        .send()?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let resp_json = json::parse(&resp.text()?)?;
+    Ok(resp_json["numConfirmations"].as_u64())
+}
+
+/// Returns the `(height, timestamp)` of the latest `count` block headers
+/// (timestamps in milliseconds)
+pub fn last_block_headers(count: u64) -> crate::Result<Vec<(BlockHeight, u64)>> {
+    let resp = reqwest::blocking::Client::new()
+        .get(&format!("{}/blocks/lastHeaders/{}", get_node_url(), count))
+        .header("api_key", get_node_api_header())
+        .send()?;
+    let resp_json = json::parse(&resp.text()?)?;
+    Ok(resp_json
+        .members()
+        .filter_map(|h| Some((h["height"].as_u64()?, h["timestamp"].as_u64()?)))
+        .collect())
+}
+
+/// Compiles an ErgoScript contract source into a P2S address using the node.
+/// Returns an error holding the node's compiler message if compilation fails.
+pub fn compile_contract(source: &str) -> crate::Result<P2SAddressString> {
+    let body = object! {
+        "source": source,
+    };
+    let resp = reqwest::blocking::Client::new()
+        .post(&(get_node_url() + "/script/p2sAddress"))
+        .header("api_key", get_node_api_header())
+        .header("Content-Type", "application/json")
+        .body(body.dump())
+        .send()?;
+    let resp_json = json::parse(&resp.text()?)?;
+    match resp_json["address"].as_str() {
+        Some(address) => Ok(address.to_string()),
+        None => Err(anyhow::anyhow!(
+            "Failed to compile contract: {}",
+            resp_json["detail"]
+        )),
+    }
+}
@@ -1,7 +1,22 @@
-use crate::{BlockDuration, NanoErg};
+use crate::protocol::ProtocolVersion;
+use crate::{BlockDuration, NanoErg, Result};
+use anyhow::anyhow;
 use reqwest::header::HeaderValue;
+use std::env;
+use std::net::IpAddr;
 use yaml_rust::{Yaml, YamlLoader};
 
+/// Number of blocks each collector rank waits if `collector_backoff_length` is not set
+static DEFAULT_COLLECTOR_BACKOFF_LENGTH: i64 = 2;
+/// Address the GET API server binds to if `core_api_bind_address` is not set
+static DEFAULT_GET_API_BIND_ADDRESS: &str = "0.0.0.0";
+/// Address the POST API server binds to if `post_api_bind_address` is not set (local only)
+static DEFAULT_POST_API_BIND_ADDRESS: &str = "127.0.0.1";
+/// Number of POST API requests a single client may send per minute if `post_api_rate_limit` is not set
+static DEFAULT_POST_API_RATE_LIMIT: u64 = 30;
+/// Funding runway (in epochs) at which a `funding_low` alert is raised if `funding_alert_epochs` is not set
+static DEFAULT_FUNDING_ALERT_EPOCHS: u64 = 10;
+
 /// Pool Parameters as defined in the `oracle-config.yaml`
 pub struct PoolParameters {
     pub minimum_pool_box_value: u64,
@@ -9,9 +24,21 @@ pub struct PoolParameters {
     pub live_epoch_length: BlockDuration,
     pub epoch_preparation_length: BlockDuration,
     pub buffer_length: BlockDuration,
+    /// Number of blocks each collector rank waits after the one above it before collecting datapoints
+    pub collector_backoff_length: BlockDuration,
     pub deviation_range: u64,
     pub consensus_num: u64,
     pub base_fee: u64,
+    /// Whether the pool uses the stake slashing contracts
+    pub stake_slashing: bool,
+    /// Minimum collateral each oracle must hold in their Datapoint box (stake slashing pools only)
+    pub minimum_collateral: NanoErg,
+    /// Whether the oracle payout price is governed on-chain by oracle votes (requires stake slashing)
+    pub governance: bool,
+    /// The total number of oracles in the pool, which the governance vote threshold is based on (governance pools only)
+    pub number_of_oracles: u64,
+    /// The version of the oracle pool protocol (contracts) used by the pool
+    pub protocol_version: ProtocolVersion,
 }
 
 impl PoolParameters {
@@ -31,6 +58,9 @@ impl PoolParameters {
         let buf = config["buffer_length"]
             .as_i64()
             .expect("No buffer_length specified in config file.");
+        let collector_backoff_length = config["collector_backoff_length"]
+            .as_i64()
+            .unwrap_or(DEFAULT_COLLECTOR_BACKOFF_LENGTH);
         let price = config["oracle_payout_price"]
             .as_i64()
             .expect("No oracle_payout_price specified in config file.");
@@ -46,17 +76,192 @@ impl PoolParameters {
         let base_fee = config["base_fee"]
             .as_i64()
             .expect("No base_fee specified in config file.");
+        let stake_slashing = yaml_to_bool(&config["stake_slashing"]);
+        let minimum_collateral = if stake_slashing {
+            config["minimum_collateral"]
+                .as_i64()
+                .expect("No minimum_collateral specified in config file.")
+        } else {
+            0
+        };
+        let governance = yaml_to_bool(&config["governance"]);
+        if governance && !stake_slashing {
+            panic!("Governance pools require stake_slashing to be enabled.");
+        }
+        let number_of_oracles = if governance {
+            yaml_to_u64(&config["number_of_oracles"])
+                .expect("No number_of_oracles specified in config file.")
+        } else {
+            0
+        };
+        let protocol_version = match config["protocol_version"].as_str() {
+            Some(v) => ProtocolVersion::parse(v)
+                .expect("Unsupported protocol_version specified in config file."),
+            None => ProtocolVersion::V0_2,
+        };
+        if protocol_version == ProtocolVersion::V0_1 && stake_slashing {
+            panic!("Stake slashing/governance pools require protocol_version v0.2.");
+        }
         PoolParameters {
             minimum_pool_box_value: num as u64,
             oracle_payout_price: price as u64,
             live_epoch_length: lel as u64,
             epoch_preparation_length: epl as u64,
             buffer_length: buf as u64,
+            collector_backoff_length: collector_backoff_length as u64,
             deviation_range: deviation_range as u64,
             consensus_num: consensus_num as u64,
             base_fee: base_fee as u64,
+            stake_slashing: stake_slashing,
+            minimum_collateral: minimum_collateral as u64,
+            governance: governance,
+            number_of_oracles: number_of_oracles,
+            protocol_version: protocol_version,
         }
     }
+
+    /// The Pool Parameters used across the tests, with the `extra_yaml` lines added
+    #[cfg(test)]
+    pub fn for_tests(extra_yaml: &str) -> PoolParameters {
+        let yaml_string = [
+            "minimum_pool_box_value: 10000000",
+            "live_epoch_length: 20",
+            "epoch_preparation_length: 10",
+            "buffer_length: 4",
+            "deviation_range: 5",
+            "consensus_num: 3",
+            "oracle_payout_price: 1000000",
+            "base_fee: 1000000",
+            extra_yaml,
+        ]
+        .join("\n");
+        let config = &YamlLoader::load_from_str(&yaml_string).unwrap()[0];
+        PoolParameters::new_from_yaml_string(config)
+    }
+}
+
+/// Access control settings of the POST API server as defined in the `oracle-config.yaml`
+#[derive(Debug, Clone)]
+pub struct PostApiSettings {
+    /// Address the POST API server binds to
+    pub bind_address: String,
+    /// API key which requests must hold in the `X-Api-Key` header
+    pub api_key: Option<String>,
+    /// Secret which requests must be signed with (`X-Timestamp` + `X-Signature` headers)
+    pub signing_secret: Option<String>,
+    /// Client IP addresses which may use the POST API (empty allows all)
+    pub allowed_ips: Vec<IpAddr>,
+    /// Number of requests a single client may send per minute (0 disables rate limiting)
+    pub rate_limit_per_minute: u64,
+    /// Origins which may make cross-origin requests (empty disables CORS)
+    pub cors_origins: Vec<String>,
+}
+
+impl PostApiSettings {
+    pub fn new() -> PostApiSettings {
+        let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
+        PostApiSettings::new_from_yaml_string(config)
+    }
+
+    /// Create a `PostApiSettings` from a `&Yaml` string
+    pub fn new_from_yaml_string(config: &Yaml) -> PostApiSettings {
+        let optional_str = |key: &str| {
+            config[key]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        let str_list = |key: &str| -> Vec<String> {
+            config[key]
+                .as_vec()
+                .map(|v| {
+                    v.iter()
+                        .filter_map(|s| s.as_str())
+                        .map(|s| s.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let allowed_ips = str_list("post_api_allowed_ips")
+            .iter()
+            .map(|ip| {
+                ip.parse()
+                    .expect("Invalid IP address in post_api_allowed_ips in config file.")
+            })
+            .collect();
+        PostApiSettings {
+            bind_address: optional_str("post_api_bind_address")
+                .unwrap_or(DEFAULT_POST_API_BIND_ADDRESS.to_string()),
+            api_key: optional_str("post_api_key"),
+            signing_secret: optional_str("post_api_signing_secret"),
+            allowed_ips: allowed_ips,
+            rate_limit_per_minute: yaml_to_u64(&config["post_api_rate_limit"])
+                .unwrap_or(DEFAULT_POST_API_RATE_LIMIT),
+            cors_origins: str_list("post_api_cors_origins"),
+        }
+    }
+}
+
+/// TLS settings of the API servers as defined in the `oracle-config.yaml`
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    /// Path of the PEM encoded certificate (chain) the API servers present
+    pub cert_path: String,
+    /// Path of the PEM encoded private key of the certificate
+    pub key_path: String,
+    /// Path of the PEM encoded CA certificate(s) which POST API clients must
+    /// present a certificate signed by (client certificates are not verified if unset)
+    pub client_ca_path: Option<String>,
+}
+
+impl TlsSettings {
+    /// Returns the TLS settings if TLS is enabled (`api_tls_cert_path` & `api_tls_key_path` are set)
+    pub fn new() -> Option<TlsSettings> {
+        let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
+        TlsSettings::new_from_yaml_string(config)
+    }
+
+    /// Create a `TlsSettings` from a `&Yaml` string
+    pub fn new_from_yaml_string(config: &Yaml) -> Option<TlsSettings> {
+        let optional_str = |key: &str| {
+            config[key]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        match (
+            optional_str("api_tls_cert_path"),
+            optional_str("api_tls_key_path"),
+        ) {
+            (Some(cert_path), Some(key_path)) => Some(TlsSettings {
+                cert_path: cert_path,
+                key_path: key_path,
+                client_ca_path: optional_str("post_api_client_ca_path"),
+            }),
+            (None, None) => None,
+            _ => panic!("Both `api_tls_cert_path` and `api_tls_key_path` must be set in the config file to enable TLS."),
+        }
+    }
+}
+
+/// Parses a boolean config value, accepting both `true` and `True` styles.
+/// Missing values are treated as `false`.
+pub fn yaml_to_bool(value: &Yaml) -> bool {
+    match value {
+        Yaml::Boolean(b) => *b,
+        Yaml::String(s) => s.to_lowercase() == "true",
+        _ => false,
+    }
+}
+
+/// Parses an unsigned integer config value, also accepting quoted numbers
+/// (as written by the `bootstrap` subcommands)
+pub fn yaml_to_u64(value: &Yaml) -> Option<u64> {
+    match value {
+        Yaml::Integer(i) if *i >= 0 => Some(*i as u64),
+        Yaml::String(s) => s.parse().ok(),
+        _ => None,
+    }
 }
 
 pub fn get_pool_deposits_contract_address() -> String {
@@ -76,11 +281,112 @@ pub fn get_core_api_port() -> String {
         .to_string()
 }
 
+/// Returns "events_api_port" (the port of the `/events` stream server) from
+/// the config file, defaulting to `core_api_port` + 3
+pub fn get_events_api_port() -> String {
+    let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
+    match yaml_to_u64(&config["events_api_port"]) {
+        Some(port) => port.to_string(),
+        None => (get_core_api_port()
+            .parse::<u16>()
+            .expect("Failed to parse oracle core port from config to u16.")
+            + 3)
+        .to_string(),
+    }
+}
+
+/// Returns "funding_alert_epochs" (the funding runway in epochs at which a
+/// `funding_low` alert is raised) from the config file
+pub fn get_funding_alert_epochs() -> u64 {
+    let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
+    yaml_to_u64(&config["funding_alert_epochs"]).unwrap_or(DEFAULT_FUNDING_ALERT_EPOCHS)
+}
+
+/// Returns "core_api_bind_address" (the address the GET API server binds to) from the config file
+pub fn get_core_api_bind_address() -> String {
+    let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
+    config["core_api_bind_address"]
+        .as_str()
+        .unwrap_or(DEFAULT_GET_API_BIND_ADDRESS)
+        .to_string()
+}
+
+/// Returns whether the oracle core runs in observer (`--readonly`) mode, which
+/// only reads the state of the pool and has no local oracle
+pub fn is_observer_mode() -> bool {
+    env::args().any(|a| a == "--readonly")
+}
+
+lazy_static! {
+    /// Whether all actions are dry run via the `--dry-run` flag
+    static ref DRY_RUN_ALL_ACTIONS: bool = env::args().any(|a| a == "--dry-run");
+    /// The actions set to dry run via `dry_run_actions` in the config file
+    /// (read once, as it is checked for every action)
+    static ref DRY_RUN_ACTIONS: Vec<String> = {
+        let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
+        config["dry_run_actions"]
+            .as_vec()
+            .map(|actions| {
+                actions
+                    .iter()
+                    .filter_map(|a| a.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+}
+
+/// Returns whether the given action should be dry run (built and printed
+/// as json rather than posted). Either enabled for all actions via the
+/// `--dry-run` flag, or per action via `dry_run_actions` in the config file.
+pub fn is_dry_run_action(action_name: &str) -> bool {
+    *DRY_RUN_ALL_ACTIONS || DRY_RUN_ACTIONS.iter().any(|a| a == action_name)
+}
+
 /// Reads the `oracle-config.yaml` file
 pub fn get_config_yaml() -> String {
     std::fs::read_to_string("oracle-config.yaml").expect("Failed to open oracle-config.yaml")
 }
 
+/// Reads a string value from the config file, erroring if it is missing/empty
+pub fn get_config_str(key: &str) -> Result<String> {
+    let config = &YamlLoader::load_from_str(&get_config_yaml())?[0];
+    match config[key].as_str() {
+        Some(s) if !s.is_empty() => Ok(s.to_string()),
+        _ => Err(anyhow!("No `{}` specified in oracle-config.yaml.", key)),
+    }
+}
+
+/// Sets the value of a top-level key in the `oracle-config.yaml` file
+/// (keeping all comments/other values intact)
+pub fn set_config_value(key: &str, value: &str) -> std::io::Result<()> {
+    let new_config = set_yaml_value(&get_config_yaml(), key, value);
+    std::fs::write("oracle-config.yaml", new_config)
+}
+
+/// Sets the value of a top-level key in a yaml string, appending the key if it is missing.
+/// The value is written as a quoted string.
+pub fn set_yaml_value(yaml_string: &str, key: &str, value: &str) -> String {
+    let new_line = format!("{}: \"{}\"", key, value);
+    let prefix = format!("{}:", key);
+    let mut found = false;
+    let mut lines: Vec<String> = yaml_string
+        .lines()
+        .map(|l| {
+            if l.starts_with(&prefix) {
+                found = true;
+                new_line.clone()
+            } else {
+                l.to_string()
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(new_line);
+    }
+    lines.join("\n") + "\n"
+}
+
 /// Returns `http://ip:port` using `node_ip` and `node_port` from the config file
 pub fn get_node_url() -> String {
     let ip = get_node_ip();
@@ -131,6 +437,16 @@ mod tests {
         assert_eq!(get_node_url(), "http://0.0.0.0:9053".to_string())
     }
     #[test]
+    fn set_yaml_value_works() {
+        let yaml_string = "# Comment\noracle_pool_nft: \"old\"\nbase_fee: 1000000\n";
+        let new_yaml = set_yaml_value(yaml_string, "oracle_pool_nft", "new");
+        assert_eq!(new_yaml, "# Comment\noracle_pool_nft: \"new\"\nbase_fee: 1000000\n");
+        let new_yaml = set_yaml_value(&new_yaml, "datapoint_contract_address", "abc");
+        let config = &YamlLoader::load_from_str(&new_yaml).unwrap()[0];
+        assert_eq!(config["datapoint_contract_address"].as_str(), Some("abc"));
+        assert_eq!(config["base_fee"].as_i64(), Some(1000000));
+    }
+    #[test]
     fn pool_parameter_parsing_works() {
         let yaml_string = "
             minimum_pool_box_value: 10000000
@@ -146,9 +462,133 @@ mod tests {
         assert_eq!(pool_params.live_epoch_length, 20);
         assert_eq!(pool_params.epoch_preparation_length, 10);
         assert_eq!(pool_params.buffer_length, 4);
+        assert_eq!(pool_params.collector_backoff_length, 2);
         assert_eq!(pool_params.minimum_pool_box_value, 10000000);
         assert_eq!(pool_params.deviation_range, 5);
         assert_eq!(pool_params.oracle_payout_price, 1000000);
         assert_eq!(pool_params.base_fee, 1000000);
+        assert_eq!(pool_params.stake_slashing, false);
+        assert_eq!(pool_params.minimum_collateral, 0);
+        assert_eq!(pool_params.governance, false);
+    }
+
+    #[test]
+    fn stake_slashing_parameter_parsing_works() {
+        let yaml_string = "
+            minimum_pool_box_value: 10000000
+            live_epoch_length: 20
+            epoch_preparation_length: 10
+            buffer_length: 4
+            deviation_range: 5
+            consensus_num: 3
+            oracle_payout_price: 1000000
+            base_fee: 1000000
+            stake_slashing: True
+            minimum_collateral: 100000000
+            ";
+        let config = &YamlLoader::load_from_str(yaml_string).unwrap()[0];
+        let pool_params = PoolParameters::new_from_yaml_string(&config);
+        assert_eq!(pool_params.stake_slashing, true);
+        assert_eq!(pool_params.minimum_collateral, 100000000);
+        assert_eq!(pool_params.governance, false);
+    }
+
+    #[test]
+    fn governance_parameter_parsing_works() {
+        let yaml_string = "
+            minimum_pool_box_value: 10000000
+            live_epoch_length: 20
+            epoch_preparation_length: 10
+            buffer_length: 4
+            deviation_range: 5
+            consensus_num: 3
+            oracle_payout_price: 1000000
+            base_fee: 1000000
+            stake_slashing: True
+            minimum_collateral: 100000000
+            governance: True
+            number_of_oracles: \"8\"
+            ";
+        let config = &YamlLoader::load_from_str(yaml_string).unwrap()[0];
+        let pool_params = PoolParameters::new_from_yaml_string(&config);
+        assert_eq!(pool_params.governance, true);
+        assert_eq!(pool_params.number_of_oracles, 8);
+    }
+
+    #[test]
+    fn post_api_settings_parsing_works() {
+        let config = &YamlLoader::load_from_str("core_api_port: \"9010\"").unwrap()[0];
+        let settings = PostApiSettings::new_from_yaml_string(&config);
+        assert_eq!(settings.bind_address, "127.0.0.1");
+        assert_eq!(settings.api_key, None);
+        assert_eq!(settings.rate_limit_per_minute, 30);
+        assert!(settings.allowed_ips.is_empty());
+
+        let yaml_string = "
+            post_api_bind_address: \"0.0.0.0\"
+            post_api_key: \"secret-key\"
+            post_api_allowed_ips: [\"10.0.0.2\", \"::1\"]
+            post_api_rate_limit: 0
+            post_api_cors_origins: [\"https://oracle.example.com\"]
+            ";
+        let config = &YamlLoader::load_from_str(yaml_string).unwrap()[0];
+        let settings = PostApiSettings::new_from_yaml_string(&config);
+        assert_eq!(settings.bind_address, "0.0.0.0");
+        assert_eq!(settings.api_key, Some("secret-key".to_string()));
+        assert_eq!(settings.allowed_ips.len(), 2);
+        assert_eq!(settings.rate_limit_per_minute, 0);
+        assert_eq!(settings.cors_origins, vec!["https://oracle.example.com"]);
+    }
+
+    #[test]
+    fn tls_settings_parsing_works() {
+        let config = &YamlLoader::load_from_str("core_api_port: \"9010\"").unwrap()[0];
+        assert!(TlsSettings::new_from_yaml_string(&config).is_none());
+
+        let yaml_string = "
+            api_tls_cert_path: \"cert.pem\"
+            api_tls_key_path: \"key.pem\"
+            post_api_client_ca_path: \"\"
+            ";
+        let config = &YamlLoader::load_from_str(yaml_string).unwrap()[0];
+        let settings = TlsSettings::new_from_yaml_string(&config).unwrap();
+        assert_eq!(settings.cert_path, "cert.pem");
+        assert_eq!(settings.client_ca_path, None);
+    }
+
+    #[test]
+    fn protocol_version_parsing_works() {
+        let yaml_string = "
+            minimum_pool_box_value: 10000000
+            live_epoch_length: 20
+            epoch_preparation_length: 10
+            buffer_length: 4
+            deviation_range: 5
+            consensus_num: 3
+            oracle_payout_price: 1000000
+            base_fee: 1000000
+            protocol_version: \"v0.1\"
+            ";
+        let config = &YamlLoader::load_from_str(yaml_string).unwrap()[0];
+        let pool_params = PoolParameters::new_from_yaml_string(&config);
+        assert_eq!(pool_params.protocol_version, ProtocolVersion::V0_1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Governance pools require stake_slashing to be enabled.")]
+    fn governance_requires_stake_slashing() {
+        let yaml_string = "
+            minimum_pool_box_value: 10000000
+            live_epoch_length: 20
+            epoch_preparation_length: 10
+            buffer_length: 4
+            deviation_range: 5
+            consensus_num: 3
+            oracle_payout_price: 1000000
+            base_fee: 1000000
+            governance: true
+            ";
+        let config = &YamlLoader::load_from_str(yaml_string).unwrap()[0];
+        PoolParameters::new_from_yaml_string(&config);
     }
 }
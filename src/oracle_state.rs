@@ -1,16 +1,40 @@
 /// This files relates to the state of the oracle/oracle pool.
-use crate::oracle_config::get_config_yaml;
+use crate::encoding::decode_group_element_list;
+use crate::oracle_config::{get_config_yaml, is_observer_mode};
 use crate::scans::{
     register_datapoint_scan, register_epoch_preparation_scan, register_live_epoch_scan,
     register_local_oracle_datapoint_scan, register_pool_deposit_scan, save_scan_ids_locally, Scan,
 };
 use crate::Result;
 use crate::{BlockHeight, EpochID, NanoErg, P2PKAddress, TokenID};
+use anyhow::anyhow;
 use ergo_lib::chain::ergo_box::ErgoBox;
+use ergo_lib::chain::Base16Str;
 use ergo_offchain_utilities::encoding::{unwrap_hex_encoded_string, unwrap_int, unwrap_long};
 use std::path::Path;
+use thiserror::Error;
 use yaml_rust::YamlLoader;
 
+/// The value a Datapoint box holds after its collateral has been slashed.
+/// Must match `minBoxValue` hard-coded in the stake slashing contracts.
+pub static SLASHED_DATAPOINT_BOX_VALUE: NanoErg = 1000000;
+/// The minimum value the Pool Deposit box holding slashed collateral is created with
+pub static MIN_SLASHED_COLLATERAL_BOX_VALUE: NanoErg = 1000000;
+/// File which holds the ids of the registered UTXO-set scans
+static SCAN_IDS_FILE: &str = "scanIDs.json";
+/// File which holds the ids of the UTXO-set scans registered in observer mode
+static OBSERVER_SCAN_IDS_FILE: &str = "observerScanIDs.json";
+
+#[derive(Error, Debug)]
+pub enum ObserverError {
+    #[error(
+        "The oracle core is running in observer (`--readonly`) mode, which has no local oracle."
+    )]
+    NoLocalOracle(),
+    #[error("No oracle_pool_participant_token specified in config file.")]
+    NoParticipantToken(),
+}
+
 /// Enum for the state that the oracle pool box is currently in
 #[derive(Debug, Clone)]
 pub enum PoolBoxState {
@@ -25,21 +49,27 @@ pub struct Stage {
     pub scan: Scan,
 }
 
+/// The local oracle running the oracle core
+#[derive(Debug, Clone)]
+pub struct LocalOracle {
+    pub address: P2PKAddress,
+    /// Scan of the local oracle's Datapoint box
+    pub datapoint_scan: Scan,
+}
+
 /// Overarching struct which allows for acquiring the state of the whole oracle pool protocol
 #[derive(Debug, Clone)]
 pub struct OraclePool {
-    /// Address of the local oracle running the oracle core
-    pub local_oracle_address: P2PKAddress,
-    /// Token IDs
+    /// The local oracle running the oracle core (`None` in observer mode)
+    pub local_oracle: Option<LocalOracle>,
+    /// Token IDs (the participant token is optional in observer mode)
     pub oracle_pool_nft: TokenID,
-    pub oracle_pool_participant_token: TokenID,
+    pub oracle_pool_participant_token: Option<TokenID>,
     /// Stages
     pub epoch_preparation_stage: Stage,
     pub live_epoch_stage: Stage,
     pub datapoint_stage: Stage,
     pub pool_deposit_stage: Stage,
-    // Local Oracle Datapoint Scan
-    pub local_oracle_datapoint_scan: Scan,
 }
 
 /// The state of the oracle pool when it is in the Live Epoch stage
@@ -70,6 +100,18 @@ pub struct DatapointState {
     pub creation_height: BlockHeight,
 }
 
+/// The latest datapoint posted by a single oracle of the pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct OracleDatapoint {
+    /// The oracle's address held in R4 as a hex-encoded `GroupElement` register value
+    pub oracle_address_raw: String,
+    pub datapoint: u64,
+    /// Box id of the epoch which the datapoint was posted in/originates from
+    pub origin_epoch_id: EpochID,
+    /// Height that the datapoint was declared as being created
+    pub creation_height: BlockHeight,
+}
+
 /// The current UTXO-set state of all of the Pool Deposit boxes
 #[derive(Debug, Clone)]
 pub struct PoolDepositsState {
@@ -77,23 +119,84 @@ pub struct PoolDepositsState {
     pub total_nanoergs: NanoErg,
 }
 
+/// The collateral held by a single oracle's Datapoint box (stake slashing pools only)
+#[derive(Debug, Clone)]
+pub struct OracleStake {
+    /// The oracle's address held in R4 as a hex-encoded `GroupElement` register value
+    pub oracle_address_raw: String,
+    pub collateral: NanoErg,
+    /// Box id of the epoch which the datapoint was posted in/originates from
+    pub origin_epoch_id: EpochID,
+    /// Box id of the oracle's Datapoint box
+    pub box_id: String,
+    /// The hex-encoded R4-R6 register values of the oracle's Datapoint box
+    pub registers: Vec<String>,
+}
+
+impl OracleStake {
+    /// Whether the stake holds enough collateral to be slashed. The slashed
+    /// collateral must be able to fund a Pool Deposit box on its own.
+    pub fn is_slashable(&self) -> bool {
+        self.collateral >= SLASHED_DATAPOINT_BOX_VALUE + MIN_SLASHED_COLLATERAL_BOX_VALUE
+    }
+}
+
+/// The stake slashing state of the oracle pool during the Epoch Preparation stage
+#[derive(Debug, Clone)]
+pub struct StakeSlashingState {
+    /// Address of the latest collector held in R6 of the Epoch Preparation box
+    pub latest_collector_raw: String,
+    /// Addresses of the oracles collected in the last epoch held in R7 of the Epoch Preparation box
+    pub collected_oracles_raw: Vec<String>,
+    /// Box id of the previous Live Epoch box held in R8 of the Epoch Preparation box
+    pub previous_epoch_id: EpochID,
+    /// Height the Epoch Preparation box was created at
+    pub preparation_height: BlockHeight,
+    pub stakes: Vec<OracleStake>,
+    /// Oracles which did not commit a datapoint in the last epoch and still hold collateral
+    pub uncommitted: Vec<OracleStake>,
+    /// Oracles which committed a datapoint in the last epoch but were not collected
+    pub missed_by_collector: Vec<OracleStake>,
+}
+
+impl StakeSlashingState {
+    /// Returns the stake of the latest collector if it still holds collateral
+    pub fn slashable_collector_stake(&self) -> Option<OracleStake> {
+        self.stakes
+            .iter()
+            .find(|s| s.oracle_address_raw == self.latest_collector_raw && s.is_slashable())
+            .cloned()
+    }
+}
+
 impl OraclePool {
-    /// Create a new `OraclePool` struct
+    /// Create a new `OraclePool` struct. In observer mode only the pool NFT and
+    /// the contract addresses are required, and no local oracle scan is registered.
     pub fn new() -> OraclePool {
         let config = &YamlLoader::load_from_str(&get_config_yaml()).unwrap()[0];
-
-        let local_oracle_address = config["oracle_address"]
-            .as_str()
-            .expect("No oracle_pool_nft specified in config file.")
-            .to_string();
+        let observer = is_observer_mode();
+
+        let local_oracle_address = if observer {
+            None
+        } else {
+            Some(
+                config["oracle_address"]
+                    .as_str()
+                    .expect("No oracle_address specified in config file.")
+                    .to_string(),
+            )
+        };
         let oracle_pool_nft = config["oracle_pool_nft"]
             .as_str()
             .expect("No oracle_pool_nft specified in config file.")
             .to_string();
         let oracle_pool_participant_token = config["oracle_pool_participant_token"]
             .as_str()
-            .expect("No oracle_pool_participant_token specified in config file.")
-            .to_string();
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string());
+        if !observer && oracle_pool_participant_token.is_none() {
+            panic!("No oracle_pool_participant_token specified in config file.");
+        }
 
         let epoch_preparation_contract_address = config["epoch_preparation_contract_address"]
             .as_str()
@@ -112,29 +215,42 @@ impl OraclePool {
             .expect("No pool_deposit_contract_address specified in config file.")
             .to_string();
 
-        // If scanIDs.json exists, skip registering scans & saving generated ids
-        if !Path::new("scanIDs.json").exists() {
-            let scans = vec![
+        // Observer mode keeps its own scans, as it does not register the local oracle scan
+        let scan_ids_file = if observer {
+            OBSERVER_SCAN_IDS_FILE
+        } else {
+            SCAN_IDS_FILE
+        };
+
+        // If the scan ids file exists, skip registering scans & saving generated ids
+        if !Path::new(scan_ids_file).exists() {
+            let mut scans = vec![
                 register_epoch_preparation_scan(
                     &oracle_pool_nft,
                     &epoch_preparation_contract_address,
                 )
                 .unwrap(),
                 register_live_epoch_scan(&oracle_pool_nft, &live_epoch_contract_address).unwrap(),
-                register_local_oracle_datapoint_scan(
-                    &oracle_pool_participant_token,
-                    &datapoint_contract_address,
-                    &local_oracle_address,
-                )
-                .unwrap(),
                 register_datapoint_scan(
-                    &oracle_pool_participant_token,
+                    oracle_pool_participant_token.as_ref(),
                     &datapoint_contract_address,
                 )
                 .unwrap(),
                 register_pool_deposit_scan(&pool_deposit_contract_address).unwrap(),
             ];
-            let res = save_scan_ids_locally(scans);
+            if let (Some(address), Some(token)) =
+                (&local_oracle_address, &oracle_pool_participant_token)
+            {
+                scans.push(
+                    register_local_oracle_datapoint_scan(
+                        token,
+                        &datapoint_contract_address,
+                        address,
+                    )
+                    .unwrap(),
+                );
+            }
+            let res = save_scan_ids_locally(scans, scan_ids_file);
             if let Ok(_) = res {
                 // Congrats scans registered screen here
                 print!("\x1B[2J\x1B[1;1H");
@@ -150,11 +266,12 @@ impl OraclePool {
             }
         }
 
-        // Read scanIDs.json for scan ids
+        // Read the scan ids file for scan ids
         let scan_json = json::parse(
-            &std::fs::read_to_string("scanIDs.json").expect("Unable to read scanIDs.json"),
+            &std::fs::read_to_string(scan_ids_file)
+                .expect(&format!("Unable to read {}", scan_ids_file)),
         )
-        .expect("Failed to parse scanIDs.json");
+        .expect(&format!("Failed to parse {}", scan_ids_file));
 
         // Create all `Scan` structs for protocol
         let epoch_preparation_scan = Scan::new(
@@ -169,10 +286,13 @@ impl OraclePool {
             &"All Oracle Datapoints Scan".to_string(),
             &scan_json["All Datapoints Scan"].to_string(),
         );
-        let local_oracle_datapoint_scan = Scan::new(
-            &"Local Oracle Datapoint Scan".to_string(),
-            &scan_json["Local Oracle Datapoint Scan"].to_string(),
-        );
+        let local_oracle = local_oracle_address.map(|address| LocalOracle {
+            address: address,
+            datapoint_scan: Scan::new(
+                &"Local Oracle Datapoint Scan".to_string(),
+                &scan_json["Local Oracle Datapoint Scan"].to_string(),
+            ),
+        });
         let pool_deposit_scan = Scan::new(
             &"Pool Deposits Scan".to_string(),
             &scan_json["Pool Deposits Scan"].to_string(),
@@ -180,7 +300,7 @@ impl OraclePool {
 
         // Create `OraclePool` struct
         OraclePool {
-            local_oracle_address: local_oracle_address,
+            local_oracle: local_oracle,
             oracle_pool_nft: oracle_pool_nft,
             oracle_pool_participant_token: oracle_pool_participant_token,
             epoch_preparation_stage: Stage {
@@ -199,10 +319,25 @@ impl OraclePool {
                 contract_address: pool_deposit_contract_address,
                 scan: pool_deposit_scan,
             },
-            local_oracle_datapoint_scan: local_oracle_datapoint_scan,
         }
     }
 
+    /// Returns the local oracle, erroring in observer mode
+    pub fn local_oracle(&self) -> Result<&LocalOracle> {
+        Ok(self
+            .local_oracle
+            .as_ref()
+            .ok_or(ObserverError::NoLocalOracle())?)
+    }
+
+    /// Returns the pool participant token id, erroring if it was not configured (observer mode)
+    pub fn participant_token(&self) -> Result<&TokenID> {
+        Ok(self
+            .oracle_pool_participant_token
+            .as_ref()
+            .ok_or(ObserverError::NoParticipantToken())?)
+    }
+
     /// Get the current stage of the oracle pool box. Returns either `Preparation` or `Epoch`.
     pub fn check_oracle_pool_stage(&self) -> PoolBoxState {
         match self.get_live_epoch_state() {
@@ -217,9 +352,11 @@ impl OraclePool {
         let epoch_box_regs = epoch_box.additional_registers.get_ordered_values();
         let epoch_box_id: String = epoch_box.box_id().into();
 
-        // Whether datapoint was commit in the current Live Epoch
-        let datapoint_state = self.get_datapoint_state()?;
-        let commit_datapoint_in_epoch: bool = epoch_box_id == datapoint_state.origin_epoch_id;
+        // Whether datapoint was commit in the current Live Epoch (never in observer mode)
+        let commit_datapoint_in_epoch = match self.get_datapoint_state() {
+            Ok(datapoint_state) => epoch_box_id == datapoint_state.origin_epoch_id,
+            Err(_) => false,
+        };
 
         // Latest pool datapoint is held in R4 of the epoch box
         let latest_pool_datapoint = unwrap_long(&epoch_box_regs[0])?;
@@ -260,7 +397,7 @@ impl OraclePool {
 
     /// Get the current state of the local oracle's datapoint
     pub fn get_datapoint_state(&self) -> Result<DatapointState> {
-        let datapoint_box = self.local_oracle_datapoint_scan.get_box()?;
+        let datapoint_box = self.local_oracle()?.datapoint_scan.get_box()?;
         let datapoint_box_regs = datapoint_box.additional_registers.get_ordered_values();
 
         // The Live Epoch box id of the epoch the datapoint was posted in (which is held in R5)
@@ -278,6 +415,93 @@ impl OraclePool {
         Ok(datapoint_state)
     }
 
+    /// Get the latest datapoint posted by every oracle of the pool. Boxes
+    /// without valid R4-R6 registers are skipped.
+    pub fn get_all_datapoints(&self) -> Result<Vec<OracleDatapoint>> {
+        let mut datapoints = vec![];
+        for datapoint_box in self.datapoint_stage.get_boxes()? {
+            let datapoint_box_regs = datapoint_box.additional_registers.get_ordered_values();
+            if datapoint_box_regs.len() < 3 {
+                continue;
+            }
+            if let (Ok(origin_epoch_id), Ok(datapoint)) = (
+                unwrap_hex_encoded_string(&datapoint_box_regs[1]),
+                unwrap_long(&datapoint_box_regs[2]),
+            ) {
+                datapoints.push(OracleDatapoint {
+                    oracle_address_raw: datapoint_box_regs[0].base16_str(),
+                    datapoint: datapoint as u64,
+                    origin_epoch_id: origin_epoch_id,
+                    creation_height: datapoint_box.creation_height as BlockHeight,
+                });
+            }
+        }
+        Ok(datapoints)
+    }
+
+    /// Get the collateral held by every oracle's Datapoint box. Boxes
+    /// without valid R4-R5 registers are skipped.
+    pub fn get_oracle_stakes(&self) -> Result<Vec<OracleStake>> {
+        let mut stakes = vec![];
+        for datapoint_box in self.datapoint_stage.get_boxes()? {
+            let datapoint_box_regs = datapoint_box.additional_registers.get_ordered_values();
+            let (oracle_address_reg, origin_epoch_id_reg) =
+                match (datapoint_box_regs.get(0), datapoint_box_regs.get(1)) {
+                    (Some(r4), Some(r5)) => (r4, r5),
+                    _ => continue,
+                };
+            let origin_epoch_id = match unwrap_hex_encoded_string(origin_epoch_id_reg) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let stake = OracleStake {
+                oracle_address_raw: oracle_address_reg.base16_str(),
+                collateral: datapoint_box.value.as_u64().clone(),
+                origin_epoch_id: origin_epoch_id,
+                box_id: datapoint_box.box_id().into(),
+                registers: datapoint_box_regs.iter().map(|r| r.base16_str()).collect(),
+            };
+            stakes.push(stake);
+        }
+        Ok(stakes)
+    }
+
+    /// Get the stake slashing state of the pool. Only available during the
+    /// Epoch Preparation stage of a stake slashing pool.
+    pub fn get_stake_slashing_state(&self) -> Result<StakeSlashingState> {
+        let epoch_prep_box = self.epoch_preparation_stage.get_box()?;
+        let epoch_prep_box_regs = epoch_prep_box.additional_registers.get_ordered_values();
+        if epoch_prep_box_regs.len() < 5 {
+            return Err(anyhow!(
+                "The Epoch Preparation box is missing the stake slashing registers R6-R8."
+            ));
+        }
+
+        // Latest collector held in R6
+        let latest_collector_raw = epoch_prep_box_regs[2].base16_str();
+        // Oracles collected in the last epoch held in R7
+        let collected_oracles_raw =
+            decode_group_element_list(&epoch_prep_box_regs[3].base16_str())?;
+        // Previous Live Epoch box id held in R8
+        let previous_epoch_id = unwrap_hex_encoded_string(&epoch_prep_box_regs[4])?;
+
+        let stakes = self.get_oracle_stakes()?;
+        let (uncommitted, missed_by_collector) =
+            classify_stakes(&stakes, &previous_epoch_id, &collected_oracles_raw);
+
+        let slashing_state = StakeSlashingState {
+            latest_collector_raw: latest_collector_raw,
+            collected_oracles_raw: collected_oracles_raw,
+            previous_epoch_id: previous_epoch_id,
+            preparation_height: epoch_prep_box.creation_height as BlockHeight,
+            stakes: stakes,
+            uncommitted: uncommitted,
+            missed_by_collector: missed_by_collector,
+        };
+
+        Ok(slashing_state)
+    }
+
     /// Get the current state of all of the pool deposit boxes
     pub fn get_pool_deposits_state(&self) -> Result<PoolDepositsState> {
         let deposits_box_list = self.pool_deposit_stage.get_boxes()?;
@@ -324,3 +548,97 @@ impl Stage {
         Ok(self.get_boxes()?.len() as u64)
     }
 }
+
+/// Splits the oracle stakes into the stakes of oracles which did not commit a
+/// datapoint in the previous epoch (and can still be slashed), and the stakes
+/// whose datapoint from the previous epoch was not collected by the collector.
+/// Nothing is slashable until the first epoch has been collected, as the
+/// bootstrapped Epoch Preparation box holds a placeholder rather than a box id.
+pub fn classify_stakes(
+    stakes: &Vec<OracleStake>,
+    previous_epoch_id: &EpochID,
+    collected_oracles_raw: &Vec<String>,
+) -> (Vec<OracleStake>, Vec<OracleStake>) {
+    if previous_epoch_id.len() != 64 {
+        return (vec![], vec![]);
+    }
+    let uncommitted = stakes
+        .iter()
+        .filter(|s| &s.origin_epoch_id != previous_epoch_id && s.is_slashable())
+        .cloned()
+        .collect();
+    let missed_by_collector = stakes
+        .iter()
+        .filter(|s| {
+            &s.origin_epoch_id == previous_epoch_id
+                && !collected_oracles_raw.contains(&s.oracle_address_raw)
+        })
+        .cloned()
+        .collect();
+    (uncommitted, missed_by_collector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static PREVIOUS_EPOCH: &str =
+        "14e10314b0b33f13667871c62b0e86904cb6aee854630af4296b567b18875185";
+    static OLDER_EPOCH: &str = "0d742ecb0d3ffc9cf3104d3da89cf758d200e10e9c4889284c22ea659bcefcc4";
+
+    fn stake(oracle: u8, collateral: NanoErg, origin_epoch_id: &str) -> OracleStake {
+        OracleStake {
+            oracle_address_raw: format!("0702{}", format!("{:02x}", oracle).repeat(32)),
+            collateral: collateral,
+            origin_epoch_id: origin_epoch_id.to_string(),
+            box_id: format!("{:02x}", oracle).repeat(32),
+            registers: vec![],
+        }
+    }
+
+    #[test]
+    fn stakes_are_classified_by_epoch_and_collection() {
+        let stakes = vec![
+            stake(1, 100000000, PREVIOUS_EPOCH),
+            stake(2, 100000000, PREVIOUS_EPOCH),
+            stake(3, 100000000, OLDER_EPOCH),
+        ];
+        let collected = vec![stakes[0].oracle_address_raw.clone()];
+        let (uncommitted, missed) =
+            classify_stakes(&stakes, &PREVIOUS_EPOCH.to_string(), &collected);
+        assert_eq!(uncommitted.len(), 1);
+        assert_eq!(
+            uncommitted[0].oracle_address_raw,
+            stakes[2].oracle_address_raw
+        );
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].oracle_address_raw, stakes[1].oracle_address_raw);
+    }
+
+    #[test]
+    fn stakes_without_enough_collateral_are_not_slashed() {
+        let stakes = vec![
+            stake(1, SLASHED_DATAPOINT_BOX_VALUE, OLDER_EPOCH),
+            stake(2, SLASHED_DATAPOINT_BOX_VALUE + 5, OLDER_EPOCH),
+            stake(
+                3,
+                SLASHED_DATAPOINT_BOX_VALUE + MIN_SLASHED_COLLATERAL_BOX_VALUE,
+                OLDER_EPOCH,
+            ),
+        ];
+        let (uncommitted, _) = classify_stakes(&stakes, &PREVIOUS_EPOCH.to_string(), &vec![]);
+        assert_eq!(uncommitted.len(), 1);
+        assert_eq!(
+            uncommitted[0].oracle_address_raw,
+            stakes[2].oracle_address_raw
+        );
+    }
+
+    #[test]
+    fn bootstrapped_stakes_are_not_slashed() {
+        let stakes = vec![stake(1, 100000000, "01"), stake(2, 100000000, "01")];
+        let (uncommitted, missed) = classify_stakes(&stakes, &"01".to_string(), &vec![]);
+        assert!(uncommitted.is_empty());
+        assert!(missed.is_empty());
+    }
+}
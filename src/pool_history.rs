@@ -0,0 +1,138 @@
+/// This file holds the history of the datapoints finalized by the oracle pool.
+/// Every Epoch Preparation box observed by the oracle core (each holding the
+/// datapoint finalized in the previous epoch) is recorded in a local file, so
+/// the history covers the epochs since the core was first started.
+use crate::oracle_state::OraclePool;
+use crate::{BlockHeight, Result};
+use ergo_offchain_utilities::encoding::{unwrap_int, unwrap_long};
+use json::JsonValue;
+use std::path::Path;
+
+/// File which holds the recorded pool history
+static POOL_HISTORY_FILE: &str = "poolHistory.json";
+/// Maximum number of entries kept in the pool history
+static MAX_POOL_HISTORY_LENGTH: usize = 1000;
+
+/// A datapoint finalized by the oracle pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolHistoryEntry {
+    /// Box id of the Epoch Preparation box holding the datapoint
+    pub box_id: String,
+    pub datapoint: u64,
+    /// Height the following epoch ends
+    pub epoch_ends: BlockHeight,
+    /// Height the Epoch Preparation box was created at
+    pub creation_height: BlockHeight,
+}
+
+impl PoolHistoryEntry {
+    /// Whether both entries hold the datapoint finalized in the same epoch. A
+    /// "Collect Funds" tx recreates the Epoch Preparation box (with a new box
+    /// id) while keeping the finalized datapoint & the epoch end.
+    pub fn is_same_collection(&self, other: &PoolHistoryEntry) -> bool {
+        self.epoch_ends == other.epoch_ends && self.datapoint == other.datapoint
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            box_id: self.box_id.clone(),
+            datapoint: self.datapoint,
+            epoch_ends: self.epoch_ends,
+            creation_height: self.creation_height,
+        }
+    }
+
+    fn from_json(entry: &JsonValue) -> Option<PoolHistoryEntry> {
+        Some(PoolHistoryEntry {
+            box_id: entry["box_id"].as_str()?.to_string(),
+            datapoint: entry["datapoint"].as_u64()?,
+            epoch_ends: entry["epoch_ends"].as_u64()?,
+            creation_height: entry["creation_height"].as_u64()?,
+        })
+    }
+}
+
+/// Returns the pool history entry of the current Epoch Preparation box
+/// (which holds the latest finalized datapoint)
+pub fn latest_pool_history_entry(op: &OraclePool) -> Result<PoolHistoryEntry> {
+    let epoch_prep_box = op.epoch_preparation_stage.get_box()?;
+    let epoch_prep_box_regs = epoch_prep_box.additional_registers.get_ordered_values();
+    Ok(PoolHistoryEntry {
+        box_id: epoch_prep_box.box_id().into(),
+        datapoint: unwrap_long(&epoch_prep_box_regs[0])? as u64,
+        epoch_ends: unwrap_int(&epoch_prep_box_regs[1])? as u64,
+        creation_height: epoch_prep_box.creation_height as BlockHeight,
+    })
+}
+
+/// Records the current Epoch Preparation box in the pool history if it was
+/// not recorded yet. Returns whether a new entry was added.
+pub fn record_pool_history(op: &OraclePool) -> Result<bool> {
+    let entry = latest_pool_history_entry(op)?;
+    let mut history = get_pool_history();
+    if !add_history_entry(&mut history, entry) {
+        return Ok(false);
+    }
+    let history_json: Vec<JsonValue> = history.iter().map(|e| e.to_json()).collect();
+    std::fs::write(
+        POOL_HISTORY_FILE,
+        json::stringify_pretty(JsonValue::from(history_json), 4),
+    )?;
+    Ok(true)
+}
+
+/// Returns the recorded pool history (oldest first)
+pub fn get_pool_history() -> Vec<PoolHistoryEntry> {
+    if !Path::new(POOL_HISTORY_FILE).exists() {
+        return vec![];
+    }
+    match std::fs::read_to_string(POOL_HISTORY_FILE).map(|s| json::parse(&s)) {
+        Ok(Ok(history_json)) => history_json
+            .members()
+            .filter_map(PoolHistoryEntry::from_json)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Appends the entry to the history unless it holds the latest collection,
+/// dropping the oldest entries beyond `MAX_POOL_HISTORY_LENGTH`
+fn add_history_entry(history: &mut Vec<PoolHistoryEntry>, entry: PoolHistoryEntry) -> bool {
+    if history.last().map(|e| e.is_same_collection(&entry)) == Some(true) {
+        return false;
+    }
+    history.push(entry);
+    if history.len() > MAX_POOL_HISTORY_LENGTH {
+        let excess = history.len() - MAX_POOL_HISTORY_LENGTH;
+        history.drain(0..excess);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(box_id: &str, epoch_ends: BlockHeight) -> PoolHistoryEntry {
+        PoolHistoryEntry {
+            box_id: box_id.to_string(),
+            datapoint: 251821000,
+            epoch_ends: epoch_ends,
+            creation_height: 288690,
+        }
+    }
+
+    #[test]
+    fn history_entries_are_recorded_once() {
+        let mut history = vec![entry("a", 288699)];
+        assert!(!add_history_entry(&mut history, entry("a", 288699)));
+        // A "Collect Funds" tx only changes the box id
+        assert!(!add_history_entry(&mut history, entry("b", 288699)));
+        assert!(add_history_entry(&mut history, entry("c", 288709)));
+        assert_eq!(history, vec![entry("a", 288699), entry("c", 288709)]);
+        assert_eq!(
+            PoolHistoryEntry::from_json(&entry("c", 288709).to_json()),
+            Some(entry("c", 288709))
+        );
+    }
+}
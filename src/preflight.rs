@@ -0,0 +1,490 @@
+/// This file holds the pre-flight checks which are performed on every
+/// action tx before it is submitted to the node. The tx is generated
+/// (but not sent) by the node, and then each input is evaluated locally
+/// against its ErgoTree using the ergo-lib interpreter.
+use crate::node_interface::{
+    address_to_tree, current_block_height, generate_transaction, serialized_box_from_id,
+    submit_transaction,
+};
+use crate::oracle_config::PoolParameters;
+use crate::oracle_state::OraclePool;
+use crate::{NanoErg, Result, TxId};
+use ergo_lib::chain::ergo_box::ErgoBox;
+use ergo_lib::chain::preheader::PreHeader;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::chain::Base16Str;
+use ergo_lib::ergotree_interpreter::eval::context::Context;
+use ergo_lib::ergotree_interpreter::eval::env::Env;
+use ergo_lib::ergotree_interpreter::eval::reduce_to_crypto;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::SigmaBoolean;
+use ergo_offchain_utilities::encoding::{unwrap_hex_encoded_string, unwrap_int, unwrap_long};
+use json::JsonValue;
+use log::info;
+use std::rc::Rc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PreflightError {
+    #[error("Pre-flight check failed. Input #{index} (box id {box_id}) guarded by the `{contract}` contract evaluated to false. {reason}")]
+    ContractConditionFailed {
+        index: usize,
+        box_id: String,
+        contract: String,
+        reason: String,
+    },
+    #[error("Pre-flight check failed. Unable to evaluate the script of input #{0}: {1}")]
+    FailedToEvaluateInput(usize, String),
+    #[error("Pre-flight check failed. Unable to parse the transaction generated by the node: {0}")]
+    FailedParsingTransaction(String),
+    #[error("Pre-flight check failed. Unable to acquire box {0} from the node.")]
+    FailedAcquiringBox(String),
+}
+
+impl OraclePool {
+    /// Generates the tx for the given tx request json, evaluates it locally
+    /// via `preflight_check()` and submits it to the node if it passes.
+    pub fn preflight_and_submit(&self, tx_request_json: &JsonValue) -> Result<TxId> {
+        let signed_tx_json = generate_transaction(tx_request_json)?;
+        // Only a failed contract condition proves the tx is invalid. If the
+        // check itself could not be performed (ie. an op unsupported by the
+        // local interpreter) the node is left to validate the tx.
+        if let Err(e) = self.preflight_check(&signed_tx_json) {
+            match e.downcast_ref::<PreflightError>() {
+                Some(PreflightError::ContractConditionFailed { .. }) => return Err(e),
+                _ => info!("{} Submitting the tx regardless.", e),
+            }
+        }
+        let result = submit_transaction(&signed_tx_json)?;
+        Ok(result)
+    }
+
+    /// Evaluates every input of a signed tx against its ErgoTree using the
+    /// ergo-lib interpreter. Returns a `PreflightError` describing which
+    /// contract (and where possible which contract condition) failed.
+    pub fn preflight_check(&self, signed_tx_json: &JsonValue) -> Result<()> {
+        let tx: Transaction = serde_json::from_str(&signed_tx_json.dump())
+            .map_err(|e| PreflightError::FailedParsingTransaction(e.to_string()))?;
+
+        let input_boxes = tx
+            .inputs
+            .iter()
+            .map(|i| box_from_id(&i.box_id.clone().into()))
+            .collect::<Result<Vec<ErgoBox>>>()?;
+        let data_input_boxes = tx
+            .data_inputs
+            .iter()
+            .map(|d| box_from_id(&d.box_id.clone().into()))
+            .collect::<Result<Vec<ErgoBox>>>()?;
+
+        // The tx will be evaluated by the node as part of the next block
+        let height = current_block_height()? + 1;
+
+        let inputs: Vec<Rc<ErgoBox>> = input_boxes.iter().cloned().map(Rc::new).collect();
+        let data_inputs: Vec<Rc<ErgoBox>> = data_input_boxes.iter().cloned().map(Rc::new).collect();
+        let outputs: Vec<Rc<ErgoBox>> = tx.outputs.iter().cloned().map(Rc::new).collect();
+
+        for (index, input_box) in input_boxes.iter().enumerate() {
+            let ctx = Rc::new(Context {
+                height: height as i32,
+                self_box: Rc::new(input_box.clone()),
+                outputs: outputs.clone(),
+                data_inputs: data_inputs.clone(),
+                inputs: inputs.clone(),
+                pre_header: PreHeader::dummy(),
+            });
+            let reduction = reduce_to_crypto(&input_box.ergo_tree, &Env::empty(), ctx)
+                .map_err(|e| PreflightError::FailedToEvaluateInput(index, format!("{:?}", e)))?;
+
+            // Any result other than `false` is a signature requirement which
+            // the node wallet is expected to have already satisfied.
+            if let SigmaBoolean::TrivialProp(false) = reduction.sigma_prop {
+                let contract = self.contract_name_of_box(input_box);
+                let reason = if contract == "Live Epoch" {
+                    let parameters = PoolParameters::new();
+                    let payout_price = self
+                        .get_oracle_payout_price(&parameters)
+                        .unwrap_or(parameters.oracle_payout_price);
+                    let collect_tx =
+                        CollectDatapointsTx::new(input_box, &tx.outputs, &data_input_boxes);
+                    collect_datapoints_failed_condition(&collect_tx, &parameters, payout_price)
+                } else {
+                    "".to_string()
+                };
+                Err(PreflightError::ContractConditionFailed {
+                    index: index,
+                    box_id: input_box.box_id().into(),
+                    contract: contract,
+                    reason: reason,
+                })?;
+            }
+        }
+
+        info!("Pre-flight check passed for tx: {}", signed_tx_json.dump());
+        Ok(())
+    }
+
+    /// Returns the name of the oracle pool contract which guards the given box
+    fn contract_name_of_box(&self, b: &ErgoBox) -> String {
+        let box_tree = base16::encode_lower(&b.ergo_tree.sigma_serialize_bytes());
+        let stages = vec![
+            ("Live Epoch", &self.live_epoch_stage),
+            ("Epoch Preparation", &self.epoch_preparation_stage),
+            ("Datapoint", &self.datapoint_stage),
+            ("Pool Deposit", &self.pool_deposit_stage),
+        ];
+        for (name, stage) in stages {
+            if let Ok(tree) = address_to_tree(&stage.contract_address) {
+                if tree == box_tree {
+                    return name.to_string();
+                }
+            }
+        }
+        "Unknown".to_string()
+    }
+}
+
+/// Acquires a box (which must be part of the UTXO-set) from the node by id
+fn box_from_id(box_id: &String) -> Result<ErgoBox> {
+    let serialized_box = serialized_box_from_id(box_id)?;
+    let bytes = base16::decode(&serialized_box)
+        .map_err(|_| PreflightError::FailedAcquiringBox(box_id.clone()))?;
+    let b = ErgoBox::sigma_parse_bytes(bytes)
+        .map_err(|_| PreflightError::FailedAcquiringBox(box_id.clone()))?;
+    Ok(b)
+}
+
+/// The values of a "Collect Datapoints" tx which are checked by the
+/// `live_epoch.es` contract
+#[derive(Debug, Clone, Default)]
+pub struct CollectDatapointsTx {
+    /// Box id of the Live Epoch box
+    pub epoch_id: String,
+    /// R5 of the Live Epoch box
+    pub epoch_ends: Option<i32>,
+    pub live_epoch_value: u64,
+    /// The datapoints of the data-inputs posted in the current epoch (in order)
+    pub datapoints: Vec<i64>,
+    /// The hex-encoded R4 (oracle public key) of the same data-inputs
+    pub oracle_keys: Vec<String>,
+    /// The R7 payout price votes of the same data-inputs (governance pools only)
+    pub votes: Vec<Option<NanoErg>>,
+    /// R4 of the new Epoch Preparation box
+    pub output_datapoint: Option<i64>,
+    /// R5 of the new Epoch Preparation box
+    pub output_epoch_ends: Option<i32>,
+    /// The hex-encoded R6 (latest collector) of the new Epoch Preparation box
+    pub output_collector: Option<String>,
+    /// The hex-encoded R7 (collected oracles) of the new Epoch Preparation box
+    pub output_collected_oracles: Option<String>,
+    /// R8 (previous Live Epoch box id) of the new Epoch Preparation box
+    pub output_previous_epoch_id: Option<String>,
+    /// R9 (next payout price) of the new Epoch Preparation box
+    pub output_payout_price: Option<NanoErg>,
+    /// The nanoErg value of every output
+    pub output_values: Vec<u64>,
+    /// R4 of the first oracle payout output
+    pub collector_index: Option<i32>,
+}
+
+impl CollectDatapointsTx {
+    /// Extracts the checked values from the Live Epoch input box, the tx
+    /// outputs and the Datapoint data-inputs
+    pub fn new(
+        live_epoch_box: &ErgoBox,
+        outputs: &Vec<ErgoBox>,
+        data_inputs: &Vec<ErgoBox>,
+    ) -> CollectDatapointsTx {
+        let epoch_id: String = live_epoch_box.box_id().into();
+        let epoch_regs = live_epoch_box.additional_registers.get_ordered_values();
+        let output_regs = |i: usize| {
+            outputs
+                .get(i)
+                .map(|b| b.additional_registers.get_ordered_values())
+                .unwrap_or_default()
+        };
+
+        // Registers of the Datapoint boxes which were posted in the current epoch
+        let datapoint_regs: Vec<_> = data_inputs
+            .iter()
+            .map(|b| b.additional_registers.get_ordered_values())
+            .filter(|regs| {
+                regs.get(1).and_then(|r| unwrap_hex_encoded_string(r).ok())
+                    == Some(epoch_id.clone())
+            })
+            .collect();
+        let datapoints = datapoint_regs
+            .iter()
+            .map(|regs| regs.get(2).and_then(|r| unwrap_long(r).ok()).unwrap_or(0))
+            .collect();
+        let oracle_keys = datapoint_regs
+            .iter()
+            .map(|regs| regs.get(0).map(|r| r.base16_str()).unwrap_or_default())
+            .collect();
+        let votes = datapoint_regs
+            .iter()
+            .map(|regs| {
+                regs.get(3)
+                    .and_then(|r| unwrap_long(r).ok())
+                    .map(|p| p as NanoErg)
+            })
+            .collect();
+
+        CollectDatapointsTx {
+            epoch_id: epoch_id.clone(),
+            epoch_ends: epoch_regs.get(1).and_then(|r| unwrap_int(r).ok()),
+            live_epoch_value: live_epoch_box.value.as_u64().clone(),
+            datapoints: datapoints,
+            oracle_keys: oracle_keys,
+            votes: votes,
+            output_datapoint: output_regs(0).get(0).and_then(|r| unwrap_long(r).ok()),
+            output_epoch_ends: output_regs(0).get(1).and_then(|r| unwrap_int(r).ok()),
+            output_collector: output_regs(0).get(2).map(|r| r.base16_str()),
+            output_collected_oracles: output_regs(0).get(3).map(|r| r.base16_str()),
+            output_previous_epoch_id: output_regs(0)
+                .get(4)
+                .and_then(|r| unwrap_hex_encoded_string(r).ok()),
+            output_payout_price: output_regs(0)
+                .get(5)
+                .and_then(|r| unwrap_long(r).ok())
+                .map(|p| p as NanoErg),
+            output_values: outputs.iter().map(|b| b.value.as_u64().clone()).collect(),
+            collector_index: output_regs(1).get(0).and_then(|r| unwrap_int(r).ok()),
+        }
+    }
+}
+
+/// Mirrors the conditions of the `live_epoch.es` contract for the
+/// "Collect Datapoints" action in order to report which one failed.
+/// `payout_price` is the oracle payout price currently in effect on-chain.
+pub fn collect_datapoints_failed_condition(
+    tx: &CollectDatapointsTx,
+    parameters: &PoolParameters,
+    payout_price: NanoErg,
+) -> String {
+    let protocol = parameters.protocol();
+    let datapoints = &tx.datapoints;
+    let min_datapoints = protocol.min_datapoints(parameters);
+    if datapoints.len() == 0 || (datapoints.len() as u64) < min_datapoints {
+        return format!(
+            "Only {} Datapoint boxes from the current epoch were provided as data-inputs, but at least {} are required.",
+            datapoints.len(),
+            min_datapoints
+        );
+    }
+
+    // The datapoints must pass the finalization rules of the protocol version
+    if let Some(condition) = protocol.datapoints_failed_condition(datapoints, parameters) {
+        return condition;
+    }
+
+    let pool_output_value = match tx.output_values.get(0) {
+        Some(v) => *v,
+        None => return "The transaction has no outputs.".to_string(),
+    };
+
+    // The new pool box must hold the average of the datapoints in R4
+    let average = datapoints.iter().sum::<i64>() / datapoints.len() as i64;
+    if tx.output_datapoint != Some(average) {
+        return format!(
+            "R4 of the new Epoch Preparation box is {:?} but the average of the datapoints is {}.",
+            tx.output_datapoint, average
+        );
+    }
+
+    // The new pool box must hold the next epoch end height in R5
+    let epoch_period = (parameters.live_epoch_length + parameters.epoch_preparation_length) as i32;
+    let expected_epoch_ends = tx.epoch_ends.map(|e| e + epoch_period);
+    if expected_epoch_ends != tx.output_epoch_ends {
+        return format!(
+            "R5 of the new Epoch Preparation box is {:?} but the expected next epoch end height is {:?}.",
+            tx.output_epoch_ends, expected_epoch_ends
+        );
+    }
+
+    // The pool box may only pay out the oracles + the collector
+    let min_value =
+        tx.live_epoch_value as i64 - (payout_price * (datapoints.len() as u64 + 1)) as i64;
+    if (pool_output_value as i64) < min_value {
+        return format!(
+            "The new Epoch Preparation box holds {} nanoErgs, but must hold at least {}.",
+            pool_output_value, min_value
+        );
+    }
+
+    // Every successful oracle must be paid out (in order) in the following outputs
+    for i in 0..datapoints.len() {
+        match tx.output_values.get(i + 1) {
+            Some(v) if *v >= payout_price => (),
+            _ => {
+                return format!(
+                    "Oracle payout output #{} is missing or pays less than the oracle payout price of {}.",
+                    i + 1,
+                    payout_price
+                )
+            }
+        }
+    }
+
+    // The collector's datapoint must be identified (v0.2 pools hold its index in R4 of the first payout output)
+    let collector_index = match protocol.collector_index(tx) {
+        Some(i) => i,
+        None => {
+            return format!(
+                "R4 of the first oracle payout output ({:?}) is not a valid index of the collector's Datapoint box.",
+                tx.collector_index
+            )
+        }
+    };
+
+    // Stake slashing/governance pools must extend the registers as laid out by their protocol
+    if let Some(condition) = protocol.collected_epoch_registers_failed_condition(
+        tx,
+        collector_index,
+        payout_price,
+        parameters,
+    ) {
+        return condition;
+    }
+
+    "The collector's signature or an unchecked contract condition (such as payout addresses) failed.".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::encode_group_element_list;
+
+    fn parameters() -> PoolParameters {
+        PoolParameters::for_tests("")
+    }
+
+    fn governance_parameters() -> PoolParameters {
+        PoolParameters::for_tests(
+            "stake_slashing: true\nminimum_collateral: 100000000\ngovernance: true\nnumber_of_oracles: 4",
+        )
+    }
+
+    fn oracle_key(i: u8) -> String {
+        format!("0702{}", format!("{:02x}", i).repeat(32))
+    }
+
+    /// A valid "Collect Datapoints" tx of three oracles
+    fn valid_tx() -> CollectDatapointsTx {
+        let oracle_keys = vec![oracle_key(1), oracle_key(2), oracle_key(3)];
+        CollectDatapointsTx {
+            epoch_id: "14e10314b0b33f13667871c62b0e86904cb6aee854630af4296b567b18875185"
+                .to_string(),
+            epoch_ends: Some(100),
+            live_epoch_value: 50000000,
+            datapoints: vec![300, 299, 297],
+            votes: vec![Some(2000000), Some(2000000), Some(2000000)],
+            output_datapoint: Some(298),
+            output_epoch_ends: Some(130),
+            output_collector: Some(oracle_keys[0].clone()),
+            output_collected_oracles: encode_group_element_list(&oracle_keys).ok(),
+            output_previous_epoch_id: Some(
+                "14e10314b0b33f13667871c62b0e86904cb6aee854630af4296b567b18875185".to_string(),
+            ),
+            output_payout_price: Some(2000000),
+            oracle_keys: oracle_keys,
+            output_values: vec![46000000, 2000000, 1000000, 1000000],
+            collector_index: Some(0),
+        }
+    }
+
+    #[test]
+    fn valid_tx_reports_unchecked_condition() {
+        let reason = collect_datapoints_failed_condition(&valid_tx(), &parameters(), 1000000);
+        assert!(reason.starts_with("The collector's signature"));
+        let reason =
+            collect_datapoints_failed_condition(&valid_tx(), &governance_parameters(), 1000000);
+        assert!(reason.starts_with("The collector's signature"));
+    }
+
+    #[test]
+    fn slashing_and_governance_register_failures_are_reported() {
+        let mut tx = valid_tx();
+        tx.output_collector = Some(oracle_key(2));
+        let reason = collect_datapoints_failed_condition(&tx, &governance_parameters(), 1000000);
+        assert!(reason.starts_with("R6 of the new Epoch Preparation box"));
+
+        let mut tx = valid_tx();
+        tx.output_collected_oracles = encode_group_element_list(&vec![oracle_key(1)]).ok();
+        let reason = collect_datapoints_failed_condition(&tx, &governance_parameters(), 1000000);
+        assert!(reason.starts_with("R7 of the new Epoch Preparation box"));
+
+        let mut tx = valid_tx();
+        tx.output_previous_epoch_id = None;
+        let reason = collect_datapoints_failed_condition(&tx, &governance_parameters(), 1000000);
+        assert!(reason.starts_with("R8 of the new Epoch Preparation box"));
+
+        // 3 of 4 oracles voting for the same price passes the vote
+        let mut tx = valid_tx();
+        tx.output_payout_price = Some(1000000);
+        let reason = collect_datapoints_failed_condition(&tx, &governance_parameters(), 1000000);
+        assert!(reason.starts_with("R9 of the new Epoch Preparation box"));
+    }
+
+    #[test]
+    fn consensus_and_sort_order_failures_are_reported() {
+        let mut tx = valid_tx();
+        tx.datapoints = vec![300, 299];
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("Only 2 Datapoint boxes"));
+
+        tx.datapoints = vec![299, 300, 297];
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("Datapoint data-inputs are not sorted"));
+    }
+
+    #[test]
+    fn v0_1_pools_skip_the_deviation_rules() {
+        let v0_1_parameters = PoolParameters::for_tests("protocol_version: v0.1");
+
+        // Unsorted datapoints outside of the deviation range without a collector index
+        let mut tx = valid_tx();
+        tx.datapoints = vec![200, 300];
+        tx.output_datapoint = Some(250);
+        tx.output_values = vec![47000000, 2000000, 1000000];
+        tx.collector_index = None;
+        let reason = collect_datapoints_failed_condition(&tx, &v0_1_parameters, 1000000);
+        assert!(reason.starts_with("The collector's signature"));
+    }
+
+    #[test]
+    fn deviation_and_average_failures_are_reported() {
+        let mut tx = valid_tx();
+        tx.datapoints = vec![300, 299, 200];
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("Datapoints are not within the deviation range"));
+
+        let mut tx = valid_tx();
+        tx.output_datapoint = Some(300);
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("R4 of the new Epoch Preparation box"));
+    }
+
+    #[test]
+    fn epoch_end_and_payout_failures_are_reported() {
+        let mut tx = valid_tx();
+        tx.output_epoch_ends = Some(120);
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("R5 of the new Epoch Preparation box"));
+
+        let mut tx = valid_tx();
+        tx.output_values = vec![40000000, 2000000, 1000000, 1000000];
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("The new Epoch Preparation box holds"));
+
+        let mut tx = valid_tx();
+        tx.output_values.pop();
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("Oracle payout output #3"));
+
+        let mut tx = valid_tx();
+        tx.collector_index = Some(3);
+        let reason = collect_datapoints_failed_condition(&tx, &parameters(), 1000000);
+        assert!(reason.starts_with("R4 of the first oracle payout output"));
+    }
+}
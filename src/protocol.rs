@@ -0,0 +1,669 @@
+/// This file holds the rules which differ between the versions of the oracle
+/// pool protocol (see `smart-contracts/v0.1` and `smart-contracts/v0.2`)
+/// and its pool variants (see `smart-contracts/v0.2-stake-slashing` and
+/// `smart-contracts/v0.2-governance-stake-slashing`). The version of the
+/// pool is set via `protocol_version` in the `oracle-config.yaml`, the
+/// variant via `stake_slashing`/`governance`, and each of them implements
+/// the `PoolProtocol` trait.
+///
+/// The two versions share the same stages and register layouts, except for
+/// how datapoints are collected:
+/// - v0.1: The collector's Datapoint box is the first data-input, and the
+///   average of all datapoints posted in the epoch is used.
+/// - v0.2: Datapoint boxes are sorted in decreasing order, outliers are
+///   removed until the datapoints are within the deviation range, and the
+///   collector's index is held in R4 of the first oracle payout output.
+///
+/// The variants collect datapoints as v0.2 does, but extend the registers:
+/// - Stake slashing: The Epoch Preparation box records the latest collector
+///   (R6), the collected oracles (R7) and the previous Live Epoch box id (R8).
+/// - Governance: On top of stake slashing, Datapoint boxes hold the oracle's
+///   payout price vote (R7), the Live Epoch box the payout price (R7) and the
+///   Epoch Preparation box the payout price of the upcoming epoch (R9).
+use crate::actions::{
+    average_datapoints, finalize_datapoint, finalize_datapoint_values, sort_datapoint_boxes,
+    CollectionError,
+};
+use crate::encoding::encode_group_element_list;
+use crate::governance::{get_local_vote, resulting_payout_price, vote_threshold};
+use crate::node_interface::{address_to_raw_for_register, address_to_tree};
+use crate::oracle_config::PoolParameters;
+use crate::oracle_state::{LiveEpochState, OraclePool, PreparationState};
+use crate::preflight::CollectDatapointsTx;
+use crate::{BlockHeight, NanoErg, Result};
+use anyhow::anyhow;
+use ergo_lib::chain::ergo_box::ErgoBox;
+use ergo_lib::chain::Base16Str;
+use ergo_lib::ergotree_ir::mir::constant::Constant;
+use ergo_offchain_utilities::encoding::{
+    serialize_hex_encoded_string, string_to_blake2b_hash, unwrap_long,
+};
+use json::JsonValue;
+use std::ops::Range;
+
+/// The version of the oracle pool protocol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProtocolVersion {
+    V0_1,
+    V0_2,
+}
+
+impl ProtocolVersion {
+    /// Parses the `protocol_version` config value (ie. `v0.2`)
+    pub fn parse(version: &str) -> Option<ProtocolVersion> {
+        match version.trim_start_matches('v') {
+            "0.1" => Some(ProtocolVersion::V0_1),
+            "0.2" => Some(ProtocolVersion::V0_2),
+            _ => None,
+        }
+    }
+
+    /// The `protocol_version` config value of the version
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProtocolVersion::V0_1 => "v0.1",
+            ProtocolVersion::V0_2 => "v0.2",
+        }
+    }
+}
+
+impl PoolParameters {
+    /// Returns the rules of the pool's protocol version and variant
+    pub fn protocol(&self) -> &'static dyn PoolProtocol {
+        match self.protocol_version {
+            ProtocolVersion::V0_1 => &ProtocolV0_1,
+            ProtocolVersion::V0_2 if self.governance => &GovernanceV0_2,
+            ProtocolVersion::V0_2 if self.stake_slashing => &StakeSlashingV0_2,
+            ProtocolVersion::V0_2 => &ProtocolV0_2,
+        }
+    }
+}
+
+/// The datapoint of an epoch finalized by the collector
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub datapoint: u64,
+    /// The Datapoint boxes which are used (in data-input order)
+    pub boxes: Vec<ErgoBox>,
+    /// The index of the collector's Datapoint box in `boxes`
+    pub collector_index: usize,
+}
+
+/// The rules of a version/variant of the oracle pool protocol
+pub trait PoolProtocol {
+    /// The minimum number of datapoints the Live Epoch contract accepts
+    fn min_datapoints(&self, parameters: &PoolParameters) -> u64;
+
+    /// Finalizes the datapoint of the epoch given the valid Datapoint boxes
+    /// posted in the current epoch and the local oracle's Datapoint box
+    fn finalize_collection(
+        &self,
+        boxes: &Vec<ErgoBox>,
+        local_box: &ErgoBox,
+        parameters: &PoolParameters,
+    ) -> Result<Collection>;
+
+    /// Finalizes the datapoint of an epoch given the posted datapoints
+    /// (sorted in decreasing order), returning the datapoint and the index
+    /// range of the datapoints which are used
+    fn finalize_datapoints(
+        &self,
+        datapoints: &[i64],
+        parameters: &PoolParameters,
+    ) -> Result<(u64, Range<usize>)>;
+
+    /// The registers of the first oracle payout output of the "Collect Datapoints" tx
+    fn payout_registers(&self, collector_index: usize) -> Option<json::JsonValue>;
+
+    /// Checks the datapoints (in data-input order) of a "Collect Datapoints"
+    /// tx against the finalization rules, returning the failed condition
+    fn datapoints_failed_condition(
+        &self,
+        datapoints: &Vec<i64>,
+        parameters: &PoolParameters,
+    ) -> Option<String>;
+
+    /// The index of the collector's Datapoint box within the data-inputs of
+    /// a "Collect Datapoints" tx
+    fn collector_index(&self, tx: &CollectDatapointsTx) -> Option<usize>;
+
+    /// The registers of the Datapoint box output by a "Commit Datapoint" tx
+    fn datapoint_registers(
+        &self,
+        oracle_address_raw: String,
+        epoch_id: &str,
+        datapoint: u64,
+    ) -> Result<JsonValue> {
+        basic_datapoint_registers(oracle_address_raw, epoch_id, datapoint)
+    }
+
+    /// The registers of the Epoch Preparation box output by a "Collect Funds"
+    /// tx, given the spent Epoch Preparation box
+    fn collect_funds_registers(
+        &self,
+        prep_state: &PreparationState,
+        _epoch_prep_box: &ErgoBox,
+    ) -> Result<JsonValue> {
+        Ok(basic_epoch_prep_registers(
+            prep_state.latest_pool_datapoint,
+            prep_state.next_epoch_ends,
+        ))
+    }
+
+    /// The registers of the Live Epoch box output by the "Start Next Epoch"
+    /// and "Create New Epoch" txs
+    fn live_epoch_registers(
+        &self,
+        pool: &OraclePool,
+        latest_pool_datapoint: u64,
+        epoch_ends: BlockHeight,
+        _parameters: &PoolParameters,
+    ) -> Result<JsonValue> {
+        basic_live_epoch_registers(pool, latest_pool_datapoint, epoch_ends)
+    }
+
+    /// The registers of the Epoch Preparation box output by a "Collect
+    /// Datapoints" tx, given the finalized collection
+    fn collected_epoch_registers(
+        &self,
+        _pool: &OraclePool,
+        collection: &Collection,
+        _live_epoch_state: &LiveEpochState,
+        next_epoch_ends: BlockHeight,
+        _payout_price: NanoErg,
+        _parameters: &PoolParameters,
+    ) -> Result<JsonValue> {
+        Ok(basic_epoch_prep_registers(
+            collection.datapoint,
+            next_epoch_ends,
+        ))
+    }
+
+    /// Checks the registers which the pool variant adds to the Epoch
+    /// Preparation box of a "Collect Datapoints" tx, returning the failed condition
+    fn collected_epoch_registers_failed_condition(
+        &self,
+        _tx: &CollectDatapointsTx,
+        _collector_index: usize,
+        _payout_price: NanoErg,
+        _parameters: &PoolParameters,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// The R4-R6 registers of a Datapoint box
+fn basic_datapoint_registers(
+    oracle_address_raw: String,
+    epoch_id: &str,
+    datapoint: u64,
+) -> Result<JsonValue> {
+    Ok(object! {
+        "R4": oracle_address_raw,
+        "R5": serialize_hex_encoded_string(&epoch_id.to_string())?.base16_str(),
+        "R6": Constant::from(datapoint as i64).base16_str(),
+    })
+}
+
+/// The R4-R5 registers of an Epoch Preparation box
+fn basic_epoch_prep_registers(datapoint: u64, epoch_ends: BlockHeight) -> JsonValue {
+    object! {
+        "R4": Constant::from(datapoint as i64).base16_str(),
+        "R5": Constant::from(epoch_ends as i32).base16_str(),
+    }
+}
+
+/// The R4-R6 registers of a Live Epoch box
+fn basic_live_epoch_registers(
+    pool: &OraclePool,
+    latest_pool_datapoint: u64,
+    epoch_ends: BlockHeight,
+) -> Result<JsonValue> {
+    let epoch_prep_hash = string_to_blake2b_hash(address_to_tree(
+        &pool.epoch_preparation_stage.contract_address,
+    )?)?;
+    Ok(object! {
+        "R4": Constant::from(latest_pool_datapoint as i64).base16_str(),
+        "R5": Constant::from(epoch_ends as i32).base16_str(),
+        "R6": serialize_hex_encoded_string(&epoch_prep_hash)?.base16_str(),
+    })
+}
+
+/// Copies the registers at the given indexes (0 being R4) of the spent
+/// Epoch Preparation box into the registers of its output
+fn keep_epoch_prep_registers(
+    registers: &mut JsonValue,
+    epoch_prep_box: &ErgoBox,
+    indexes: Range<usize>,
+) -> Result<()> {
+    let epoch_prep_box_regs = epoch_prep_box.additional_registers.get_ordered_values();
+    for i in indexes {
+        let register = format!("R{}", i + 4);
+        let value = epoch_prep_box_regs.get(i).ok_or_else(|| {
+            anyhow!(
+                "The Epoch Preparation box is missing register {}.",
+                register
+            )
+        })?;
+        registers[register.as_str()] = value.base16_str().into();
+    }
+    Ok(())
+}
+
+/// The v0.1 "Basic Oracle Pool" protocol
+pub struct ProtocolV0_1;
+
+impl PoolProtocol for ProtocolV0_1 {
+    fn min_datapoints(&self, _parameters: &PoolParameters) -> u64 {
+        1
+    }
+
+    fn finalize_collection(
+        &self,
+        boxes: &Vec<ErgoBox>,
+        local_box: &ErgoBox,
+        _parameters: &PoolParameters,
+    ) -> Result<Collection> {
+        // The collector's Datapoint box must be the first data-input
+        let mut ordered_boxes = vec![boxes
+            .iter()
+            .find(|b| *b == local_box)
+            .ok_or(CollectionError::LocalOracleFailedToPostDatapoint())?
+            .clone()];
+        ordered_boxes.extend(boxes.iter().filter(|b| *b != local_box).cloned());
+        Ok(Collection {
+            datapoint: average_datapoints(&ordered_boxes)?,
+            boxes: ordered_boxes,
+            collector_index: 0,
+        })
+    }
+
+    fn finalize_datapoints(
+        &self,
+        datapoints: &[i64],
+        _parameters: &PoolParameters,
+    ) -> Result<(u64, Range<usize>)> {
+        if datapoints.is_empty() {
+            Err(CollectionError::LocalOracleFailedToPostDatapoint())?;
+        }
+        let average = datapoints.iter().sum::<i64>() / datapoints.len() as i64;
+        Ok((average as u64, 0..datapoints.len()))
+    }
+
+    fn payout_registers(&self, _collector_index: usize) -> Option<json::JsonValue> {
+        None
+    }
+
+    fn datapoints_failed_condition(
+        &self,
+        _datapoints: &Vec<i64>,
+        _parameters: &PoolParameters,
+    ) -> Option<String> {
+        None
+    }
+
+    fn collector_index(&self, _tx: &CollectDatapointsTx) -> Option<usize> {
+        Some(0)
+    }
+}
+
+/// The v0.2 "Deviation Checking Oracle Pool" protocol
+pub struct ProtocolV0_2;
+
+impl PoolProtocol for ProtocolV0_2 {
+    fn min_datapoints(&self, parameters: &PoolParameters) -> u64 {
+        parameters.consensus_num.max(1)
+    }
+
+    fn finalize_collection(
+        &self,
+        boxes: &Vec<ErgoBox>,
+        local_box: &ErgoBox,
+        parameters: &PoolParameters,
+    ) -> Result<Collection> {
+        // Sort Datapoint boxes in decreasing order
+        let sorted_boxes = sort_datapoint_boxes(boxes);
+        // Acquire the finalized datapoint and the list of successful datapoint boxes which were within the deviation range
+        let (datapoint, successful_boxes) = finalize_datapoint(
+            &sorted_boxes,
+            parameters.deviation_range as i64,
+            parameters.consensus_num as i64,
+        )?;
+        let collector_index = successful_boxes
+            .iter()
+            .position(|b| b == local_box)
+            .ok_or(CollectionError::LocalOracleFailedToPostDatapointWithinDeviation())?;
+        Ok(Collection {
+            datapoint: datapoint,
+            boxes: successful_boxes,
+            collector_index: collector_index,
+        })
+    }
+
+    fn finalize_datapoints(
+        &self,
+        datapoints: &[i64],
+        parameters: &PoolParameters,
+    ) -> Result<(u64, Range<usize>)> {
+        finalize_datapoint_values(
+            datapoints,
+            parameters.deviation_range as i64,
+            parameters.consensus_num as i64,
+        )
+    }
+
+    fn payout_registers(&self, collector_index: usize) -> Option<json::JsonValue> {
+        Some(object! {
+            "R4": Constant::from(collector_index as i32).base16_str()
+        })
+    }
+
+    fn datapoints_failed_condition(
+        &self,
+        datapoints: &Vec<i64>,
+        parameters: &PoolParameters,
+    ) -> Option<String> {
+        // Datapoints must be sorted from highest to lowest
+        if let Some(i) = (1..datapoints.len()).find(|&i| datapoints[i - 1] < datapoints[i]) {
+            return Some(format!(
+                "Datapoint data-inputs are not sorted in decreasing order (data-input #{} is {} while #{} is {}).",
+                i - 1,
+                datapoints[i - 1],
+                i,
+                datapoints[i]
+            ));
+        }
+
+        // Min datapoint must be within the deviation range of the max datapoint
+        let first = datapoints[0];
+        let last = datapoints[datapoints.len() - 1];
+        if last < first - (first * parameters.deviation_range as i64 / 100) {
+            return Some(format!(
+                "Datapoints are not within the deviation range of {}% (max {}, min {}).",
+                parameters.deviation_range, first, last
+            ));
+        }
+        None
+    }
+
+    fn collector_index(&self, tx: &CollectDatapointsTx) -> Option<usize> {
+        match tx.collector_index {
+            Some(i) if i >= 0 && (i as usize) < tx.datapoints.len() => Some(i as usize),
+            _ => None,
+        }
+    }
+}
+
+/// The v0.2 stake slashing pool variant
+pub struct StakeSlashingV0_2;
+
+impl PoolProtocol for StakeSlashingV0_2 {
+    fn min_datapoints(&self, parameters: &PoolParameters) -> u64 {
+        ProtocolV0_2.min_datapoints(parameters)
+    }
+
+    fn finalize_collection(
+        &self,
+        boxes: &Vec<ErgoBox>,
+        local_box: &ErgoBox,
+        parameters: &PoolParameters,
+    ) -> Result<Collection> {
+        ProtocolV0_2.finalize_collection(boxes, local_box, parameters)
+    }
+
+    fn finalize_datapoints(
+        &self,
+        datapoints: &[i64],
+        parameters: &PoolParameters,
+    ) -> Result<(u64, Range<usize>)> {
+        ProtocolV0_2.finalize_datapoints(datapoints, parameters)
+    }
+
+    fn payout_registers(&self, collector_index: usize) -> Option<json::JsonValue> {
+        ProtocolV0_2.payout_registers(collector_index)
+    }
+
+    fn datapoints_failed_condition(
+        &self,
+        datapoints: &Vec<i64>,
+        parameters: &PoolParameters,
+    ) -> Option<String> {
+        ProtocolV0_2.datapoints_failed_condition(datapoints, parameters)
+    }
+
+    fn collector_index(&self, tx: &CollectDatapointsTx) -> Option<usize> {
+        ProtocolV0_2.collector_index(tx)
+    }
+
+    fn collect_funds_registers(
+        &self,
+        prep_state: &PreparationState,
+        epoch_prep_box: &ErgoBox,
+    ) -> Result<JsonValue> {
+        // The collector/collected oracles/previous epoch registers are kept
+        let mut registers = basic_epoch_prep_registers(
+            prep_state.latest_pool_datapoint,
+            prep_state.next_epoch_ends,
+        );
+        keep_epoch_prep_registers(&mut registers, epoch_prep_box, 2..5)?;
+        Ok(registers)
+    }
+
+    fn collected_epoch_registers(
+        &self,
+        pool: &OraclePool,
+        collection: &Collection,
+        live_epoch_state: &LiveEpochState,
+        next_epoch_ends: BlockHeight,
+        _payout_price: NanoErg,
+        _parameters: &PoolParameters,
+    ) -> Result<JsonValue> {
+        let collected_oracles = collection
+            .boxes
+            .iter()
+            .map(|b| {
+                b.additional_registers
+                    .get_ordered_values()
+                    .get(0)
+                    .map(|r| r.base16_str())
+                    .ok_or_else(|| anyhow!("A collected Datapoint box is missing R4."))
+            })
+            .collect::<Result<Vec<String>>>()?;
+        let mut registers = basic_epoch_prep_registers(collection.datapoint, next_epoch_ends);
+        registers["R6"] = address_to_raw_for_register(&pool.local_oracle()?.address)?.into();
+        registers["R7"] = encode_group_element_list(&collected_oracles)?.into();
+        registers["R8"] = serialize_hex_encoded_string(&live_epoch_state.epoch_id)?
+            .base16_str()
+            .into();
+        Ok(registers)
+    }
+
+    fn collected_epoch_registers_failed_condition(
+        &self,
+        tx: &CollectDatapointsTx,
+        collector_index: usize,
+        _payout_price: NanoErg,
+        _parameters: &PoolParameters,
+    ) -> Option<String> {
+        let collector_key = tx.oracle_keys.get(collector_index);
+        if tx.output_collector.as_ref() != collector_key {
+            return Some(format!(
+                "R6 of the new Epoch Preparation box ({:?}) is not the public key of the collector ({:?}).",
+                tx.output_collector, collector_key
+            ));
+        }
+        let collected_oracles = encode_group_element_list(&tx.oracle_keys).ok();
+        if tx.output_collected_oracles != collected_oracles {
+            return Some(format!(
+                "R7 of the new Epoch Preparation box ({:?}) does not list the collected oracles ({:?}).",
+                tx.output_collected_oracles, collected_oracles
+            ));
+        }
+        if tx.output_previous_epoch_id.as_ref() != Some(&tx.epoch_id) {
+            return Some(format!(
+                "R8 of the new Epoch Preparation box ({:?}) is not the id of the Live Epoch box ({}).",
+                tx.output_previous_epoch_id, tx.epoch_id
+            ));
+        }
+        None
+    }
+}
+
+/// The v0.2 governance stake slashing pool variant
+pub struct GovernanceV0_2;
+
+impl PoolProtocol for GovernanceV0_2 {
+    fn min_datapoints(&self, parameters: &PoolParameters) -> u64 {
+        StakeSlashingV0_2.min_datapoints(parameters)
+    }
+
+    fn finalize_collection(
+        &self,
+        boxes: &Vec<ErgoBox>,
+        local_box: &ErgoBox,
+        parameters: &PoolParameters,
+    ) -> Result<Collection> {
+        StakeSlashingV0_2.finalize_collection(boxes, local_box, parameters)
+    }
+
+    fn finalize_datapoints(
+        &self,
+        datapoints: &[i64],
+        parameters: &PoolParameters,
+    ) -> Result<(u64, Range<usize>)> {
+        StakeSlashingV0_2.finalize_datapoints(datapoints, parameters)
+    }
+
+    fn payout_registers(&self, collector_index: usize) -> Option<json::JsonValue> {
+        StakeSlashingV0_2.payout_registers(collector_index)
+    }
+
+    fn datapoints_failed_condition(
+        &self,
+        datapoints: &Vec<i64>,
+        parameters: &PoolParameters,
+    ) -> Option<String> {
+        StakeSlashingV0_2.datapoints_failed_condition(datapoints, parameters)
+    }
+
+    fn collector_index(&self, tx: &CollectDatapointsTx) -> Option<usize> {
+        StakeSlashingV0_2.collector_index(tx)
+    }
+
+    fn datapoint_registers(
+        &self,
+        oracle_address_raw: String,
+        epoch_id: &str,
+        datapoint: u64,
+    ) -> Result<JsonValue> {
+        // The local oracle's vote for a new payout price is included
+        let mut registers = basic_datapoint_registers(oracle_address_raw, epoch_id, datapoint)?;
+        if let Some(payout_price) = get_local_vote() {
+            registers["R7"] = Constant::from(payout_price as i64).base16_str().into();
+        }
+        Ok(registers)
+    }
+
+    fn collect_funds_registers(
+        &self,
+        prep_state: &PreparationState,
+        epoch_prep_box: &ErgoBox,
+    ) -> Result<JsonValue> {
+        // The payout price of the upcoming epoch is also kept
+        let mut registers =
+            StakeSlashingV0_2.collect_funds_registers(prep_state, epoch_prep_box)?;
+        keep_epoch_prep_registers(&mut registers, epoch_prep_box, 5..6)?;
+        Ok(registers)
+    }
+
+    fn live_epoch_registers(
+        &self,
+        pool: &OraclePool,
+        latest_pool_datapoint: u64,
+        epoch_ends: BlockHeight,
+        parameters: &PoolParameters,
+    ) -> Result<JsonValue> {
+        // The voted payout price is moved into the new epoch
+        let mut registers = basic_live_epoch_registers(pool, latest_pool_datapoint, epoch_ends)?;
+        let payout_price = pool.get_oracle_payout_price(parameters)?;
+        registers["R7"] = Constant::from(payout_price as i64).base16_str().into();
+        Ok(registers)
+    }
+
+    fn collected_epoch_registers(
+        &self,
+        pool: &OraclePool,
+        collection: &Collection,
+        live_epoch_state: &LiveEpochState,
+        next_epoch_ends: BlockHeight,
+        payout_price: NanoErg,
+        parameters: &PoolParameters,
+    ) -> Result<JsonValue> {
+        let mut registers = StakeSlashingV0_2.collected_epoch_registers(
+            pool,
+            collection,
+            live_epoch_state,
+            next_epoch_ends,
+            payout_price,
+            parameters,
+        )?;
+        // The payout price voted for by 75%+ of all oracles is applied
+        let votes = collection
+            .boxes
+            .iter()
+            .map(|b| {
+                b.additional_registers
+                    .get_ordered_values()
+                    .get(3)
+                    .and_then(|r| unwrap_long(r).ok())
+                    .map(|p| p as u64)
+            })
+            .collect();
+        let new_payout_price = resulting_payout_price(
+            &votes,
+            payout_price,
+            vote_threshold(parameters.number_of_oracles),
+        );
+        registers["R9"] = Constant::from(new_payout_price as i64).base16_str().into();
+        Ok(registers)
+    }
+
+    fn collected_epoch_registers_failed_condition(
+        &self,
+        tx: &CollectDatapointsTx,
+        collector_index: usize,
+        payout_price: NanoErg,
+        parameters: &PoolParameters,
+    ) -> Option<String> {
+        if let Some(condition) = StakeSlashingV0_2.collected_epoch_registers_failed_condition(
+            tx,
+            collector_index,
+            payout_price,
+            parameters,
+        ) {
+            return Some(condition);
+        }
+        let threshold = vote_threshold(parameters.number_of_oracles);
+        let expected_price = resulting_payout_price(&tx.votes, payout_price, threshold);
+        if tx.output_payout_price != Some(expected_price) {
+            return Some(format!(
+                "R9 of the new Epoch Preparation box is {:?} but the payout price resulting from the votes is {}.",
+                tx.output_payout_price, expected_price
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_versions_are_parsed() {
+        assert_eq!(ProtocolVersion::parse("v0.1"), Some(ProtocolVersion::V0_1));
+        assert_eq!(ProtocolVersion::parse("0.2"), Some(ProtocolVersion::V0_2));
+        assert_eq!(ProtocolVersion::parse("v0.3"), None);
+        assert_eq!(ProtocolVersion::V0_1.name(), "v0.1");
+    }
+}
@@ -74,8 +74,8 @@ impl Scan {
     }
 }
 
-/// Saves UTXO-set scans (specifically id) to scanIDs.json
-pub fn save_scan_ids_locally(scans: Vec<Scan>) -> Result<bool> {
+/// Saves UTXO-set scans (specifically id) to the given file (ie. scanIDs.json)
+pub fn save_scan_ids_locally(scans: Vec<Scan>, file: &str) -> Result<bool> {
     let mut id_json = object! {};
     for scan in scans {
         if &scan.id == "null" {
@@ -83,7 +83,7 @@ pub fn save_scan_ids_locally(scans: Vec<Scan>) -> Result<bool> {
         }
         id_json[scan.name] = scan.id.into();
     }
-    std::fs::write("scanIDs.json", json::stringify_pretty(id_json, 4))?;
+    std::fs::write(file, json::stringify_pretty(id_json, 4))?;
     Ok(true)
 }
 
@@ -174,27 +174,35 @@ pub fn register_local_oracle_datapoint_scan(
     Scan::register(&"Local Oracle Datapoint Scan".to_string(), scan_json)
 }
 
-/// This function registers scanning for all of the pools oracles' Datapoint boxes for datapoint collection
+/// This function registers scanning for all of the pools oracles' Datapoint boxes for datapoint collection.
+/// Observers without a configured participant token scan the datapoint contract address alone.
 pub fn register_datapoint_scan(
-    oracle_pool_participant_token: &String,
+    oracle_pool_participant_token: Option<&String>,
     datapoint_address: &String,
 ) -> Result<Scan> {
     // ErgoTree bytes of the datapoint P2S address/script
     let datapoint_add_bytes = address_to_bytes(datapoint_address)?;
 
-    // Scan for pool participant token id + datapoint contract address + oracle_address in R4
-    let scan_json = object! {
-        "predicate": "and",
-        "args": [
-            {
-            "predicate": "containsAsset",
-            "assetId": oracle_pool_participant_token.clone(),
-            },
-            {
+    let scan_json = match oracle_pool_participant_token {
+        // Scan for pool participant token id + datapoint contract address
+        Some(token) => object! {
+            "predicate": "and",
+            "args": [
+                {
+                "predicate": "containsAsset",
+                "assetId": token.clone(),
+                },
+                {
+                "predicate": "equals",
+                "value": datapoint_add_bytes.clone(),
+                }
+            ]
+        },
+        // Scan for the datapoint contract address
+        None => object! {
             "predicate": "equals",
             "value": datapoint_add_bytes.clone(),
-            }
-        ]
+        },
     };
 
     Scan::register(&"All Datapoints Scan".to_string(), scan_json)
@@ -0,0 +1,75 @@
+/// This file holds the optional in-process TLS termination of the API servers.
+/// The sincere servers only speak plain HTTP, so when TLS is enabled in the
+/// `oracle-config.yaml` each server binds to a local-only port, while a
+/// `TlsProxy` on the configured address decrypts connections and forwards them
+/// to it. The POST API proxy can additionally require clients to present a
+/// certificate signed by the configured CA.
+///
+/// The proxy itself is shared with the Connectors' HTTP servers, and thus
+/// lives in `connector-lib`.
+use crate::oracle_config::TlsSettings;
+use crate::Result;
+use anyhow::anyhow;
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+use std::sync::Arc;
+
+#[path = "../connectors/connector-lib/src/tls_proxy.rs"]
+mod tls_proxy;
+pub use tls_proxy::{InternalConnection, InternalListener, TlsProxy};
+
+/// Create a `TlsProxy`, requiring client certificates signed by
+/// `settings.client_ca_path` (if set) when `verify_clients` is true
+pub fn new_tls_proxy(settings: &TlsSettings, verify_clients: bool) -> Result<TlsProxy> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder
+        .set_certificate_chain_file(&settings.cert_path)
+        .map_err(|e| anyhow!("Failed to load `api_tls_cert_path`: {}", e))?;
+    builder
+        .set_private_key_file(&settings.key_path, SslFiletype::PEM)
+        .map_err(|e| anyhow!("Failed to load `api_tls_key_path`: {}", e))?;
+    builder.check_private_key()?;
+    if let (true, Some(ca_path)) = (verify_clients, &settings.client_ca_path) {
+        builder
+            .set_ca_file(ca_path)
+            .map_err(|e| anyhow!("Failed to load `post_api_client_ca_path`: {}", e))?;
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    TlsProxy::new(builder.build())
+}
+
+/// Runs `app` on `address`, terminating TLS in-process if a `TlsProxy` is
+/// given. Requests which did not come through the proxy are rejected.
+pub fn run_app(app: &mut sincere::App, address: &str, tls: Option<Arc<TlsProxy>>) {
+    match tls {
+        Some(proxy) => {
+            let internal_listener =
+                InternalListener::reserve().expect("Failed to reserve a local API server port.");
+            let guard_proxy = proxy.clone();
+            app.before(move |context| {
+                match guard_proxy.internal_connection(context.request.remote_addr()) {
+                    InternalConnection::Forwarded(_) => return,
+                    InternalConnection::Probe(token) => {
+                        context.response.from_text(token).unwrap();
+                    }
+                    InternalConnection::Rejected => {
+                        context
+                            .response
+                            .status_code(403)
+                            .from_text("Connections must use TLS.")
+                            .unwrap();
+                    }
+                }
+                context.stop();
+            });
+            proxy
+                .spawn(address, internal_listener.address())
+                .expect("Failed to start the TLS API listener.");
+            // The proxy only forwards connections once it verified that the
+            // server which bound the released port is this one
+            app.run(&internal_listener.release(), 1).ok();
+        }
+        None => {
+            app.run(address, 1).ok();
+        }
+    }
+}